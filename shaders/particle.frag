@@ -0,0 +1,31 @@
+#version 430 core
+
+in vec2 f_uv;
+in vec3 f_right;
+in vec3 f_up;
+in vec3 f_view_direction;
+
+out vec4 frag_color;
+
+uniform vec3 sun_direction;
+uniform vec3 sun_color = vec3(1.0, 1.0, 1.0);
+uniform float ambient_strength = 0.0;
+
+const vec3 WATER_COLOR = vec3(0.25, 0.55, 0.95);
+
+void main() {
+    //Cut the quad down to a circle
+    float dist_squared = dot(f_uv, f_uv);
+    if (dist_squared > 1.0) {
+        discard;
+    }
+
+    //Shade the billboard as if it were a sphere
+    vec3 normal = normalize(f_right * f_uv.x + f_up * f_uv.y + f_view_direction * sqrt(1.0 - dist_squared));
+    float diffuse = max(0.0, dot(normal, sun_direction));
+    vec3 halfway = normalize(sun_direction + f_view_direction);
+    float specular = pow(max(0.0, dot(normal, halfway)), 64.0);
+
+    vec3 color = WATER_COLOR * sun_color * (diffuse + ambient_strength) + sun_color * specular;
+    frag_color = vec4(color, 0.75);
+}
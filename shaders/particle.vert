@@ -0,0 +1,35 @@
+#version 430 core
+
+//Corner of a unit quad in [-1, 1]
+layout (location = 0) in vec2 position;
+
+//Instanced arrays
+//Only the translation and x scale are used, since the quad is always rotated to face the viewer
+layout (location = 2) in mat4 model_matrix;
+
+out vec2 f_uv;
+out vec3 f_right;
+out vec3 f_up;
+out vec3 f_view_direction;
+
+uniform mat4 view_projection;
+uniform vec3 view_position;
+
+void main() {
+    vec3 center = model_matrix[3].xyz;
+    float radius = model_matrix[0][0];
+
+    vec3 view_direction = normalize(view_position - center);
+    vec3 right = cross(vec3(0.0, 0.0, 1.0), view_direction);
+    if (length(right) < 0.001) {
+        right = vec3(1.0, 0.0, 0.0);
+    }
+    right = normalize(right);
+    vec3 up = cross(view_direction, right);
+
+    f_uv = position;
+    f_right = right;
+    f_up = up;
+    f_view_direction = view_direction;
+    gl_Position = view_projection * vec4(center + (right * position.x + up * position.y) * radius, 1.0);
+}
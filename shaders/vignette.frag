@@ -0,0 +1,18 @@
+#version 430 core
+
+in vec2 f_screen_pos;
+out vec4 frag_color;
+
+uniform float strength;
+uniform float fade;                 //Blackens the whole view, for death and respawn transitions
+
+//Distances from the center of the view where the darkening starts and where it's fully opaque
+const float INNER_RADIUS = 0.3;
+const float OUTER_RADIUS = 0.9;
+
+void main() {
+    //The opening shrinks as the vignette gets stronger
+    float inner = mix(OUTER_RADIUS, INNER_RADIUS, strength);
+    float alpha = smoothstep(inner, OUTER_RADIUS, length(f_screen_pos)) * strength;
+    frag_color = vec4(0.0, 0.0, 0.0, max(alpha, fade));
+}
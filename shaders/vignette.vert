@@ -0,0 +1,11 @@
+#version 430 core
+
+//Screen position of the fragment in [-1, 1]
+out vec2 f_screen_pos;
+
+void main() {
+    //A single triangle that covers the whole screen, generated without any vertex buffers
+    vec2 position = vec2(float((gl_VertexID & 1) << 2) - 1.0, float((gl_VertexID & 2) << 1) - 1.0);
+    f_screen_pos = position;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
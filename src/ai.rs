@@ -0,0 +1,187 @@
+use ozy::collision::Sphere;
+use strum::EnumCount;
+use crate::structs::EntityList;
+use crate::traits::SphereCollider;
+
+//Lightweight utility AI that gives Totoros something to do when they aren't fleeing the player
+//Needs grow over time, and are satisfied by relaxing at the matching kind of point of interest
+
+const HUNGER_RATE: f32 = 1.0 / 180.0;           //Units per second. Needs range from 0 (satisfied) to 1 (desperate)
+const REST_RATE: f32 = 1.0 / 240.0;
+const SOCIAL_RATE: f32 = 1.0 / 150.0;
+const SATISFY_RATE: f32 = 1.0 / 8.0;            //How quickly a need drops while at a matching point of interest
+const WANDER_UTILITY: f32 = 0.15;               //Needs must score higher than this before a Totoro bothers with them
+const DISTANCE_FALLOFF: f32 = 0.05;             //Utility is divided by (1 + distance * DISTANCE_FALLOFF)
+pub const SATISFIED_THRESHOLD: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Need {
+    Hunger,
+    Rest,
+    Social
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Needs {
+    pub hunger: f32,
+    pub rest: f32,
+    pub social: f32
+}
+
+impl Needs {
+    pub fn new(hunger: f32, rest: f32, social: f32) -> Self {
+        Needs {
+            hunger,
+            rest,
+            social
+        }
+    }
+
+    pub fn get(&self, need: Need) -> f32 {
+        match need {
+            Need::Hunger => { self.hunger }
+            Need::Rest => { self.rest }
+            Need::Social => { self.social }
+        }
+    }
+
+    pub fn get_mut(&mut self, need: Need) -> &mut f32 {
+        match need {
+            Need::Hunger => { &mut self.hunger }
+            Need::Rest => { &mut self.rest }
+            Need::Social => { &mut self.social }
+        }
+    }
+
+    //Grows every need, with tiredness building faster when the sun is low
+    pub fn update(&mut self, time_of_day: &TimeOfDay, delta_time: f32) {
+        self.hunger = f32::min(self.hunger + HUNGER_RATE * delta_time, 1.0);
+        self.rest = f32::min(self.rest + REST_RATE * (2.0 - time_of_day.daylight) * delta_time, 1.0);
+        self.social = f32::min(self.social + SOCIAL_RATE * delta_time, 1.0);
+    }
+
+    pub fn satisfy(&mut self, need: Need, delta_time: f32) {
+        let n = self.get_mut(need);
+        *n = f32::max(*n - SATISFY_RATE * delta_time, 0.0);
+    }
+}
+
+//Derived from the sun's position, where sun_pitch runs from 0 at dawn through pi/2 at noon to pi at dusk
+#[derive(Clone, Copy, Debug)]
+pub struct TimeOfDay {
+    pub daylight: f32,          //0 with the sun on the horizon, 1 at noon
+    pub progress: f32           //0 at dawn, 1 at dusk
+}
+
+impl TimeOfDay {
+    pub fn from_sun_pitch(sun_pitch: f32) -> Self {
+        let pitch = f32::clamp(sun_pitch, 0.0, glm::pi::<f32>());
+        TimeOfDay {
+            daylight: f32::sin(pitch),
+            progress: pitch / glm::pi::<f32>()
+        }
+    }
+
+    //How appealing each activity is at this time of day
+    fn need_multiplier(&self, need: Need) -> f32 {
+        match need {
+            Need::Hunger => { 0.5 + self.daylight }                 //Mealtimes are while the sun is up
+            Need::Rest => { 1.5 - self.daylight }                   //Sleepy around dawn and dusk
+            Need::Social => { 0.5 + self.progress }                 //Gatherings happen in the evening
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, EnumCount, PartialEq, Eq)]
+pub enum PoiKind {
+    Food,
+    SleepingSpot,
+    GatheringSpot
+}
+
+impl PoiKind {
+    pub const NAMES: [&'static str; PoiKind::COUNT] = ["Food", "Sleeping spot", "Gathering spot"];
+
+    pub fn from_index(idx: usize) -> Option<Self> {
+        match idx {
+            0 => { Some(PoiKind::Food) }
+            1 => { Some(PoiKind::SleepingSpot) }
+            2 => { Some(PoiKind::GatheringSpot) }
+            _ => { None }
+        }
+    }
+
+    pub fn need(&self) -> Need {
+        match self {
+            PoiKind::Food => { Need::Hunger }
+            PoiKind::SleepingSpot => { Need::Rest }
+            PoiKind::GatheringSpot => { Need::Social }
+        }
+    }
+
+    pub fn debug_color(&self) -> glm::TVec4<f32> {
+        match self {
+            PoiKind::Food => { glm::vec4(0.9, 0.3, 0.1, 0.6) }
+            PoiKind::SleepingSpot => { glm::vec4(0.3, 0.2, 0.9, 0.6) }
+            PoiKind::GatheringSpot => { glm::vec4(0.2, 0.9, 0.4, 0.6) }
+        }
+    }
+}
+
+pub struct PointOfInterest {
+    pub position: glm::TVec3<f32>,
+    pub kind: PoiKind,
+    pub radius: f32                     //Totoros within this distance are considered to be using the point of interest
+}
+
+impl PointOfInterest {
+    pub const COLLISION_RADIUS: f32 = 0.3;
+
+    pub fn new(position: glm::TVec3<f32>, kind: PoiKind) -> Self {
+        PointOfInterest {
+            position,
+            kind,
+            radius: 1.5
+        }
+    }
+
+    pub fn contains(&self, point: &glm::TVec3<f32>) -> bool {
+        glm::distance(&self.position, point) < self.radius
+    }
+}
+
+impl SphereCollider for PointOfInterest {
+    fn sphere(&self) -> Sphere {
+        Sphere {
+            focus: self.position,
+            radius: Self::COLLISION_RADIUS
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activity {
+    Wander,
+    Visit(usize)            //Index of the PointOfInterest being visited
+}
+
+//Picks the highest-scoring activity for a Totoro at position with the given needs
+pub fn choose_activity(position: &glm::TVec3<f32>, needs: &Needs, points_of_interest: &EntityList<PointOfInterest>, time_of_day: &TimeOfDay) -> Activity {
+    let mut best_activity = Activity::Wander;
+    let mut best_utility = WANDER_UTILITY;
+    for i in 0..points_of_interest.len() {
+        if let Some(poi) = &points_of_interest.entities[i] {
+            let need = poi.kind.need();
+            let urgency = needs.get(need);
+
+            //Squaring the need makes desperate Totoros much more motivated than mildly peckish ones
+            let distance = glm::distance(position, &poi.position);
+            let utility = urgency * urgency * time_of_day.need_multiplier(need) / (1.0 + distance * DISTANCE_FALLOFF);
+            if utility > best_utility {
+                best_utility = utility;
+                best_activity = Activity::Visit(i);
+            }
+        }
+    }
+    best_activity
+}
@@ -0,0 +1,115 @@
+use crate::gamestate::TotoroState;
+
+//Procedural animation for Totoros
+//Every effect has a weight that is blended towards a per-state target, so that AI state changes don't pop
+
+const BLEND_SPEED: f32 = 6.0;                   //How quickly the effect weights approach their targets
+const HOP_HEIGHT: f32 = 0.15;                   //Expressed in model units, so bigger Totoros hop higher
+const HOPS_PER_METER: f32 = 1.5;
+const IDLE_RATE: f32 = 2.5;                     //Radians per second of the idle breathing cycle
+const WOBBLE_RATE: f32 = 14.0;                  //Radians per second of the drowning wobble
+const WOBBLE_AMPLITUDE: f32 = 0.35;             //Radians
+const SQUASH_STIFFNESS: f32 = 180.0;
+const SQUASH_DAMPING: f32 = 10.0;
+const LANDING_SPEED_THRESHOLD: f32 = 2.0;       //Vertical speed (m/s) that counts as a landing worth squashing for
+const LANDING_IMPULSE: f32 = 0.6;
+
+#[derive(Clone, Copy, Debug)]
+pub struct AnimationWeights {
+    pub idle: f32,
+    pub hop: f32,
+    pub wobble: f32
+}
+
+impl AnimationWeights {
+    fn for_state(state: &TotoroState) -> Self {
+        let (idle, hop, wobble) = match state {
+            TotoroState::Relaxed => { (1.0, 0.0, 0.0) }
+            TotoroState::Meandering => { (0.3, 1.0, 0.0) }
+            TotoroState::Startled | TotoroState::PrePanicking => { (0.0, 0.0, 0.0) }
+            TotoroState::Panicking => { (0.0, 1.0, 0.0) }
+            TotoroState::StartDying | TotoroState::Dying => { (0.0, 0.0, 1.0) }
+            TotoroState::BrainDead => { (0.0, 0.0, 0.0) }
+        };
+        AnimationWeights {
+            idle,
+            hop,
+            wobble
+        }
+    }
+}
+
+pub struct TotoroAnimator {
+    pub weights: AnimationWeights,
+    hop_phase: f32,
+    idle_phase: f32,
+    wobble_phase: f32,
+    squash: f32,                    //Positive values stretch the Totoro vertically, negative values squash it
+    squash_velocity: f32,
+    last_vertical_speed: f32
+}
+
+impl TotoroAnimator {
+    pub fn new(idle_phase: f32) -> Self {
+        TotoroAnimator {
+            weights: AnimationWeights::for_state(&TotoroState::Relaxed),
+            hop_phase: 0.0,
+            idle_phase,
+            wobble_phase: 0.0,
+            squash: 0.0,
+            squash_velocity: 0.0,
+            last_vertical_speed: 0.0
+        }
+    }
+
+    pub fn update(&mut self, state: &TotoroState, velocity: &glm::TVec3<f32>, delta_time: f32) {
+        //Blend the effect weights towards the current state's targets
+        let target = AnimationWeights::for_state(state);
+        let t = f32::min(BLEND_SPEED * delta_time, 1.0);
+        self.weights.idle += (target.idle - self.weights.idle) * t;
+        self.weights.hop += (target.hop - self.weights.hop) * t;
+        self.weights.wobble += (target.wobble - self.weights.wobble) * t;
+
+        //The hop cycle advances with horizontal distance travelled
+        //Each half-turn of the phase is one hop
+        let ground_speed = glm::length(&glm::vec2(velocity.x, velocity.y));
+        self.hop_phase = (self.hop_phase + ground_speed * HOPS_PER_METER * glm::pi::<f32>() * delta_time) % glm::two_pi::<f32>();
+        self.idle_phase = (self.idle_phase + IDLE_RATE * delta_time) % glm::two_pi::<f32>();
+        self.wobble_phase = (self.wobble_phase + WOBBLE_RATE * delta_time) % glm::two_pi::<f32>();
+
+        //Landing after a fall (e.g. the startle jump) kicks the squash spring
+        if self.last_vertical_speed < -LANDING_SPEED_THRESHOLD && velocity.z > self.last_vertical_speed * 0.5 {
+            self.squash_velocity += self.last_vertical_speed * LANDING_IMPULSE;
+        }
+
+        //Squash and stretch is a damped spring pulled towards a target derived from vertical motion
+        let target_squash = f32::clamp(velocity.z * 0.04, -0.25, 0.35);
+        let spring_accel = -SQUASH_STIFFNESS * (self.squash - target_squash) - SQUASH_DAMPING * self.squash_velocity;
+        self.squash_velocity += spring_accel * delta_time;
+        self.squash += self.squash_velocity * delta_time;
+
+        self.last_vertical_speed = velocity.z;
+    }
+
+    //Model-space transform to apply to the Totoro mesh before its world transform
+    pub fn local_transform(&self) -> glm::TMat4<f32> {
+        let w = &self.weights;
+        let hop = f32::abs(f32::sin(self.hop_phase));
+        let hop_height = HOP_HEIGHT * hop * w.hop;
+
+        //Squashed when touching down between hops, stretched at the top of a hop
+        let hop_stretch = (hop - 0.5) * 0.2 * w.hop;
+        let breathe = 0.03 * f32::sin(self.idle_phase) * w.idle;
+        let stretch = f32::max(1.0 + self.squash + hop_stretch + breathe, 0.3);
+        let bulge = 1.0 / f32::sqrt(stretch);      //Keeps the volume roughly constant
+
+        //The model's x-axis is the Totoro's forward direction
+        let roll = WOBBLE_AMPLITUDE * f32::sin(self.wobble_phase) * w.wobble;
+        let pitch = WOBBLE_AMPLITUDE * 0.5 * f32::sin(self.wobble_phase * 1.7) * w.wobble;
+
+        glm::translation(&glm::vec3(0.0, 0.0, hop_height)) *
+        glm::rotation(roll, &glm::vec3(1.0, 0.0, 0.0)) *
+        glm::rotation(pitch, &glm::vec3(0.0, 1.0, 0.0)) *
+        glm::scaling(&glm::vec3(bulge, bulge, stretch))
+    }
+}
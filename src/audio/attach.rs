@@ -0,0 +1,77 @@
+use std::sync::mpsc::Sender;
+use crate::routines::send_or_error;
+use crate::structs::EntityRef;
+use super::{AudioCommand, SfxHandle};
+
+//Sounds that follow something around the world
+//Runs on the main thread, which streams the positions and velocities to the audio thread every frame
+
+//What a sound is attached to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundAnchor {
+    Totoro(EntityRef)
+}
+
+struct AttachedSound {
+    handle: SfxHandle,
+    anchor: SoundAnchor,
+    last_position: Option<glm::TVec3<f32>>
+}
+
+pub struct SoundAttachments {
+    sounds: Vec<AttachedSound>
+}
+
+impl SoundAttachments {
+    pub fn new() -> Self {
+        SoundAttachments {
+            sounds: Vec::new()
+        }
+    }
+
+    pub fn attach(&mut self, handle: SfxHandle, anchor: SoundAnchor) {
+        self.sounds.push(AttachedSound {
+            handle,
+            anchor,
+            last_position: None
+        });
+    }
+
+    //Called once the audio thread is done with a sound
+    pub fn detach(&mut self, handle: SfxHandle) {
+        self.sounds.retain(|sound| sound.handle != handle);
+    }
+
+    //Where an attached sound was last moved to
+    pub fn position(&self, handle: SfxHandle) -> Option<glm::TVec3<f32>> {
+        self.sounds.iter().find(|sound| sound.handle == handle).and_then(|sound| sound.last_position)
+    }
+
+    //Moves every attached sound to wherever its anchor is now
+    //locate returns None once the anchor is gone, which stops the sound
+    pub fn update<F: Fn(SoundAnchor) -> Option<glm::TVec3<f32>>>(&mut self, audio_sender: &Sender<AudioCommand>, delta_time: f32, locate: F) {
+        let mut i = 0;
+        while i < self.sounds.len() {
+            let sound = &mut self.sounds[i];
+            let position = match locate(sound.anchor) {
+                Some(p) => { p }
+                None => {
+                    send_or_error(audio_sender, AudioCommand::StopSFX(sound.handle));
+                    self.sounds.swap_remove(i);
+                    continue;
+                }
+            };
+
+            //OpenAL uses the velocity for Doppler
+            let velocity = match sound.last_position {
+                Some(last) if delta_time > 0.0 => { (position - last) / delta_time }
+                _ => { glm::zero() }
+            };
+            sound.last_position = Some(position);
+
+            send_or_error(audio_sender, AudioCommand::SetSFXPosition(sound.handle, [position.x, position.y, position.z]));
+            send_or_error(audio_sender, AudioCommand::SetSFXVelocity(sound.handle, [velocity.x, velocity.y, velocity.z]));
+            i += 1;
+        }
+    }
+}
@@ -0,0 +1,586 @@
+use alto::{sys::ALint, Source, SourceState, StreamingSource};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::mem;
+use super::decoder::{self, AudioFrame, DecodeError, Decoder, LoopPoints};
+use super::load_decoder;
+
+//Background music streaming, with sample-accurate loop points and crossfades between tracks
+
+const IDEAL_FRAMES_QUEUED: ALint = 5;   //Ideal number of queued audio frames for streaming sources
+pub const CROSSFADE_TIME: f32 = 2.0;    //Used when a track's tempo isn't known
+const CUE_FRAMES_PER_FRAME: usize = 2;  //How far the loop cue gets wound forward for each frame streamed
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tempo {
+    pub bpm: f32,
+    pub beats_per_bar: u32,
+    pub first_beat: u64                 //Sample frame of the first downbeat
+}
+
+impl Tempo {
+    fn bar_length(&self) -> f32 {
+        60.0 / self.bpm * self.beats_per_bar as f32
+    }
+}
+
+//Extra information about a track that the audio file itself can't tell us
+//Sidecar files sit next to the track with ".track" appended, e.g. music/town_battle.mp3.track
+//Each line is "<key> = <value>", where the keys are loop_start, loop_end, bpm, beats_per_bar and first_beat
+//Loop points and the first beat are in sample frames
+//Older ".loop" sidecars use the same format with only the loop points, and are still read when there's no ".track" file
+#[derive(Default)]
+struct TrackInfo {
+    loop_points: Option<LoopPoints>,
+    tempo: Option<Tempo>
+}
+
+impl TrackInfo {
+    fn from_sidecar(path: &str) -> Self {
+        let mut sidecar_path = format!("{}.track", path);
+        let file = match File::open(&sidecar_path) {
+            Ok(f) => { f }
+            Err(_) => {
+                sidecar_path = format!("{}.loop", path);
+                match File::open(&sidecar_path) {
+                    Ok(f) => { f }
+                    Err(_) => { return TrackInfo::default(); }
+                }
+            }
+        };
+
+        let mut loop_start = None;
+        let mut loop_end = None;
+        let mut bpm = None;
+        let mut beats_per_bar = 4;
+        let mut first_beat = 0;
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(l) => { l }
+                Err(e) => {
+                    println!("Error reading {}: {}", sidecar_path, e);
+                    break;
+                }
+            };
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.is_empty() || tokens[0].starts_with('#') {
+                continue;
+            }
+            if tokens.len() != 3 {
+                println!("Malformed line in {}: {}", sidecar_path, line);
+                continue;
+            }
+
+            let value = tokens[2];
+            let parsed = match tokens[0] {
+                "loop_start" => { value.parse().map(|v| loop_start = Some(v)).is_ok() }
+                "loop_end" => { value.parse().map(|v| loop_end = Some(v)).is_ok() }
+                "bpm" => { value.parse().map(|v| bpm = Some(v)).is_ok() }
+                "beats_per_bar" => { value.parse().map(|v| beats_per_bar = v).is_ok() }
+                "first_beat" => { value.parse().map(|v| first_beat = v).is_ok() }
+                _ => {
+                    println!("Unknown key \"{}\" in {}", tokens[0], sidecar_path);
+                    true
+                }
+            };
+            if !parsed {
+                println!("Invalid value for {} in {}: {}", tokens[0], sidecar_path, value);
+            }
+        }
+
+        let loop_points = match (loop_start, loop_end) {
+            (None, None) => { None }
+            (start, end) => { LoopPoints { start: start.unwrap_or(0), end }.validated(path) }
+        };
+        let tempo = match bpm {
+            Some(bpm) if bpm > 0.0 && beats_per_bar > 0 => { Some(Tempo { bpm, beats_per_bar, first_beat }) }
+            Some(_) => {
+                println!("Ignoring the tempo in {} since it isn't positive", sidecar_path);
+                None
+            }
+            None => { None }
+        };
+        TrackInfo {
+            loop_points,
+            tempo
+        }
+    }
+}
+
+//A second decoder that gets wound forward to the loop start a little at a time while the track plays
+//Decoding a long intro all at once when the loop comes around would starve the source
+struct LoopCue {
+    decoder: Box<dyn Decoder>,
+    position: u64,
+    pending: Option<AudioFrame>         //The frame the loop start falls inside of, trimmed to begin right at it. Some once the cue is ready
+}
+
+//Wraps a decoder so that it loops forever between its loop points
+//Frames that cross the loop end are cut short and the next frame picks up exactly at the loop start
+struct LoopingStream {
+    decoder: Box<dyn Decoder>,
+    loop_points: LoopPoints,
+    position: u64,                      //Sample frame index of the start of the next frame
+    pending: Option<AudioFrame>,        //The partial frame that the loop start falls inside of
+    cue: Option<LoopCue>                //None for loops that start at the beginning, or if the track couldn't be opened twice
+}
+
+impl LoopingStream {
+    fn new(path: &str, decoder: Box<dyn Decoder>, info: &TrackInfo) -> Self {
+        //The sidecar file overrides the track's own metadata
+        let loop_points = match info.loop_points {
+            Some(points) => { points }
+            None => { decoder.loop_points().unwrap_or_default() }
+        };
+
+        let cue = if loop_points.start > 0 {
+            match decoder::open(path) {
+                Ok(decoder) => { Some(LoopCue { decoder, position: 0, pending: None }) }
+                Err(e) => {
+                    println!("Couldn't cue the loop of {}, so it may stutter when it loops: {}", path, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        LoopingStream {
+            decoder,
+            loop_points,
+            position: 0,
+            pending: None,
+            cue
+        }
+    }
+
+    //Decodes up to max_frames more of the cue, stopping once it reaches the loop start
+    fn advance_cue(&mut self, max_frames: usize) -> Result<(), String> {
+        let start = self.loop_points.start;
+        let cue = match &mut self.cue {
+            Some(cue) if cue.pending.is_none() => { cue }
+            _ => { return Ok(()); }
+        };
+
+        for _ in 0..max_frames {
+            let mut frame = match cue.decoder.next_frame() {
+                Ok(frame) => { frame }
+                Err(DecodeError::Eof) => { return Err(String::from("Loop start is past the end of the track")); }
+                Err(e) => { return Err(format!("{}", e)); }
+            };
+            let frame_length = (frame.data.len() / frame.channels) as u64;
+            if cue.position + frame_length > start {
+                let skip = (start - cue.position) as usize * frame.channels;
+                frame.data.drain(0..skip);
+                cue.pending = Some(frame);
+                cue.position = start;
+                return Ok(());
+            }
+            cue.position += frame_length;
+        }
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<AudioFrame, String> {
+        let mut frame = match self.pending.take() {
+            Some(frame) => { frame }
+            None => { self.decode()? }
+        };
+
+        let frame_length = (frame.data.len() / frame.channels) as u64;
+        if let Some(end) = self.loop_points.end {
+            if self.position + frame_length >= end {
+                let keep = end.saturating_sub(self.position) as usize;
+                frame.data.truncate(keep * frame.channels);
+                self.seek_to_loop_start()?;
+                if frame.data.is_empty() {
+                    return self.next_frame();
+                }
+                return Ok(frame);
+            }
+        }
+        self.position += frame_length;
+
+        //Errors here just mean falling back to decoding the intro when the loop comes around
+        if let Err(e) = self.advance_cue(CUE_FRAMES_PER_FRAME) {
+            println!("Error cueing the loop start: {}", e);
+            self.cue = None;
+        }
+        Ok(frame)
+    }
+
+    fn decode(&mut self) -> Result<AudioFrame, String> {
+        match self.decoder.next_frame() {
+            Ok(frame) => { Ok(frame) }
+            Err(DecodeError::Eof) => {
+                if self.position == 0 {
+                    return Err(String::from("Track has no audio"));
+                }
+                self.seek_to_loop_start()?;
+                match self.pending.take() {
+                    Some(frame) => { Ok(frame) }
+                    None => { Err(String::from("Loop start is past the end of the track")) }
+                }
+            }
+            Err(e) => { Err(format!("{}", e)) }
+        }
+    }
+
+    //Jumps to the loop start
+    fn seek_to_loop_start(&mut self) -> Result<(), String> {
+        //Swap in the cue, finishing it first if the loop came around before it was ready
+        //The old decoder then becomes the cue for the next time around
+        self.advance_cue(usize::MAX)?;
+        if let Some(cue) = self.cue.take() {
+            let mut old = mem::replace(&mut self.decoder, cue.decoder);
+            self.pending = cue.pending;
+            self.position = self.loop_points.start;
+            old.rewind()?;
+            self.cue = Some(LoopCue { decoder: old, position: 0, pending: None });
+            return Ok(());
+        }
+
+        //Without a cue, decode from the beginning up to the loop start, keeping the part of the frame at and after it
+        self.decoder.rewind()?;
+        let start = self.loop_points.start;
+        let mut position = 0;
+        loop {
+            let mut frame = match self.decoder.next_frame() {
+                Ok(frame) => { frame }
+                Err(DecodeError::Eof) => { return Err(String::from("Loop start is past the end of the track")); }
+                Err(e) => { return Err(format!("{}", e)); }
+            };
+            let frame_length = (frame.data.len() / frame.channels) as u64;
+            if position + frame_length > start {
+                let skip = (start - position) as usize * frame.channels;
+                frame.data.drain(0..skip);
+                self.pending = Some(frame);
+                self.position = start;
+                return Ok(());
+            }
+            position += frame_length;
+        }
+    }
+}
+
+//A buffer queued on a track's source
+struct QueuedFrame {
+    start: u64,                         //Track position in sample frames
+    sample_rate: i32,
+    mono: Vec<f32>                      //Downmixed copy of the samples for spectrum analysis
+}
+
+pub struct BgmTrack {
+    pub path: String,
+    info: TrackInfo,
+    stream: Option<LoopingStream>,      //None once the stream has hit an error
+    source: StreamingSource,
+    queued_frames: VecDeque<QueuedFrame>,
+    volume: f32,                        //Crossfade volume from 0 to 1
+    fade_rate: f32,                     //Change in volume per second
+    start_when_buffered: bool
+}
+
+impl BgmTrack {
+    pub fn load(ctx: &alto::Context, path: &str, volume: f32) -> Option<Self> {
+        let decoder = load_decoder(path)?;
+        let source = match ctx.new_streaming_source() {
+            Ok(s) => { s }
+            Err(e) => {
+                println!("Error creating bgm source: {}", e);
+                return None;
+            }
+        };
+        let info = TrackInfo::from_sidecar(path);
+        Some(BgmTrack {
+            path: String::from(path),
+            stream: Some(LoopingStream::new(path, decoder, &info)),
+            info,
+            source,
+            queued_frames: VecDeque::with_capacity(IDEAL_FRAMES_QUEUED as usize),
+            volume,
+            fade_rate: 0.0,
+            start_when_buffered: true
+        })
+    }
+
+    pub fn fade(&mut self, fade_time: f32, fading_in: bool) {
+        if fade_time <= 0.0 {
+            self.volume = if fading_in { 1.0 } else { 0.0 };
+            self.fade_rate = 0.0;
+            return;
+        }
+        self.fade_rate = if fading_in { 1.0 / fade_time } else { -1.0 / fade_time };
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        self.volume <= 0.0 && self.fade_rate <= 0.0
+    }
+
+    pub fn set_pitch(&mut self, pitch: f32) {
+        if let Err(e) = self.source.set_pitch(pitch) {
+            println!("Error setting bgm pitch: {}", e);
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.source.pause();
+    }
+
+    pub fn resume(&mut self) {
+        self.source.play();
+    }
+
+    pub fn play_pause(&mut self) {
+        match self.source.state() {
+            SourceState::Playing | SourceState::Initial => {
+                self.source.pause();
+                self.start_when_buffered = false;
+            }
+            SourceState::Paused | SourceState::Stopped => {
+                self.source.play();
+                self.start_when_buffered = true;
+            }
+            SourceState::Unknown(code) => { println!("Source is in an unknown state: {}", code); }
+        }
+    }
+
+    //Starts over from the very beginning of the track, intro included
+    pub fn restart(&mut self) {
+        self.source.stop();
+        let info = &self.info;
+        let path = &self.path;
+        self.stream = load_decoder(path).map(|decoder| LoopingStream::new(path, decoder, info));
+        self.start_when_buffered = true;
+    }
+
+    //Seconds until the start of the next bar, if the track's tempo is known
+    pub fn time_to_next_bar(&self) -> Option<f32> {
+        let tempo = self.info.tempo?;
+        let front = self.queued_frames.front()?;
+        if self.source.state() != SourceState::Playing || front.sample_rate <= 0 {
+            return None;
+        }
+
+        let position = front.start + self.source.sample_offset() as u64;
+        let seconds = (position as f64 - tempo.first_beat as f64) / front.sample_rate as f64;
+        let bar_length = tempo.bar_length() as f64;
+        let into_bar = seconds.rem_euclid(bar_length);
+        Some((bar_length - into_bar) as f32)
+    }
+
+    pub fn bar_length(&self) -> Option<f32> {
+        self.info.tempo.map(|tempo| tempo.bar_length())
+    }
+
+    //The next length mono samples starting from what's audible right now, along with their sample rate
+    //None if the track isn't playing or not enough audio is queued yet
+    pub fn playing_window(&self, length: usize) -> Option<(Vec<f32>, i32)> {
+        let front = self.queued_frames.front()?;
+        if self.source.state() != SourceState::Playing {
+            return None;
+        }
+
+        let mut offset = self.source.sample_offset() as usize;
+        let mut window = Vec::with_capacity(length);
+        for frame in self.queued_frames.iter() {
+            if frame.sample_rate != front.sample_rate {
+                break;
+            }
+            if offset < frame.mono.len() {
+                let take = usize::min(frame.mono.len() - offset, length - window.len());
+                window.extend_from_slice(&frame.mono[offset..offset + take]);
+                if window.len() == length {
+                    return Some((window, front.sample_rate));
+                }
+            }
+            offset = offset.saturating_sub(frame.mono.len());
+        }
+        None
+    }
+
+    //Keeps the source fed with audio and applies the crossfade
+    pub fn update(&mut self, ctx: &alto::Context, delta_time: f32, bus_gain: f32) {
+        self.volume = glm::clamp_scalar(self.volume + self.fade_rate * delta_time, 0.0, 1.0);
+        if let Err(e) = self.source.set_gain(self.volume * bus_gain) {
+            println!("Error setting bgm gain: {}", e);
+        }
+
+        //Unqueue any processed buffers
+        while self.source.buffers_processed() > 0 {
+            if let Err(e) = self.source.unqueue_buffer() {
+                println!("Error unqueueing bgm buffer: {}", e);
+                break;
+            }
+            self.queued_frames.pop_front();
+        }
+
+        //Top the source back up to the ideal number of queued frames
+        while self.source.buffers_queued() < IDEAL_FRAMES_QUEUED {
+            let stream = match &mut self.stream {
+                Some(s) => { s }
+                None => { break; }
+            };
+            let frame_start = stream.position;
+            match stream.next_frame() {
+                Ok(frame) => {
+                    if !self.queue_frame(ctx, frame_start, frame) {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    println!("Error streaming {}: {}", self.path, e);
+                    self.stream = None;
+                }
+            }
+        }
+
+        if self.source.state() != SourceState::Playing && self.start_when_buffered && self.source.buffers_queued() == IDEAL_FRAMES_QUEUED {
+            self.source.play();
+            self.start_when_buffered = false;
+        }
+    }
+
+    //Returns false if the frame couldn't be queued
+    fn queue_frame(&mut self, ctx: &alto::Context, frame_start: u64, frame: AudioFrame) -> bool {
+        let mono: Vec<f32> = frame.data.chunks_exact(frame.channels.max(1)).map(|s| {
+            s.iter().map(|sample| *sample as f32).sum::<f32>() / (s.len() as f32 * i16::MAX as f32)
+        }).collect();
+
+        let buffer = if frame.channels == 1 {            //Mono
+            let samples: Vec<_> = frame.data.iter().map(|sample| alto::Mono { center: *sample }).collect();
+            ctx.new_buffer(samples, frame.sample_rate)
+        } else if frame.channels == 2 {     //Stereo
+            let samples: Vec<_> = frame.data.chunks_exact(2).map(|s| alto::Stereo { left: s[0], right: s[1] }).collect();
+            ctx.new_buffer(samples, frame.sample_rate)
+        } else {
+            println!("Audio file must be mono or stereo.");
+            self.stream = None;
+            return false;
+        };
+
+        match buffer {
+            Ok(b) => {
+                if let Err(e) = self.source.queue_buffer(b) {
+                    println!("Error queueing bgm buffer: {}", e);
+                    return false;
+                }
+                self.queued_frames.push_back(QueuedFrame {
+                    start: frame_start,
+                    sample_rate: frame.sample_rate,
+                    mono
+                });
+                true
+            }
+            Err(e) => {
+                println!("Error creating bgm buffer: {}", e);
+                false
+            }
+        }
+    }
+}
+
+//A crossfade waiting for the next bar line
+struct ScheduledTransition {
+    path: String,
+    wait: f32,                          //Seconds of music until the transition
+    fade_time: f32
+}
+
+//The current track plus any tracks that are still fading out
+pub struct BgmPlayer {
+    pub current: Option<BgmTrack>,
+    fading_out: Vec<BgmTrack>,
+    scheduled: Option<ScheduledTransition>,
+    pitch: f32
+}
+
+impl BgmPlayer {
+    pub fn new(ctx: &alto::Context, path: &str) -> Self {
+        BgmPlayer {
+            current: BgmTrack::load(ctx, path, 1.0),
+            fading_out: Vec::new(),
+            scheduled: None,
+            pitch: 1.0
+        }
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn set_pitch(&mut self, pitch: f32) {
+        self.pitch = pitch;
+        for track in self.tracks_mut() {
+            track.set_pitch(pitch);
+        }
+    }
+
+    pub fn tracks_mut(&mut self) -> impl Iterator<Item = &mut BgmTrack> {
+        self.current.iter_mut().chain(self.fading_out.iter_mut())
+    }
+
+    //Crossfades to another track on the current track's next bar line, over the length of one bar
+    //Tracks without a known tempo crossfade right away
+    pub fn transition_to(&mut self, ctx: &alto::Context, path: &str) {
+        let already_playing = match &self.current {
+            Some(track) => { track.path == path }
+            None => { false }
+        };
+        if already_playing {
+            self.scheduled = None;
+            return;
+        }
+
+        let timing = self.current.as_ref().and_then(|track| Some((track.time_to_next_bar()?, track.bar_length()?)));
+        match timing {
+            Some((wait, bar_length)) => {
+                self.scheduled = Some(ScheduledTransition {
+                    path: String::from(path),
+                    wait,
+                    fade_time: bar_length
+                });
+            }
+            None => {
+                self.scheduled = None;
+                self.crossfade_to(ctx, path, CROSSFADE_TIME);
+            }
+        }
+    }
+
+    //Fades the current track out while the new one fades in
+    pub fn crossfade_to(&mut self, ctx: &alto::Context, path: &str, fade_time: f32) {
+        self.scheduled = None;
+        if let Some(mut track) = BgmTrack::load(ctx, path, 0.0) {
+            track.set_pitch(self.pitch);
+            track.fade(fade_time, true);
+            if let Some(mut old) = self.current.take() {
+                old.fade(fade_time, false);
+                self.fading_out.push(old);
+            }
+            self.current = Some(track);
+        }
+    }
+
+    pub fn update(&mut self, ctx: &alto::Context, delta_time: f32, bus_gain: f32) {
+        //Pitch shifting speeds up the music, so the bar line comes sooner
+        let due = match &mut self.scheduled {
+            Some(transition) => {
+                transition.wait -= delta_time * self.pitch;
+                transition.wait <= 0.0
+            }
+            None => { false }
+        };
+        if due {
+            if let Some(transition) = self.scheduled.take() {
+                self.crossfade_to(ctx, &transition.path, transition.fade_time);
+            }
+        }
+
+        for track in self.tracks_mut() {
+            track.update(ctx, delta_time, bus_gain);
+        }
+        self.fading_out.retain(|track| !track.is_faded_out());
+    }
+}
@@ -0,0 +1,479 @@
+use lewton::inside_ogg::OggStreamReader;
+use minimp3 as mp3;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+//Every supported audio format is decoded into chunks of interleaved 16-bit samples
+
+const SUPPORTED_EXTENSIONS: [&str; 3] = ["mp3", "wav", "ogg"];
+const WAV_FRAME_LENGTH: usize = 1152;       //Sample frames per chunk, chosen to match an mp3 frame
+
+pub struct AudioFrame {
+    pub data: Vec<i16>,
+    pub channels: usize,
+    pub sample_rate: i32
+}
+
+pub enum DecodeError {
+    Eof,
+    Other(String)
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Eof => { write!(f, "end of file") }
+            DecodeError::Other(message) => { write!(f, "{}", message) }
+        }
+    }
+}
+
+pub trait Decoder: Send {
+    fn next_frame(&mut self) -> Result<AudioFrame, DecodeError>;
+
+    //Goes back to the start of the audio so it can be looped
+    fn rewind(&mut self) -> Result<(), String>;
+
+    //Loop points stored in the file's own metadata
+    fn loop_points(&self) -> Option<LoopPoints> { None }
+}
+
+//Where a track loops, in sample frames from the start of the audio
+//Tracks with an intro start their loop partway in, and tracks without an end loop when the audio runs out
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoopPoints {
+    pub start: u64,
+    pub end: Option<u64>
+}
+
+impl LoopPoints {
+    //A loop has to contain at least one sample
+    pub fn validated(self, path: &str) -> Option<Self> {
+        match self.end {
+            Some(end) if end <= self.start => {
+                println!("Ignoring loop points for {} since the loop end comes before the loop start", path);
+                None
+            }
+            _ => { Some(self) }
+        }
+    }
+}
+
+pub fn is_supported(path: &str) -> bool {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(e) => { SUPPORTED_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()) }
+        None => { false }
+    }
+}
+
+//Picks a decoder based on the file's extension
+pub fn open(path: &str) -> Result<Box<dyn Decoder>, String> {
+    let extension = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(e) => { e.to_ascii_lowercase() }
+        None => { return Err(format!("{} has no file extension", path)); }
+    };
+
+    let file = File::open(path).map_err(|e| format!("Unable to open: {}\n{}", path, e))?;
+    match extension.as_str() {
+        "mp3" => {
+            let mut file = file;
+            let gapless = read_lame_gapless_info(&mut file);
+            file.seek(SeekFrom::Start(0)).map_err(|e| format!("{}", e))?;
+            Ok(Box::new(Mp3Decoder {
+                decoder: mp3::Decoder::new(file),
+                gapless,
+                position: 0
+            }))
+        }
+        "wav" => { Ok(Box::new(WavDecoder::new(file)?)) }
+        "ogg" => {
+            match OggStreamReader::new(file) {
+                Ok(reader) => { Ok(Box::new(OggDecoder { reader })) }
+                Err(e) => { Err(format!("Error reading ogg headers of {}: {}", path, e)) }
+            }
+        }
+        _ => { Err(format!("Unsupported audio format: {}", extension)) }
+    }
+}
+
+//Decodes the whole file and averages the channels together, since OpenAL only positions mono sources in 3D
+pub fn decode_mono(path: &str) -> Result<(Vec<i16>, i32), String> {
+    let mut decoder = open(path)?;
+    let mut samples = Vec::new();
+    let mut sample_rate = 0;
+    loop {
+        match decoder.next_frame() {
+            Ok(frame) => {
+                sample_rate = frame.sample_rate;
+                if frame.channels == 1 {
+                    samples.extend_from_slice(&frame.data);
+                } else {
+                    for chunk in frame.data.chunks_exact(frame.channels) {
+                        let sum: i32 = chunk.iter().map(|s| *s as i32).sum();
+                        samples.push((sum / frame.channels as i32) as i16);
+                    }
+                }
+            }
+            Err(DecodeError::Eof) => { break; }
+            Err(e) => { return Err(format!("Error decoding {}: {}", path, e)); }
+        }
+    }
+    Ok((samples, sample_rate))
+}
+
+//The silence mp3 encoders add to the start and end of a track, which has to be cut out for a seamless loop
+#[derive(Clone, Copy)]
+struct Mp3Gapless {
+    skip: u64,                      //Sample frames of padding at the start
+    length: u64                     //Sample frames of actual audio
+}
+
+const MP3_DECODER_DELAY: u64 = 529;
+
+//Reads the encoder delay and padding from the LAME tag in the first frame, if there is one
+//The tag lives inside a Xing/Info frame, which minimp3 decodes as a frame of silence
+fn read_lame_gapless_info(file: &mut File) -> Option<Mp3Gapless> {
+    let mut bytes = vec![0; 4096];
+    let read = file.read(&mut bytes).ok()?;
+    bytes.truncate(read);
+
+    //Skip past an ID3v2 tag, whose size is stored as four 7-bit bytes
+    let mut offset = 0;
+    if bytes.len() >= 10 && &bytes[0..3] == b"ID3" {
+        let size = bytes[6..10].iter().fold(0usize, |acc, b| (acc << 7) | (*b & 0x7F) as usize);
+        offset = 10 + size;
+        if bytes[5] & 0x10 != 0 {
+            offset += 10;       //Footer
+        }
+        if offset + 4 > bytes.len() {
+            file.seek(SeekFrom::Start(offset as u64)).ok()?;
+            bytes = vec![0; 4096];
+            let read = file.read(&mut bytes).ok()?;
+            bytes.truncate(read);
+            offset = 0;
+        }
+    }
+    let frame = bytes.get(offset..)?;
+    if frame.len() < 4 || frame[0] != 0xFF || frame[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let samples_per_frame = if (frame[1] >> 3) & 0x03 == 0x03 { 1152 } else { 576 };    //MPEG-1 or MPEG-2/2.5
+
+    //The Xing header comes after the side info, whose size depends on the version and channel count
+    let xing = (4..usize::min(64, frame.len().saturating_sub(4))).find(|i| &frame[*i..*i + 4] == b"Xing" || &frame[*i..*i + 4] == b"Info")?;
+    let read_u32 = |i: usize| -> Option<u32> { Some(u32::from_be_bytes([*frame.get(i)?, *frame.get(i + 1)?, *frame.get(i + 2)?, *frame.get(i + 3)?])) };
+    let flags = read_u32(xing + 4)?;
+    let mut p = xing + 8;
+    let frame_count = if flags & 0x1 != 0 {
+        p += 4;
+        read_u32(p - 4)? as u64
+    } else {
+        return None;
+    };
+    if flags & 0x2 != 0 { p += 4; }         //Byte count
+    if flags & 0x4 != 0 { p += 100; }       //Seek table
+    if flags & 0x8 != 0 { p += 4; }         //Quality
+
+    //The delay and padding are packed into three bytes 21 bytes into the LAME extension
+    let encoder = frame.get(p..p + 4)?;
+    if encoder != b"LAME" && encoder != b"Lavc" && encoder != b"Lavf" {
+        return None;
+    }
+    let packed = frame.get(p + 21..p + 24)?;
+    let delay = ((packed[0] as u64) << 4) | (packed[1] as u64 >> 4);
+    let padding = (((packed[1] & 0x0F) as u64) << 8) | packed[2] as u64;
+
+    let total = frame_count * samples_per_frame;
+    if delay + padding >= total {
+        return None;
+    }
+    Some(Mp3Gapless {
+        skip: samples_per_frame + delay + MP3_DECODER_DELAY,
+        length: total - delay - padding
+    })
+}
+
+struct Mp3Decoder {
+    decoder: mp3::Decoder<File>,
+    gapless: Option<Mp3Gapless>,
+    position: u64                   //Sample frames decoded so far, including padding
+}
+
+impl Decoder for Mp3Decoder {
+    fn next_frame(&mut self) -> Result<AudioFrame, DecodeError> {
+        loop {
+            let frame = match self.decoder.next_frame() {
+                Ok(frame) => { frame }
+                Err(mp3::Error::Eof) => { return Err(DecodeError::Eof); }
+                Err(e) => { return Err(DecodeError::Other(format!("{}", e))); }
+            };
+            if frame.channels == 0 {
+                continue;
+            }
+
+            let frame_start = self.position;
+            let frame_length = (frame.data.len() / frame.channels) as u64;
+            self.position += frame_length;
+
+            //Trim the encoder padding off of either end
+            let mut data = frame.data;
+            if let Some(gapless) = self.gapless {
+                let end = gapless.skip + gapless.length;
+                if frame_start >= end {
+                    return Err(DecodeError::Eof);
+                }
+                let first = gapless.skip.saturating_sub(frame_start).min(frame_length);
+                let last = u64::min(end - frame_start, frame_length);
+                if first >= last {
+                    continue;
+                }
+                data = data[(first as usize * frame.channels)..(last as usize * frame.channels)].to_vec();
+            }
+
+            return Ok(AudioFrame {
+                data,
+                channels: frame.channels,
+                sample_rate: frame.sample_rate
+            });
+        }
+    }
+
+    //minimp3 reads ahead of what it has decoded, so seeking the file isn't enough and the decoder has to start over
+    fn rewind(&mut self) -> Result<(), String> {
+        let mut file = self.decoder.reader_mut().try_clone().map_err(|e| format!("{}", e))?;
+        file.seek(SeekFrom::Start(0)).map_err(|e| format!("{}", e))?;
+        self.decoder = mp3::Decoder::new(file);
+        self.position = 0;
+        Ok(())
+    }
+}
+
+struct OggDecoder {
+    reader: OggStreamReader<File>
+}
+
+impl Decoder for OggDecoder {
+    fn next_frame(&mut self) -> Result<AudioFrame, DecodeError> {
+        //Packets can decode to nothing, so keep going until there are samples
+        loop {
+            match self.reader.read_dec_packet_itl() {
+                Ok(Some(data)) => {
+                    if data.is_empty() {
+                        continue;
+                    }
+                    return Ok(AudioFrame {
+                        data,
+                        channels: self.reader.ident_hdr.audio_channels as usize,
+                        sample_rate: self.reader.ident_hdr.audio_sample_rate as i32
+                    });
+                }
+                Ok(None) => { return Err(DecodeError::Eof); }
+                Err(e) => { return Err(DecodeError::Other(format!("{}", e))); }
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), String> {
+        self.reader.seek_absgp_pg(0).map_err(|e| format!("{}", e))
+    }
+
+    //Uses the LOOPSTART and LOOPLENGTH (or LOOPEND) comments that a lot of game music is tagged with
+    fn loop_points(&self) -> Option<LoopPoints> {
+        let mut start = None;
+        let mut length = None;
+        let mut end = None;
+        for (key, value) in self.reader.comment_hdr.comment_list.iter() {
+            let value = value.trim().parse::<u64>().ok();
+            match key.to_ascii_uppercase().as_str() {
+                "LOOPSTART" => { start = value; }
+                "LOOPLENGTH" => { length = value; }
+                "LOOPEND" => { end = value; }
+                _ => {}
+            }
+        }
+
+        let start = start?;
+        let end = match (length, end) {
+            (Some(length), _) => { Some(start + length) }
+            (None, end) => { end }
+        };
+        LoopPoints { start, end }.validated("ogg metadata")
+    }
+}
+
+#[derive(Clone, Copy)]
+enum WavFormat {
+    Pcm,
+    Float
+}
+
+struct WavDecoder {
+    reader: BufReader<File>,
+    format: WavFormat,
+    channels: usize,
+    sample_rate: i32,
+    bytes_per_sample: usize,
+    data_start: u64,
+    data_length: u64,
+    position: u64                   //Bytes read from the data chunk
+}
+
+impl WavDecoder {
+    const FORMAT_PCM: u16 = 1;
+    const FORMAT_FLOAT: u16 = 3;
+    const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+    //Reads the RIFF header and finds the fmt and data chunks
+    fn new(file: File) -> Result<Self, String> {
+        let mut reader = BufReader::new(file);
+        let mut header = [0; 12];
+        reader.read_exact(&mut header).map_err(|e| format!("Error reading wav header: {}", e))?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(String::from("Not a RIFF WAVE file"));
+        }
+
+        let mut fmt = None;
+        loop {
+            let mut chunk_header = [0; 8];
+            reader.read_exact(&mut chunk_header).map_err(|_| String::from("wav file has no data chunk"))?;
+            let chunk_length = u32::from_le_bytes([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]) as u64;
+
+            match &chunk_header[0..4] {
+                b"fmt " => {
+                    let mut chunk = vec![0; chunk_length as usize];
+                    reader.read_exact(&mut chunk).map_err(|e| format!("Error reading wav fmt chunk: {}", e))?;
+                    if chunk.len() < 16 {
+                        return Err(String::from("wav fmt chunk is too short"));
+                    }
+                    let read_u16 = |i: usize| { u16::from_le_bytes([chunk[i], chunk[i + 1]]) };
+
+                    //Extensible files keep the real format at the start of the subformat GUID
+                    let mut format_tag = read_u16(0);
+                    if format_tag == Self::FORMAT_EXTENSIBLE && chunk.len() >= 26 {
+                        format_tag = read_u16(24);
+                    }
+                    let channels = read_u16(2) as usize;
+                    let sample_rate = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as i32;
+                    let bits_per_sample = read_u16(14) as usize;
+
+                    let format = match (format_tag, bits_per_sample) {
+                        (Self::FORMAT_PCM, 8) | (Self::FORMAT_PCM, 16) | (Self::FORMAT_PCM, 24) | (Self::FORMAT_PCM, 32) => { WavFormat::Pcm }
+                        (Self::FORMAT_FLOAT, 32) | (Self::FORMAT_FLOAT, 64) => { WavFormat::Float }
+                        _ => { return Err(format!("Unsupported wav format {} with {} bits per sample", format_tag, bits_per_sample)); }
+                    };
+                    if channels == 0 {
+                        return Err(String::from("wav file has no channels"));
+                    }
+                    fmt = Some((format, channels, sample_rate, bits_per_sample / 8));
+                    if chunk_length % 2 == 1 {
+                        reader.seek(SeekFrom::Current(1)).map_err(|e| format!("{}", e))?;
+                    }
+                }
+                b"data" => {
+                    let (format, channels, sample_rate, bytes_per_sample) = match fmt {
+                        Some(f) => { f }
+                        None => { return Err(String::from("wav data chunk comes before the fmt chunk")); }
+                    };
+                    let data_start = reader.seek(SeekFrom::Current(0)).map_err(|e| format!("{}", e))?;
+                    return Ok(WavDecoder {
+                        reader,
+                        format,
+                        channels,
+                        sample_rate,
+                        bytes_per_sample,
+                        data_start,
+                        data_length: chunk_length,
+                        position: 0
+                    });
+                }
+                _ => {
+                    //Chunks are padded to an even length
+                    let skip = chunk_length + chunk_length % 2;
+                    reader.seek(SeekFrom::Current(skip as i64)).map_err(|e| format!("{}", e))?;
+                }
+            }
+        }
+    }
+
+    fn convert_sample(&self, bytes: &[u8]) -> i16 {
+        match (self.format, self.bytes_per_sample) {
+            (WavFormat::Pcm, 1) => { ((bytes[0] as i16) - 128) << 8 }          //8-bit wav is unsigned
+            (WavFormat::Pcm, 2) => { i16::from_le_bytes([bytes[0], bytes[1]]) }
+            (WavFormat::Pcm, 3) => { i16::from_le_bytes([bytes[1], bytes[2]]) }
+            (WavFormat::Pcm, _) => { i16::from_le_bytes([bytes[2], bytes[3]]) }
+            (WavFormat::Float, 4) => {
+                let f = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                (f32::clamp(f, -1.0, 1.0) * i16::MAX as f32) as i16
+            }
+            (WavFormat::Float, _) => {
+                let f = f64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]);
+                (f64::clamp(f, -1.0, 1.0) * i16::MAX as f64) as i16
+            }
+        }
+    }
+}
+
+impl Decoder for WavDecoder {
+    fn next_frame(&mut self) -> Result<AudioFrame, DecodeError> {
+        let frame_bytes = (self.bytes_per_sample * self.channels) as u64;
+        let remaining = (self.data_length - self.position) / frame_bytes * frame_bytes;
+        if remaining == 0 {
+            return Err(DecodeError::Eof);
+        }
+
+        let length = u64::min(remaining, WAV_FRAME_LENGTH as u64 * frame_bytes);
+        let mut bytes = vec![0; length as usize];
+        if let Err(e) = self.reader.read_exact(&mut bytes) {
+            return Err(DecodeError::Other(format!("{}", e)));
+        }
+        self.position += length;
+
+        let data = bytes.chunks_exact(self.bytes_per_sample).map(|s| self.convert_sample(s)).collect();
+        Ok(AudioFrame {
+            data,
+            channels: self.channels,
+            sample_rate: self.sample_rate
+        })
+    }
+
+    fn rewind(&mut self) -> Result<(), String> {
+        self.position = 0;
+        self.reader.seek(SeekFrom::Start(self.data_start)).map(|_| ()).map_err(|e| format!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_MP3: &str = "sfx/totoro/drown.mp3";
+
+    //Decodes up to frame_count frames, stopping early at the end of the file
+    fn decode_frames(decoder: &mut dyn Decoder, frame_count: usize) -> Vec<i16> {
+        let mut samples = Vec::new();
+        for _ in 0..frame_count {
+            match decoder.next_frame() {
+                Ok(frame) => { samples.extend_from_slice(&frame.data); }
+                Err(DecodeError::Eof) => { break; }
+                Err(e) => { panic!("Error decoding {}: {}", TEST_MP3, e); }
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn mp3_rewind_matches_fresh_decode() {
+        //Stop partway through, the way a loop end before the end of the file does
+        let mut decoder = open(TEST_MP3).unwrap();
+        let first_pass = decode_frames(decoder.as_mut(), 10);
+        assert!(first_pass.len() > 0);
+
+        decoder.rewind().unwrap();
+        let second_pass = decode_frames(decoder.as_mut(), 20);
+        let fresh = decode_frames(open(TEST_MP3).unwrap().as_mut(), 20);
+        assert_eq!(second_pass, fresh);
+        assert_eq!(&second_pass[..first_pass.len()], &first_pass[..]);
+    }
+}
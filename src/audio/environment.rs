@@ -0,0 +1,125 @@
+use ozy::collision::{Ray, Terrain, ray_hit_terrain};
+use std::sync::mpsc::Sender;
+use crate::gamestate::ReverbZone;
+use crate::routines::send_or_error;
+use crate::structs::EntityList;
+use super::{AudioCommand, ReverbPreset, SfxHandle};
+
+//How the level changes what sounds reach the listener
+//Runs on the main thread since that's where the collision data lives
+
+const RAYS_PER_FRAME: usize = 8;            //Raycasts against the terrain are expensive, so only a few sounds get checked each frame
+const OCCLUSION_RATE: f32 = 4.0;            //How fast occlusion follows a change in line of sight, per second
+const OCCLUSION_EPSILON: f32 = 0.25;        //Hits this close to the sound are the ground it's sitting on, not something in the way
+
+struct OccludedSound {
+    handle: SfxHandle,
+    position: glm::TVec3<f32>,
+    blocked: bool,
+    occlusion: f32,
+    sent_occlusion: f32
+}
+
+//Muffles sounds that the terrain is between the listener and
+pub struct SoundOcclusion {
+    sounds: Vec<OccludedSound>,           //In the order they get raycast, so every sound gets its turn
+    next_ray: usize
+}
+
+impl SoundOcclusion {
+    pub fn new() -> Self {
+        SoundOcclusion {
+            sounds: Vec::new(),
+            next_ray: 0
+        }
+    }
+
+    pub fn track(&mut self, handle: SfxHandle, position: [f32; 3]) {
+        self.forget(handle);
+        self.sounds.push(OccludedSound {
+            handle,
+            position: glm::make_vec3(&position),
+            blocked: false,
+            occlusion: 0.0,
+            sent_occlusion: 0.0
+        });
+    }
+
+    //Called once the audio thread is done with a sound
+    pub fn forget(&mut self, handle: SfxHandle) {
+        if let Some(idx) = self.sounds.iter().position(|sound| sound.handle == handle) {
+            self.sounds.remove(idx);
+
+            //Keep the cursor on the same sound it was going to check next
+            if idx < self.next_ray {
+                self.next_ray -= 1;
+            }
+        }
+    }
+
+    //current_position gives the latest position of sounds that move around
+    //frame_time is real time, so that occlusion still eases while the simulation is slowed down or paused
+    pub fn update<F: Fn(SfxHandle) -> Option<glm::TVec3<f32>>>(&mut self, audio_sender: &Sender<AudioCommand>, terrain: &Terrain, listener: &glm::TVec3<f32>, frame_time: f32, current_position: F) {
+        if self.sounds.is_empty() {
+            return;
+        }
+
+        //Check line of sight for a few sounds, picking up where last frame left off
+        let count = self.sounds.len();
+        for i in 0..usize::min(RAYS_PER_FRAME, count) {
+            let sound = &mut self.sounds[(self.next_ray + i) % count];
+            if let Some(position) = current_position(sound.handle) {
+                sound.position = position;
+            }
+            sound.blocked = line_of_sight_blocked(terrain, listener, &sound.position);
+        }
+        self.next_ray = (self.next_ray + RAYS_PER_FRAME) % count;
+
+        //Ease toward the target so sounds don't pop when something passes in front of them
+        for sound in self.sounds.iter_mut() {
+            let target = if sound.blocked { 1.0 } else { 0.0 };
+            let step = OCCLUSION_RATE * frame_time;
+            sound.occlusion = if sound.occlusion < target { f32::min(sound.occlusion + step, target) }
+                              else { f32::max(sound.occlusion - step, target) };
+
+            if f32::abs(sound.occlusion - sound.sent_occlusion) > 0.01 || (sound.occlusion == target && sound.sent_occlusion != target) {
+                send_or_error(audio_sender, AudioCommand::SetSFXOcclusion(sound.handle, sound.occlusion));
+                sound.sent_occlusion = sound.occlusion;
+            }
+        }
+    }
+}
+
+fn line_of_sight_blocked(terrain: &Terrain, listener: &glm::TVec3<f32>, source: &glm::TVec3<f32>) -> bool {
+    let offset = source - listener;
+    let distance = glm::length(&offset);
+    if distance <= OCCLUSION_EPSILON {
+        return false;
+    }
+
+    let ray = Ray {
+        origin: *listener,
+        direction: offset / distance
+    };
+    match ray_hit_terrain(terrain, &ray) {
+        Some(collision) => { glm::distance(listener, &collision.point) < distance - OCCLUSION_EPSILON }
+        None => { false }
+    }
+}
+
+//The reverb of the smallest zone the listener is in, since smaller zones are placed inside bigger ones for more detail
+pub fn listener_reverb(zones: &EntityList<ReverbZone>, listener: &glm::TVec3<f32>) -> Option<ReverbPreset> {
+    let mut best: Option<&ReverbZone> = None;
+    for i in 0..zones.len() {
+        if let Some(zone) = &zones.entities[i] {
+            if !zone.contains(listener) {
+                continue;
+            }
+            best = match best {
+                Some(b) if b.radius <= zone.radius => { Some(b) }
+                _ => { Some(zone) }
+            };
+        }
+    }
+    best.map(|zone| zone.preset)
+}
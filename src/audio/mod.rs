@@ -0,0 +1,356 @@
+pub mod attach;
+mod bgm;
+pub mod decoder;
+pub mod environment;
+pub mod null;
+mod openal;
+pub mod spectrum;
+pub mod voices;
+
+use strum::EnumCount;
+use tfd::MessageBoxIcon;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::routines::send_or_error;
+use crate::structs::Configuration;
+use self::decoder::Decoder;
+use self::null::{AudioRecording, NullBackend, RecordingBackend};
+use self::openal::OpenAlBackend;
+
+pub const DEFAULT_BGM_PATH: &str = "music/cryptic_relics.mp3";
+const DANGER_BGM_PATH: &str = "music/town_battle.mp3";
+const CLEARED_BGM_PATH: &str = "music/recovery_spring.mp3";
+
+//The mood of the current gameplay, which decides which track the dynamic music plays
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MusicState {
+    Explore,
+    Danger,
+    Cleared
+}
+
+impl MusicState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MusicState::Explore => { "Explore" }
+            MusicState::Danger => { "Danger" }
+            MusicState::Cleared => { "Cleared" }
+        }
+    }
+}
+
+//The kinds of space a reverb zone can make the level sound like
+#[derive(Clone, Copy, Debug, EnumCount, PartialEq, Eq)]
+pub enum ReverbPreset {
+    Cave,
+    OpenField,
+    Hall
+}
+
+impl ReverbPreset {
+    pub const NAMES: [&'static str; ReverbPreset::COUNT] = ["Cave", "Open field", "Hall"];
+
+    pub fn from_index(idx: usize) -> Option<Self> {
+        match idx {
+            0 => { Some(ReverbPreset::Cave) }
+            1 => { Some(ReverbPreset::OpenField) }
+            2 => { Some(ReverbPreset::Hall) }
+            _ => { None }
+        }
+    }
+}
+
+//Every sound plays through one of these, so that each kind of sound can be turned up or down on its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bus {
+    Music,
+    Sfx,
+    Voice,
+    Ui
+}
+
+impl Bus {
+    pub const ALL: [Bus; 4] = [Bus::Music, Bus::Sfx, Bus::Voice, Bus::Ui];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bus::Music => { "Music" }
+            Bus::Sfx => { "SFX" }
+            Bus::Voice => { "Voice" }
+            Bus::Ui => { "UI" }
+        }
+    }
+
+    fn config_key(&self) -> &'static str {
+        match self {
+            Bus::Music => { Configuration::BGM_VOLUME }
+            Bus::Sfx => { Configuration::SFX_VOLUME }
+            Bus::Voice => { Configuration::VOICE_VOLUME }
+            Bus::Ui => { Configuration::UI_VOLUME }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BusSettings {
+    pub volume: f32,                //Linear volume from 0 to 100
+    pub muted: bool,
+    pub soloed: bool
+}
+
+#[derive(Clone, Debug)]
+pub struct Mixer {
+    pub buses: [BusSettings; 4]     //Indexed by Bus
+}
+
+impl Mixer {
+    pub const DEFAULT_VOLUME: f32 = 10.0;
+
+    pub fn from_config(config: &Configuration) -> Self {
+        let mut buses = [BusSettings { volume: Self::DEFAULT_VOLUME, muted: false, soloed: false }; 4];
+        for bus in Bus::ALL.iter() {
+            if let Some(v) = config.float_options.get(bus.config_key()) {
+                buses[*bus as usize].volume = *v;
+            }
+        }
+        Mixer {
+            buses
+        }
+    }
+
+    //Only volumes are saved, since muting and soloing are for the current session
+    pub fn save_to_config(&self, config: &mut Configuration) {
+        for bus in Bus::ALL.iter() {
+            config.float_options.insert(String::from(bus.config_key()), self.buses[*bus as usize].volume);
+        }
+    }
+
+    pub fn bus(&self, bus: Bus) -> &BusSettings {
+        &self.buses[bus as usize]
+    }
+
+    pub fn bus_mut(&mut self, bus: Bus) -> &mut BusSettings {
+        &mut self.buses[bus as usize]
+    }
+
+    //Soloing any bus silences every bus that isn't soloed
+    pub fn gain(&self, bus: Bus) -> f32 {
+        let any_soloed = self.buses.iter().any(|b| b.soloed);
+        let settings = self.bus(bus);
+        if settings.muted || (any_soloed && !settings.soloed) { 0.0 }
+        else { linearize_gain(settings.volume) }
+    }
+}
+
+//Identifies one playing sound effect
+//Handles are never reused, so a stale handle can't accidentally control some other sound
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SfxHandle(u64);
+
+static NEXT_SFX_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+//When every source is busy, a new sound takes over the source of the least important sound playing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SfxPriority {
+    Low,
+    Normal,
+    High
+}
+
+#[derive(Clone, Debug)]
+pub struct SoundEffectRequest {
+    pub path: String,
+    pub position: [f32; 3],
+    pub linear_gain: f32,
+    pub looping: bool,
+    pub bus: Bus,
+    pub priority: SfxPriority
+}
+
+//Messages the audio system sends back to the main thread
+#[derive(Clone, Debug)]
+pub enum AudioEvent {
+    SfxStarted(SfxHandle, usize),   //Includes the index of the source the sound is playing on
+    SfxFinished(SfxHandle),         //Also sent when a sound is stopped
+    SfxStolen(SfxHandle),           //Stopped to make room for a more important sound
+    SfxDropped(SfxHandle),          //Never played, because it wasn't loaded or everything playing was more important
+    Spectrum(spectrum::Bands),      //Levels of the music's frequency bands, sent a few dozen times a second
+    OutputDevices(Vec<String>),     //Names of the output devices that can be switched to
+    OutputDeviceOpened(String),     //Sent whenever the audio starts playing on a device
+    OutputDeviceLost(String)        //The device was unplugged or otherwise went away, and nothing has replaced it yet
+}
+
+//Represents the kinds of messages the audio system can receive from the main thread
+#[derive(Clone, Debug)]
+pub enum AudioCommand {
+    SetListenerPosition([f32; 3]),
+    SetListenerVelocity([f32; 3]),
+    SetListenerOrientation(([f32; 3], [f32; 3])),
+    SetListenerGain(f32),
+    SetBusVolume(Bus, f32),
+    SetBusMuted(Bus, bool),
+    SetBusSoloed(Bus, bool),
+    SetPitchShift(f32),
+    SetMusicState(MusicState),
+    SetDynamicMusic(bool),
+    LoadSFX(String),
+    PlaySFX(SfxHandle, SoundEffectRequest),
+    StopSFX(SfxHandle),
+    SetSFXPosition(SfxHandle, [f32; 3]),
+    SetSFXVelocity(SfxHandle, [f32; 3]),
+    SetSFXGain(SfxHandle, f32),
+    SetSFXPitch(SfxHandle, f32),
+    SetSFXOcclusion(SfxHandle, f32),    //From 0 for a clear path to the listener up to 1 for fully blocked
+    SetReverb(Option<ReverbPreset>),
+    RefreshOutputDevices,
+    SetOutputDevice(Option<String>),    //None follows the system's default device
+    SelectNewBGM,
+    RestartBGM,
+    PlayPause
+}
+
+//Starts playing a sound effect and returns the handle for controlling it
+//The audio thread reports back on the event channel once the sound has started, finished, or been cut off
+pub fn play_sfx(audio_sender: &Sender<AudioCommand>, request: SoundEffectRequest) -> SfxHandle {
+    let handle = SfxHandle(NEXT_SFX_HANDLE.fetch_add(1, Ordering::Relaxed));
+    send_or_error(audio_sender, AudioCommand::PlaySFX(handle, request));
+    handle
+}
+
+//Returns a decoder for the audio file at path, chosen by its extension
+fn load_decoder(path: &str) -> Option<Box<dyn Decoder>> {
+    match decoder::open(path) {
+        Ok(d) => { Some(d) }
+        Err(e) => {
+            tfd::message_box_ok("Error loading audio", &e, MessageBoxIcon::Error);
+            None
+        }
+    }
+}
+
+//Gain is a non-linear quantity, so we do this conversion in order to translate from a volume value that is linear
+fn linearize_gain(linear_gain: f32) -> f32 {
+    (f32::exp(linear_gain / 100.0) - 1.0) / (glm::e::<f32>() - 1.0)
+}
+
+fn music_path(state: MusicState, explore_path: &str) -> &str {
+    match state {
+        MusicState::Explore => { explore_path }
+        MusicState::Danger => { DANGER_BGM_PATH }
+        MusicState::Cleared => { CLEARED_BGM_PATH }
+    }
+}
+
+//Whatever actually turns the commands from the main thread into sound
+pub trait AudioBackend {
+    fn handle_command(&mut self, command: AudioCommand);
+    fn update(&mut self, delta_time: f32);          //Called about every 10ms
+}
+
+//Which backend the audio thread runs, chosen by the audio_backend config option
+//"openal" is the default, "null" plays nothing, and "record" or "record:<path>" logs the commands to a file
+enum BackendChoice {
+    OpenAl,
+    Null,
+    Record(String)
+}
+
+impl BackendChoice {
+    const DEFAULT_LOG_PATH: &'static str = "audio_log.txt";
+
+    fn from_config(config: &Configuration) -> Self {
+        let option = match config.string_options.get(Configuration::AUDIO_BACKEND) {
+            Some(s) => { s.as_str() }
+            None => { return BackendChoice::OpenAl; }
+        };
+
+        let mut parts = option.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("openal"), None) => { BackendChoice::OpenAl }
+            (Some("null"), None) => { BackendChoice::Null }
+            (Some("record"), None) => { BackendChoice::Record(String::from(Self::DEFAULT_LOG_PATH)) }
+            (Some("record"), Some(path)) => { BackendChoice::Record(String::from(path)) }
+            _ => {
+                println!("Unknown audio backend \"{}\", using OpenAL", option);
+                BackendChoice::OpenAl
+            }
+        }
+    }
+}
+
+//Main function for the audio system
+//Returns the recording when the recording backend was chosen
+pub fn audio_main(audio_receiver: Receiver<AudioCommand>, event_sender: Sender<AudioEvent>, config: &Configuration) -> Option<AudioRecording> {
+    //Allocation is necessary here because we are moving this into another thread
+    let bgm_path = match config.string_options.get(Configuration::MUSIC_NAME) {
+        Some(path) => { String::from(path) }
+        None => { String::from(DEFAULT_BGM_PATH) }
+    };
+
+    let master_volume = match config.float_options.get(Configuration::MASTER_VOLUME) {
+        Some(v) => { *v }
+        None => { 100.0 }
+    };
+    let mixer = Mixer::from_config(config);
+    let output_device = config.string_options.get(Configuration::AUDIO_DEVICE).map(|name| Configuration::unescape(name));
+
+    match BackendChoice::from_config(config) {
+        BackendChoice::OpenAl => {
+            //The OpenAL backend has to be created on the audio thread, since the context can't be sent between threads
+            //If it can't start, the game keeps going without sound instead of leaving the main thread with nobody to talk to
+            thread::spawn(move || {
+                let mut backend: Box<dyn AudioBackend> = match OpenAlBackend::new(master_volume, mixer, bgm_path, output_device, event_sender.clone()) {
+                    Ok(b) => { Box::new(b) }
+                    Err(e) => {
+                        tfd::message_box_ok("OpenAL Error", &format!("{}\n\nThe game will still work, but without any audio.", e), MessageBoxIcon::Warning);
+                        Box::new(NullBackend::new(event_sender))
+                    }
+                };
+                run_backend(backend.as_mut(), audio_receiver);
+            });
+            None
+        }
+        BackendChoice::Null => {
+            spawn_with_backend(audio_receiver, NullBackend::new(event_sender));
+            None
+        }
+        BackendChoice::Record(path) => {
+            let recording = AudioRecording::new();
+            spawn_with_backend(audio_receiver, RecordingBackend::with_log_file(recording.clone(), event_sender, &path));
+            Some(recording)
+        }
+    }
+}
+
+//Runs an audio system with the given backend on its own thread
+//Handy for driving a RecordingBackend from a headless run and then checking what played
+pub fn spawn_with_backend<B: AudioBackend + Send + 'static>(audio_receiver: Receiver<AudioCommand>, mut backend: B) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        run_backend(&mut backend, audio_receiver);
+    })
+}
+
+//Feeds commands to the backend until the main thread hangs up
+fn run_backend(backend: &mut dyn AudioBackend, audio_receiver: Receiver<AudioCommand>) {
+    let mut last_update = Instant::now();
+    loop {
+        //Process all commands from the main thread
+        loop {
+            match audio_receiver.try_recv() {
+                Ok(command) => { backend.handle_command(command); }
+                Err(TryRecvError::Empty) => { break; }
+                Err(TryRecvError::Disconnected) => { return; }
+            }
+        }
+
+        let now = Instant::now();
+        let delta_time = now.duration_since(last_update).as_secs_f32();
+        last_update = now;
+        backend.update(delta_time);
+
+        //Sleep for 10ms to avoid throttling the CPU core
+        thread::sleep(Duration::from_millis(10));
+    }
+}
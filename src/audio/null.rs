@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use crate::routines::send_or_error;
+use super::{AudioBackend, AudioCommand, AudioEvent, SoundEffectRequest};
+
+//Backends that don't make any sound, for running without an audio device
+//They still answer with events, so the main thread forgets about sounds the same way it does with OpenAL
+
+const RECORDING_LIMIT: usize = 1024;    //Only the most recent commands are kept, so long runs don't grow without bound
+
+//Throws every command away
+//This is what the audio thread falls back to when OpenAL can't start, so that the main thread can keep sending commands
+pub struct NullBackend {
+    events: Sender<AudioEvent>
+}
+
+impl NullBackend {
+    pub fn new(events: Sender<AudioEvent>) -> Self {
+        NullBackend {
+            events
+        }
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn handle_command(&mut self, command: AudioCommand) {
+        match command {
+            AudioCommand::PlaySFX(handle, _) => { send_or_error(&self.events, AudioEvent::SfxDropped(handle)); }
+            AudioCommand::StopSFX(handle) => { send_or_error(&self.events, AudioEvent::SfxFinished(handle)); }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, _delta_time: f32) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordedCommand {
+    pub time: f32,                      //Seconds since the backend was created
+    pub command: AudioCommand
+}
+
+#[derive(Default)]
+struct RecordedState {
+    commands: VecDeque<RecordedCommand>,    //Only PlaySFX and StopSFX, since everything else is sent far too often to keep
+    sfx_played: usize
+}
+
+//The sound effects a RecordingBackend has been asked to play and stop
+//Clones share the same recording, so one can stay on the main thread while the backend runs on the audio thread
+#[derive(Clone, Default)]
+pub struct AudioRecording {
+    state: Arc<Mutex<RecordedState>>
+}
+
+impl AudioRecording {
+    pub fn new() -> Self {
+        AudioRecording::default()
+    }
+
+    pub fn commands(&self) -> Vec<RecordedCommand> {
+        match self.state.lock() {
+            Ok(state) => { state.commands.iter().cloned().collect() }
+            Err(_) => { Vec::new() }
+        }
+    }
+
+    //Every sound effect still in the recording, in order, along with when it was requested
+    pub fn played_sfx(&self) -> Vec<(f32, SoundEffectRequest)> {
+        self.recent_sfx(RECORDING_LIMIT).into_iter().rev().collect()
+    }
+
+    //The last few sound effects requested, newest first
+    pub fn recent_sfx(&self, count: usize) -> Vec<(f32, SoundEffectRequest)> {
+        let state = match self.state.lock() {
+            Ok(s) => { s }
+            Err(_) => { return Vec::new(); }
+        };
+        state.commands.iter().rev().filter_map(|recorded| {
+            match &recorded.command {
+                AudioCommand::PlaySFX(_, request) => { Some((recorded.time, request.clone())) }
+                _ => { None }
+            }
+        }).take(count).collect()
+    }
+
+    //Total sound effects requested, including ones that have aged out of the recording
+    pub fn sfx_played_count(&self) -> usize {
+        match self.state.lock() {
+            Ok(state) => { state.sfx_played }
+            Err(_) => { 0 }
+        }
+    }
+
+    fn push(&self, recorded: RecordedCommand) {
+        if let Ok(mut state) = self.state.lock() {
+            if let AudioCommand::PlaySFX(..) = recorded.command {
+                state.sfx_played += 1;
+            }
+            if state.commands.len() == RECORDING_LIMIT {
+                state.commands.pop_front();
+            }
+            state.commands.push_back(recorded);
+        }
+    }
+}
+
+//Remembers the sound effects it's asked to play, and optionally logs commands to a file as they arrive
+pub struct RecordingBackend {
+    recording: AudioRecording,
+    events: Sender<AudioEvent>,
+    log_file: Option<File>,
+    time: f32
+}
+
+impl RecordingBackend {
+    pub fn new(recording: AudioRecording, events: Sender<AudioEvent>) -> Self {
+        RecordingBackend {
+            recording,
+            events,
+            log_file: None,
+            time: 0.0
+        }
+    }
+
+    pub fn with_log_file(recording: AudioRecording, events: Sender<AudioEvent>, path: &str) -> Self {
+        let mut backend = RecordingBackend::new(recording, events);
+        match File::create(path) {
+            Ok(f) => { backend.log_file = Some(f); }
+            Err(e) => { println!("Couldn't create audio log {}: {}", path, e); }
+        }
+        backend
+    }
+}
+
+impl AudioBackend for RecordingBackend {
+    fn handle_command(&mut self, command: AudioCommand) {
+        //The listener and attached sounds are updated every frame, so logging them would drown out everything else
+        let noisy = match command {
+            AudioCommand::SetListenerPosition(_) | AudioCommand::SetListenerVelocity(_) | AudioCommand::SetListenerOrientation(_) |
+            AudioCommand::SetSFXPosition(..) | AudioCommand::SetSFXVelocity(..) | AudioCommand::SetSFXOcclusion(..) => { true }
+            _ => { false }
+        };
+        if let (Some(file), false) = (&mut self.log_file, noisy) {
+            if let Err(e) = writeln!(file, "{:.3} {:?}", self.time, command) {
+                println!("Error writing to audio log: {}", e);
+                self.log_file = None;
+            }
+        }
+
+        //Nothing actually plays, so every sound finishes as soon as it starts
+        match command {
+            AudioCommand::PlaySFX(handle, _) => {
+                send_or_error(&self.events, AudioEvent::SfxStarted(handle, 0));
+                send_or_error(&self.events, AudioEvent::SfxFinished(handle));
+            }
+            AudioCommand::StopSFX(handle) => { send_or_error(&self.events, AudioEvent::SfxFinished(handle)); }
+            _ => { return; }
+        }
+
+        self.recording.push(RecordedCommand {
+            time: self.time,
+            command
+        });
+    }
+
+    fn update(&mut self, delta_time: f32) {
+        self.time += delta_time;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use crate::audio::{Bus, SfxPriority, play_sfx, spawn_with_backend};
+    use super::*;
+
+    fn yell_request() -> SoundEffectRequest {
+        SoundEffectRequest {
+            path: String::from("sfx/totoro/yells/yell.mp3"),
+            position: [1.0, 2.0, 3.0],
+            linear_gain: 100.0,
+            looping: false,
+            bus: Bus::Voice,
+            priority: SfxPriority::Normal
+        }
+    }
+
+    #[test]
+    fn recording_backend_records_played_sfx() {
+        let (audio_sender, audio_receiver) = mpsc::channel();
+        let (event_sender, event_receiver) = mpsc::channel();
+        let recording = AudioRecording::new();
+        let audio_thread = spawn_with_backend(audio_receiver, RecordingBackend::new(recording.clone(), event_sender));
+
+        let handle = play_sfx(&audio_sender, yell_request());
+        audio_sender.send(AudioCommand::SetListenerPosition([0.0, 0.0, 0.0])).unwrap();
+        drop(audio_sender);
+        audio_thread.join().unwrap();
+
+        let played = recording.played_sfx();
+        assert_eq!(played.len(), 1);
+        assert_eq!(played[0].1.path, "sfx/totoro/yells/yell.mp3");
+        assert_eq!(played[0].1.position, [1.0, 2.0, 3.0]);
+        assert_eq!(recording.commands().len(), 1);
+
+        let events: Vec<AudioEvent> = event_receiver.try_iter().collect();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], AudioEvent::SfxStarted(h, _) if h == handle));
+        assert!(matches!(events[1], AudioEvent::SfxFinished(h) if h == handle));
+    }
+
+    #[test]
+    fn null_backend_drops_sfx() {
+        let (audio_sender, audio_receiver) = mpsc::channel();
+        let (event_sender, event_receiver) = mpsc::channel();
+        let audio_thread = spawn_with_backend(audio_receiver, NullBackend::new(event_sender));
+
+        let handle = play_sfx(&audio_sender, yell_request());
+        drop(audio_sender);
+        audio_thread.join().unwrap();
+
+        let events: Vec<AudioEvent> = event_receiver.try_iter().collect();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], AudioEvent::SfxDropped(h) if h == handle));
+    }
+
+    #[test]
+    fn recording_is_bounded() {
+        let (event_sender, _event_receiver) = mpsc::channel();
+        let recording = AudioRecording::new();
+        let mut backend = RecordingBackend::new(recording.clone(), event_sender);
+        for _ in 0..RECORDING_LIMIT + 10 {
+            backend.handle_command(AudioCommand::PlaySFX(crate::audio::SfxHandle(0), yell_request()));
+        }
+
+        assert_eq!(recording.commands().len(), RECORDING_LIMIT);
+        assert_eq!(recording.sfx_played_count(), RECORDING_LIMIT + 10);
+    }
+}
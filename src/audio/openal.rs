@@ -0,0 +1,602 @@
+use alto::{DeviceObject, Source, SourceState, StaticSource};
+use alto::efx::{AuxEffectSlot, LowpassFilter, ReverbEffect};
+use std::ffi::CString;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::collections::HashMap;
+use crate::routines::send_or_error;
+use super::{AudioBackend, AudioCommand, AudioEvent, Bus, Mixer, MusicState, ReverbPreset, SfxHandle, SfxPriority, SoundEffectRequest, decoder, linearize_gain, music_path};
+use super::bgm::{BgmPlayer, CROSSFADE_TIME};
+use super::spectrum::{SpectrumAnalyzer, WINDOW_SIZE};
+
+//The real audio backend, which plays everything through OpenAL
+
+const STATIC_SOURCE_LIMIT: usize = 64;
+const OCCLUDED_GAIN: f32 = 0.6;             //What's left of a fully occluded sound's volume...
+const OCCLUDED_GAINHF: f32 = 0.1;           //...and of its high frequencies
+const FALLBACK_OCCLUDED_GAIN: f32 = 0.3;    //Without EFX there's no filter, so occlusion can only turn sounds down
+const SPECTRUM_INTERVAL: f32 = 1.0 / 30.0;  //Seconds between spectrum updates sent to the main thread
+const DEVICE_CHECK_INTERVAL: f32 = 1.0;     //Seconds between checks that the output device is still there
+
+//One of the sources sound effects play on
+struct ActiveSoundEffect {
+    handle: Option<SfxHandle>,          //None once the sound has finished or been stopped
+    source: StaticSource,
+    filter: Option<LowpassFilter>,      //None when EFX isn't available
+    bus: Bus,
+    linear_gain: f32,
+    pitch: f32,                         //Multiplied with the bgm pitch
+    occlusion: f32,
+    priority: SfxPriority,
+    started: u64                        //Order the sounds started in, so the oldest sound of a priority gets stolen first
+}
+
+impl ActiveSoundEffect {
+    fn is_playing(&self) -> bool {
+        self.handle.is_some() && self.source.state() == SourceState::Playing
+    }
+
+    //Combines the sound's own volume with its bus and how occluded it is
+    fn update_gain(&mut self, mixer: &Mixer) {
+        let mut gain = linearize_gain(self.linear_gain) * mixer.gain(self.bus);
+        match &mut self.filter {
+            Some(filter) => {
+                if let Err(e) = set_occlusion_filter(&mut self.source, filter, self.occlusion) {
+                    println!("Error setting sfx occlusion filter: {}", e);
+                }
+            }
+            None => { gain *= 1.0 - (1.0 - FALLBACK_OCCLUDED_GAIN) * self.occlusion; }
+        }
+
+        if let Err(e) = self.source.set_gain(gain) {
+            println!("Error setting sfx gain: {}", e);
+        }
+    }
+}
+
+fn set_occlusion_filter(source: &mut StaticSource, filter: &mut LowpassFilter, occlusion: f32) -> alto::AltoResult<()> {
+    filter.set_gain(1.0 - (1.0 - OCCLUDED_GAIN) * occlusion)?;
+    filter.set_gainhf(1.0 - (1.0 - OCCLUDED_GAINHF) * occlusion)?;
+    source.set_direct_filter(filter)
+}
+
+//Reverb goes through the EFX extension, which not every OpenAL implementation has
+struct Reverb {
+    slot: AuxEffectSlot,
+    effect: ReverbEffect
+}
+
+impl Reverb {
+    fn new(ctx: &alto::Context) -> alto::AltoResult<Self> {
+        let mut slot = ctx.new_aux_effect_slot()?;
+        slot.set_gain(0.0)?;
+        let effect = ctx.new_effect::<ReverbEffect>()?;
+        Ok(Reverb {
+            slot,
+            effect
+        })
+    }
+
+    //Parameters are taken from the EFX presets of the same names
+    fn set_preset(&mut self, preset: Option<ReverbPreset>) -> alto::AltoResult<()> {
+        //(density, diffusion, gain, gainhf, decay time, decay hf ratio, reflections gain, reflections delay, late reverb gain, late reverb delay)
+        let params = match preset {
+            Some(ReverbPreset::Cave) => { (1.0, 1.0, 0.3162, 1.0, 2.91, 1.3, 0.5, 0.015, 0.7063, 0.022) }
+            Some(ReverbPreset::OpenField) => { (1.0, 0.21, 0.3162, 0.1, 1.49, 0.5, 0.0585, 0.179, 0.1089, 0.1) }
+            Some(ReverbPreset::Hall) => { (1.0, 1.0, 0.3162, 0.5623, 3.92, 0.7, 0.2427, 0.02, 0.9977, 0.029) }
+            None => {
+                return self.slot.set_gain(0.0);
+            }
+        };
+
+        let effect = &mut self.effect;
+        effect.set_density(params.0)?;
+        effect.set_diffusion(params.1)?;
+        effect.set_gain(params.2)?;
+        effect.set_gainhf(params.3)?;
+        effect.set_decay_time(params.4)?;
+        effect.set_decay_hfratio(params.5)?;
+        effect.set_reflections_gain(params.6)?;
+        effect.set_reflections_delay(params.7)?;
+        effect.set_late_reverb_gain(params.8)?;
+        effect.set_late_reverb_delay(params.9)?;
+        self.slot.set_effect(&self.effect)?;
+        self.slot.set_gain(1.0)
+    }
+}
+
+pub struct OpenAlBackend {
+    alto: alto::Alto,
+    context: alto::Context,
+    device_name: Option<String>,        //None after the device is lost, until another one is opened
+    preferred_device: Option<String>,   //None follows the system's default device
+    device_check_timer: f32,
+    listener_gain: f32,                 //Reapplied when switching devices
+    mixer: Mixer,
+    sfx_buffers: HashMap<String, Arc<alto::Buffer>>,    //Associates sfx paths with their loaded audio data
+    active_sfx: Vec<ActiveSoundEffect>,
+    sfx_started: u64,
+    events: Sender<AudioEvent>,
+    reverb: Option<Reverb>,             //None when EFX isn't available
+    reverb_preset: Option<ReverbPreset>,    //Reapplied when switching devices
+    bgm: BgmPlayer,
+    explore_path: String,               //With dynamic music on, this is the track for exploring and the other states have their own tracks
+    music_state: MusicState,
+    dynamic_music: bool,
+    spectrum: SpectrumAnalyzer,
+    spectrum_timer: f32
+}
+
+impl OpenAlBackend {
+    //Initializing the OpenAL context
+    //This can fail if OpenAL is not installed on the host system
+    pub fn new(master_volume: f32, mixer: Mixer, bgm_path: String, preferred_device: Option<String>, events: Sender<AudioEvent>) -> Result<Self, String> {
+        let alto = match alto::Alto::load_default() {
+            Ok(a) => { a }
+            Err(e) => { return Err(format!("Error initializing OpenAL: {}", e)); }
+        };
+        let (context, device_name) = open_output(&alto, preferred_device.as_deref())?;
+        set_linearized_gain(&context, master_volume);
+        let reverb = create_reverb(&context);
+
+        let active_sfx = Vec::with_capacity(STATIC_SOURCE_LIMIT);
+        /*
+        for i in 0..STATIC_SOURCE_LIMIT {
+            let source = alto_context.new_static_source().unwrap();
+            let sfx = ActiveSoundEffect {
+                id: i,
+                source
+            };
+            active_sfx.push(sfx);
+        }
+        */
+
+        //Start streaming the default bgm
+        let bgm = BgmPlayer::new(&context, &bgm_path);
+
+        send_or_error(&events, AudioEvent::OutputDevices(output_devices(&alto)));
+        send_or_error(&events, AudioEvent::OutputDeviceOpened(device_name.clone()));
+
+        Ok(OpenAlBackend {
+            alto,
+            context,
+            device_name: Some(device_name),
+            preferred_device,
+            device_check_timer: DEVICE_CHECK_INTERVAL,
+            listener_gain: master_volume,
+            mixer,
+            sfx_buffers: HashMap::new(),
+            active_sfx,
+            sfx_started: 0,
+            events,
+            reverb,
+            reverb_preset: None,
+            bgm,
+            explore_path: bgm_path,
+            music_state: MusicState::Explore,
+            dynamic_music: true,
+            spectrum: SpectrumAnalyzer::new(),
+            spectrum_timer: 0.0
+        })
+    }
+
+    //Moves all of the audio over to a newly opened device
+    //Everything made from the old context has to be made again from the new one
+    fn switch_context(&mut self, context: alto::Context, device_name: String) {
+        //Sounds that were playing get cut off, so let the main thread know they're done
+        for sfx in self.active_sfx.drain(..) {
+            if let Some(handle) = sfx.handle {
+                send_or_error(&self.events, AudioEvent::SfxFinished(handle));
+            }
+        }
+
+        //The music starts the current track over, since there's no carrying its position across
+        let bgm_path = match &self.bgm.current {
+            Some(track) => { track.path.clone() }
+            None => { String::from(music_path(self.music_state, &self.explore_path)) }
+        };
+        let pitch = self.bgm.pitch();
+        self.bgm = BgmPlayer::new(&context, &bgm_path);
+        self.bgm.set_pitch(pitch);
+
+        self.context = context;
+        set_linearized_gain(&self.context, self.listener_gain);
+        self.reverb = create_reverb(&self.context);
+        self.set_reverb(self.reverb_preset);
+
+        let paths: Vec<String> = self.sfx_buffers.keys().cloned().collect();
+        self.sfx_buffers.clear();
+        for path in paths {
+            self.load_sfx(path);
+        }
+
+        println!("Playing audio on \"{}\"", device_name);
+        send_or_error(&self.events, AudioEvent::OutputDeviceOpened(device_name.clone()));
+        send_or_error(&self.events, AudioEvent::OutputDevices(output_devices(&self.alto)));
+        self.device_name = Some(device_name);
+    }
+
+    //Notices when the device goes away, and gets back to the preferred device once it's available again
+    fn check_device(&mut self) {
+        //Without the ALC_EXT_disconnect extension there's no telling, so assume the device is still there
+        let connected = self.context.device().connected().unwrap_or(true);
+        if !connected {
+            if let Some(name) = self.device_name.take() {
+                println!("Lost audio device \"{}\"", name);
+                send_or_error(&self.events, AudioEvent::OutputDeviceLost(name));
+            }
+
+            //If nothing can be opened yet, the next check tries again
+            if let Ok((context, name)) = open_output(&self.alto, self.preferred_device.as_deref()) {
+                self.switch_context(context, name);
+            }
+            return;
+        }
+
+        //Switch back once the preferred device shows up again, e.g. when headphones are plugged back in
+        let preferred = match (&self.preferred_device, &self.device_name) {
+            (Some(preferred), Some(current)) if preferred != current => { preferred.clone() }
+            _ => { return; }
+        };
+        if output_devices(&self.alto).contains(&preferred) {
+            if let Ok((context, name)) = open_output(&self.alto, Some(&preferred)) {
+                if name == preferred {
+                    self.switch_context(context, name);
+                }
+            }
+        }
+    }
+
+    fn load_sfx(&mut self, path: String) {
+        //Sound effects are downmixed to mono so that OpenAL can position them
+        match decoder::decode_mono(&path) {
+            Ok((data, freq)) => {
+                let samples: Vec<_> = data.into_iter().map(|sample| alto::Mono { center: sample }).collect();
+                match self.context.new_buffer(samples, freq) {
+                    Ok(b) => { self.sfx_buffers.insert(path, Arc::new(b)); }
+                    Err(e) => { println!("Error creating buffer for {}: {}", path, e); }
+                }
+            }
+            Err(e) => { println!("{}", e); }
+        }
+    }
+
+    fn set_reverb(&mut self, preset: Option<ReverbPreset>) {
+        self.reverb_preset = preset;
+        if let Some(reverb) = &mut self.reverb {
+            if let Err(e) = reverb.set_preset(preset) {
+                println!("Error setting reverb: {}", e);
+            }
+        }
+    }
+
+    //Pushes the mixer's bus gains out to every sound effect
+    //The bgm picks up the music bus gain on its own every update
+    fn apply_mixer(&mut self) {
+        for sfx in &mut self.active_sfx {
+            sfx.update_gain(&self.mixer);
+        }
+    }
+
+    fn find_sfx(&mut self, handle: SfxHandle) -> Option<&mut ActiveSoundEffect> {
+        self.active_sfx.iter_mut().find(|sfx| sfx.handle == Some(handle))
+    }
+
+    //Picks the source a new sound should play on
+    //Free sources come first, then new sources while under the limit, and finally the least important sound playing
+    fn choose_slot(&mut self, priority: SfxPriority) -> Option<usize> {
+        if let Some(i) = self.active_sfx.iter().position(|sfx| !sfx.is_playing()) {
+            //The sound that was here may have finished since the last update
+            if let Some(handle) = self.active_sfx[i].handle {
+                send_or_error(&self.events, AudioEvent::SfxFinished(handle));
+            }
+            return Some(i);
+        }
+
+        if self.active_sfx.len() < STATIC_SOURCE_LIMIT {
+            match self.context.new_static_source() {
+                Ok(mut source) => {
+                    //Every source feeds the reverb, which is silent outside of reverb zones
+                    let mut filter = None;
+                    if let Some(reverb) = &mut self.reverb {
+                        if let Err(e) = source.set_aux_send(0, &mut reverb.slot) {
+                            println!("Error connecting sfx source to reverb: {}", e);
+                        }
+                        filter = self.context.new_filter::<LowpassFilter>().ok();
+                    }
+
+                    self.active_sfx.push(ActiveSoundEffect {
+                        handle: None,
+                        source,
+                        filter,
+                        bus: Bus::Sfx,
+                        linear_gain: 0.0,
+                        pitch: 1.0,
+                        occlusion: 0.0,
+                        priority: SfxPriority::Low,
+                        started: 0
+                    });
+                    return Some(self.active_sfx.len() - 1);
+                }
+                Err(e) => { println!("Error creating sfx source: {}", e); }
+            }
+        }
+
+        let (victim, sfx) = self.active_sfx.iter().enumerate().min_by_key(|(_, sfx)| (sfx.priority, sfx.started))?;
+        if sfx.priority > priority {
+            return None;
+        }
+        if let Some(handle) = sfx.handle {
+            send_or_error(&self.events, AudioEvent::SfxStolen(handle));
+        }
+        Some(victim)
+    }
+
+    fn play_sfx(&mut self, handle: SfxHandle, request: SoundEffectRequest) {
+        let buffer = match self.sfx_buffers.get(&request.path) {
+            Some(b) => { b.clone() }
+            None => {
+                println!("{} hasn't been loaded yet", request.path);
+                send_or_error(&self.events, AudioEvent::SfxDropped(handle));
+                return;
+            }
+        };
+
+        let slot = match self.choose_slot(request.priority) {
+            Some(i) => { i }
+            None => {
+                println!("No available sfx slot to play {}", request.path);
+                send_or_error(&self.events, AudioEvent::SfxDropped(handle));
+                return;
+            }
+        };
+
+        let pitch = self.bgm.pitch();
+        let sfx = &mut self.active_sfx[slot];
+        sfx.source.stop();
+        sfx.handle = Some(handle);
+        sfx.bus = request.bus;
+        sfx.linear_gain = request.linear_gain;
+        sfx.pitch = 1.0;
+        sfx.occlusion = 0.0;
+        sfx.priority = request.priority;
+        sfx.started = self.sfx_started;
+        self.sfx_started += 1;
+
+        sfx.update_gain(&self.mixer);
+        if let Err(e) = set_up_source(&mut sfx.source, buffer, &request, pitch) {
+            println!("Error setting up sfx source for {}: {}", request.path, e);
+            sfx.handle = None;
+            send_or_error(&self.events, AudioEvent::SfxDropped(handle));
+            return;
+        }
+        sfx.source.play();
+        send_or_error(&self.events, AudioEvent::SfxStarted(handle, slot));
+    }
+}
+
+fn set_up_source(source: &mut StaticSource, buffer: Arc<alto::Buffer>, request: &SoundEffectRequest, pitch: f32) -> alto::AltoResult<()> {
+    source.set_buffer(buffer)?;
+    source.set_position(request.position)?;
+    source.set_velocity([0.0, 0.0, 0.0])?;
+    source.set_pitch(pitch)?;
+    source.set_looping(request.looping);
+    Ok(())
+}
+
+impl AudioBackend for OpenAlBackend {
+    fn handle_command(&mut self, command: AudioCommand) {
+        match command {
+            AudioCommand::SetListenerPosition(pos) => {
+                if let Err(e) = self.context.set_position(pos) {
+                    println!("Error setting listener position: {}", e);
+                }
+            }
+            AudioCommand::SetListenerVelocity(vel) => {
+                if let Err(e) = self.context.set_velocity(vel) {
+                    println!("Error setting listener velocity: {}", e);
+                }
+            }
+            AudioCommand::SetListenerOrientation(ori) => {
+                if let Err(e) = self.context.set_orientation(ori) {
+                    println!("Error setting listener orientation: {}", e);
+                }
+            }
+            AudioCommand::SetListenerGain(volume) => {
+                self.listener_gain = volume;
+                set_linearized_gain(&self.context, volume);
+            }
+            AudioCommand::SetBusVolume(bus, volume) => {
+                self.mixer.bus_mut(bus).volume = volume;
+                self.apply_mixer();
+            }
+            AudioCommand::SetBusMuted(bus, muted) => {
+                self.mixer.bus_mut(bus).muted = muted;
+                self.apply_mixer();
+            }
+            AudioCommand::SetBusSoloed(bus, soloed) => {
+                self.mixer.bus_mut(bus).soloed = soloed;
+                self.apply_mixer();
+            }
+            AudioCommand::SetPitchShift(shift) => { self.bgm.set_pitch(shift); }
+            AudioCommand::SetMusicState(state) => {
+                self.music_state = state;
+                if self.dynamic_music {
+                    self.bgm.transition_to(&self.context, music_path(self.music_state, &self.explore_path));
+                }
+            }
+            AudioCommand::SetDynamicMusic(enabled) => {
+                self.dynamic_music = enabled;
+                let path = if self.dynamic_music { music_path(self.music_state, &self.explore_path) } else { self.explore_path.as_str() };
+                self.bgm.transition_to(&self.context, path);
+            }
+            AudioCommand::LoadSFX(path) => { self.load_sfx(path); }
+            AudioCommand::PlaySFX(handle, request) => { self.play_sfx(handle, request); }
+            AudioCommand::StopSFX(handle) => {
+                if let Some(sfx) = self.find_sfx(handle) {
+                    sfx.source.stop();
+                    sfx.handle = None;
+                    send_or_error(&self.events, AudioEvent::SfxFinished(handle));
+                }
+            }
+            AudioCommand::SetSFXPosition(handle, position) => {
+                if let Some(sfx) = self.find_sfx(handle) {
+                    if let Err(e) = sfx.source.set_position(position) {
+                        println!("Error setting sfx position: {}", e);
+                    }
+                }
+            }
+            AudioCommand::SetSFXVelocity(handle, velocity) => {
+                if let Some(sfx) = self.find_sfx(handle) {
+                    if let Err(e) = sfx.source.set_velocity(velocity) {
+                        println!("Error setting sfx velocity: {}", e);
+                    }
+                }
+            }
+            AudioCommand::SetSFXGain(handle, linear_gain) => {
+                let mixer = &self.mixer;
+                if let Some(sfx) = self.active_sfx.iter_mut().find(|sfx| sfx.handle == Some(handle)) {
+                    sfx.linear_gain = linear_gain;
+                    sfx.update_gain(mixer);
+                }
+            }
+            AudioCommand::SetSFXOcclusion(handle, occlusion) => {
+                let mixer = &self.mixer;
+                if let Some(sfx) = self.active_sfx.iter_mut().find(|sfx| sfx.handle == Some(handle)) {
+                    sfx.occlusion = occlusion;
+                    sfx.update_gain(mixer);
+                }
+            }
+            AudioCommand::SetReverb(preset) => { self.set_reverb(preset); }
+            AudioCommand::RefreshOutputDevices => {
+                send_or_error(&self.events, AudioEvent::OutputDevices(output_devices(&self.alto)));
+            }
+            AudioCommand::SetOutputDevice(preferred) => {
+                self.preferred_device = preferred;
+                match open_output(&self.alto, self.preferred_device.as_deref()) {
+                    Ok((context, name)) => { self.switch_context(context, name); }
+                    Err(e) => { println!("Error switching audio device: {}", e); }
+                }
+            }
+            AudioCommand::SetSFXPitch(handle, pitch) => {
+                //Applied along with the bgm pitch on the next update
+                if let Some(sfx) = self.find_sfx(handle) {
+                    sfx.pitch = pitch;
+                }
+            }
+            AudioCommand::SelectNewBGM => {
+                //The file dialog blocks this thread, so the bgm has to be paused to keep it from running out of audio
+                for track in self.bgm.tracks_mut() {
+                    track.pause();
+                }
+                let choice = tfd::open_file_dialog("Choose bgm", "music/", Some((&["*.mp3", "*.wav", "*.ogg"], "Audio files (*.mp3, *.wav, *.ogg)")));
+                for track in self.bgm.tracks_mut() {
+                    track.resume();
+                }
+                if let Some(path) = choice {
+                    if !self.dynamic_music || self.music_state == MusicState::Explore {
+                        self.bgm.crossfade_to(&self.context, &path, CROSSFADE_TIME);
+                    }
+                    self.explore_path = path;
+                }
+            }
+            AudioCommand::RestartBGM => {
+                if let Some(track) = &mut self.bgm.current {
+                    track.restart();
+                }
+            }
+            AudioCommand::PlayPause => {
+                if let Some(track) = &mut self.bgm.current {
+                    track.play_pause();
+                }
+            }
+        }
+    }
+
+    fn update(&mut self, delta_time: f32) {
+        self.device_check_timer -= delta_time;
+        if self.device_check_timer <= 0.0 {
+            self.device_check_timer = DEVICE_CHECK_INTERVAL;
+            self.check_device();
+        }
+
+        self.bgm.update(&self.context, delta_time, self.mixer.gain(Bus::Music));
+
+        //Analyze whatever part of the current track is audible right now
+        self.spectrum_timer -= delta_time;
+        if self.spectrum_timer <= 0.0 {
+            let elapsed = SPECTRUM_INTERVAL - self.spectrum_timer;
+            self.spectrum_timer = SPECTRUM_INTERVAL;
+            let window = self.bgm.current.as_ref().and_then(|track| track.playing_window(WINDOW_SIZE));
+            let bands = self.spectrum.analyze(window.as_ref().map(|(samples, rate)| (samples.as_slice(), *rate)), elapsed);
+            send_or_error(&self.events, AudioEvent::Spectrum(bands));
+        }
+
+        //Report finished sounds, and match sfx pitches with the bgm pitch
+        let pitch = self.bgm.pitch();
+        for sfx in &mut self.active_sfx {
+            let handle = match sfx.handle {
+                Some(h) => { h }
+                None => { continue; }
+            };
+
+            let source = &mut sfx.source;
+            if source.state() == SourceState::Playing {
+                if let Err(e) = source.set_pitch(sfx.pitch * pitch) {
+                    println!("Error setting audio source pitch: {}", e);
+                }
+            } else {
+                sfx.handle = None;
+                send_or_error(&self.events, AudioEvent::SfxFinished(handle));
+            }
+        }
+    }
+}
+
+//Opens the preferred output device, falling back to the default one when it can't be opened, e.g. because it's been unplugged
+//Returns the new context along with the name of the device it ended up on
+fn open_output(alto: &alto::Alto, preferred: Option<&str>) -> Result<(alto::Context, String), String> {
+    if let Some(name) = preferred {
+        let opened = match CString::new(name) {
+            Ok(spec) => { alto.open(Some(&spec)).and_then(|device| device.new_context(None)).map_err(|e| format!("{}", e)) }
+            Err(e) => { Err(format!("{}", e)) }
+        };
+        match opened {
+            Ok(context) => { return Ok((context, String::from(name))); }
+            Err(e) => { println!("Couldn't open audio device \"{}\", using the default device instead: {}", name, e); }
+        }
+    }
+
+    let device_name = match alto.default_output() {
+        Some(s) => { s }
+        None => { return Err(String::from("No default audio output device found")); }
+    };
+    let device = match alto.open(Some(&device_name)) {
+        Ok(dev) => { dev }
+        Err(e) => { return Err(format!("Error opening default audio device: {}", e)); }
+    };
+    let context = match device.new_context(None) {
+        Ok(ctxt) => { ctxt }
+        Err(e) => { return Err(format!("Error creating OpenAL context: {}", e)); }
+    };
+    Ok((context, device_name.to_string_lossy().into_owned()))
+}
+
+fn output_devices(alto: &alto::Alto) -> Vec<String> {
+    alto.enumerate_outputs().iter().map(|name| name.to_string_lossy().into_owned()).collect()
+}
+
+//Without EFX the game still works, but occlusion can only turn sounds down and there's no reverb
+fn create_reverb(ctx: &alto::Context) -> Option<Reverb> {
+    match Reverb::new(ctx) {
+        Ok(r) => { Some(r) }
+        Err(e) => {
+            println!("EFX is unavailable, so audio will play without reverb or occlusion filtering: {}", e);
+            None
+        }
+    }
+}
+
+fn set_linearized_gain(ctxt: &alto::Context, linear_gain: f32) {
+    ctxt.set_gain(linearize_gain(linear_gain)).unwrap();
+}
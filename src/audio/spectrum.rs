@@ -0,0 +1,160 @@
+//Splits the music into a few frequency bands so that visuals can react to it
+//The audio thread analyzes whatever part of the bgm is currently playing and sends the band levels to the main thread
+
+pub const BAND_COUNT: usize = 4;
+pub const BAND_NAMES: [&'static str; BAND_COUNT] = ["Bass", "Low mids", "High mids", "Treble"];
+const BAND_EDGES: [f32; BAND_COUNT + 1] = [20.0, 250.0, 2000.0, 6000.0, 16000.0];     //In Hz
+
+pub const WINDOW_SIZE: usize = 1024;        //Samples per analysis. Must be a power of two
+const FLOOR_DB: f32 = -60.0;                //Band energies at or below this are reported as 0
+const ATTACK_RATE: f32 = 30.0;              //How fast levels rise and fall, per second
+const RELEASE_RATE: f32 = 4.0;
+
+pub type Bands = [f32; BAND_COUNT];
+
+pub struct SpectrumAnalyzer {
+    levels: Bands,
+    hann: Vec<f32>,
+    hann_energy: f32            //Sum of the squared window, for turning bin power back into amplitude
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        let hann: Vec<f32> = (0..WINDOW_SIZE).map(|i| {
+            0.5 - 0.5 * f32::cos(glm::two_pi::<f32>() * i as f32 / (WINDOW_SIZE - 1) as f32)
+        }).collect();
+        let hann_energy = hann.iter().map(|w| w * w).sum();
+
+        SpectrumAnalyzer {
+            levels: [0.0; BAND_COUNT],
+            hann,
+            hann_energy
+        }
+    }
+
+    //Returns each band's level from 0 to 1
+    //Passing None lets the levels fall back to silence, e.g. while the music is paused
+    pub fn analyze(&mut self, window: Option<(&[f32], i32)>, delta_time: f32) -> Bands {
+        let targets = match window {
+            Some((samples, sample_rate)) if samples.len() >= WINDOW_SIZE && sample_rate > 0 => {
+                self.band_levels(&samples[..WINDOW_SIZE], sample_rate)
+            }
+            _ => { [0.0; BAND_COUNT] }
+        };
+
+        //Rise quickly so beats land on time, but fall slowly so the visuals don't strobe
+        for i in 0..BAND_COUNT {
+            let rate = if targets[i] > self.levels[i] { ATTACK_RATE } else { RELEASE_RATE };
+            let t = f32::min(rate * delta_time, 1.0);
+            self.levels[i] += (targets[i] - self.levels[i]) * t;
+        }
+        self.levels
+    }
+
+    fn band_levels(&self, samples: &[f32], sample_rate: i32) -> Bands {
+        let mut re: Vec<f32> = samples.iter().zip(self.hann.iter()).map(|(s, w)| s * w).collect();
+        let mut im = vec![0.0; WINDOW_SIZE];
+        fft(&mut re, &mut im);
+
+        //Total power of the bins in each band, skipping the mirrored upper half of the spectrum
+        //Summing rather than averaging keeps wide bands from reading quieter than narrow ones
+        let bin_width = sample_rate as f32 / WINDOW_SIZE as f32;
+        let mut levels = [0.0; BAND_COUNT];
+        for band in 0..BAND_COUNT {
+            let first = usize::max((BAND_EDGES[band] / bin_width) as usize, 1);
+            let last = usize::min((BAND_EDGES[band + 1] / bin_width) as usize, WINDOW_SIZE / 2);
+            if first >= last {
+                continue;
+            }
+
+            let mut power = 0.0;
+            for bin in first..last {
+                power += re[bin] * re[bin] + im[bin] * im[bin];
+            }
+
+            //Normalized by the window's energy, so a full scale sine inside the band reads as 0dB
+            let amplitude = f32::sqrt(4.0 * power / (WINDOW_SIZE as f32 * self.hann_energy));
+            let db = 20.0 * f32::log10(f32::max(amplitude, 1.0e-6));
+            levels[band] = f32::clamp(1.0 - db / FLOOR_DB, 0.0, 1.0);
+        }
+        levels
+    }
+}
+
+//In-place iterative radix-2 FFT
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    //Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -glm::two_pi::<f32>() / length as f32;
+        let (w_re, w_im) = (f32::cos(angle), f32::sin(angle));
+        for start in (0..n).step_by(length) {
+            let (mut cur_re, mut cur_im) = (1.0, 0.0);
+            for k in 0..length / 2 {
+                let a = start + k;
+                let b = a + length / 2;
+                let t_re = re[b] * cur_re - im[b] * cur_im;
+                let t_im = re[b] * cur_im + im[b] * cur_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+
+                let next_re = cur_re * w_re - cur_im * w_im;
+                cur_im = cur_re * w_im + cur_im * w_re;
+                cur_re = next_re;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+//Which band drives a scene parameter, and how hard
+#[derive(Clone, Copy)]
+pub struct SpectrumBinding {
+    pub band: Option<usize>,
+    pub strength: f32
+}
+
+impl SpectrumBinding {
+    //How much the parameter should be pushed, from 0 up to strength
+    pub fn amount(&self, bands: &Bands) -> f32 {
+        match self.band {
+            Some(b) => { bands[b] * self.strength }
+            None => { 0.0 }
+        }
+    }
+}
+
+//The scene parameters that can follow the music
+pub struct SpectrumBindings {
+    pub flicker_amplitude: SpectrumBinding,
+    pub sun_size: SpectrumBinding,
+    pub glitch_intensity: SpectrumBinding
+}
+
+impl SpectrumBindings {
+    pub fn new() -> Self {
+        SpectrumBindings {
+            flicker_amplitude: SpectrumBinding { band: None, strength: 2.0 },
+            sun_size: SpectrumBinding { band: None, strength: 1.0 },
+            glitch_intensity: SpectrumBinding { band: None, strength: 1.0 }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs::read_dir;
+use std::sync::mpsc::Sender;
+use crate::damage::{DamageSource, TotoroEvent};
+use crate::gamestate::Totoro;
+use crate::rng::{GameRng, RngStream};
+use crate::routines::{send_or_error, vec_to_array};
+use crate::structs::EntityList;
+use super::{AudioCommand, Bus, SfxHandle, SfxPriority, SoundEffectRequest};
+use super::attach::{SoundAnchor, SoundAttachments};
+use super::environment::SoundOcclusion;
+
+//The sounds Totoros make, and how they react to what happens to them
+
+pub struct TotoroVoices {
+    yell_paths: Vec<String>
+}
+
+impl TotoroVoices {
+    //Preloads every supported sound in the yell directory
+    pub fn load(audio_sender: &Sender<AudioCommand>, yell_dir: &str) -> Self {
+        let yell_paths = match read_dir(yell_dir) {
+            Ok(iter) => {
+                let mut paths = Vec::new();
+                for entry in iter {
+                    match entry {
+                        Ok(ent) => {
+                            let name = format!("{}/{}", yell_dir, ent.file_name().into_string().unwrap());
+                            if super::decoder::is_supported(&name) {
+                                paths.push(name.clone());
+                                send_or_error(audio_sender, AudioCommand::LoadSFX(name));
+                            }
+                        }
+                        Err(e) => {
+                            tfd::message_box_ok("Audio error", &format!("Error reading sfx entry: {}", e), tfd::MessageBoxIcon::Error);
+                        }
+                    }
+                }
+                paths
+            }
+            Err(e) => {
+                tfd::message_box_ok("Audio error", &format!("Error reading sfx directory: {}", e), tfd::MessageBoxIcon::Error);
+                Vec::new()
+            }
+        };
+
+        TotoroVoices {
+            yell_paths
+        }
+    }
+
+    //Plays a random yell, returning None if there are no yells to play
+    pub fn yell(&self, audio_sender: &Sender<AudioCommand>, rng: &mut GameRng, position: glm::TVec3<f32>) -> Option<SfxHandle> {
+        if self.yell_paths.len() == 0 {
+            return None;
+        }
+
+        let path = self.yell_paths[rng.random_index(RngStream::Audio, self.yell_paths.len())].clone();
+        let yell_req = SoundEffectRequest {
+            path,
+            position: vec_to_array(position),
+            linear_gain: 200.0,
+            looping: false,
+            bus: Bus::Voice,
+            priority: SfxPriority::Normal
+        };
+        Some(super::play_sfx(audio_sender, yell_req))
+    }
+
+    //Audio's reaction to a TotoroEvent
+    pub fn react(&self, event: &TotoroEvent, audio_sender: &Sender<AudioCommand>, rng: &mut GameRng, totoros: &EntityList<Totoro>, attachments: &mut SoundAttachments, occlusion: &mut SoundOcclusion) {
+        match event {
+            TotoroEvent::Startled { idx, position } => {
+                if let Some(handle) = self.yell(audio_sender, rng, *position) {
+                    occlusion.track(handle, vec_to_array(*position));
+                    let totoro_ref = totoros.reference(*idx);
+                    if totoros.get_by_ref(totoro_ref).is_some() {
+                        attachments.attach(handle, SoundAnchor::Totoro(totoro_ref));
+                    }
+                }
+            }
+            TotoroEvent::Damaged { idx, source: DamageSource::Fall, position, .. } => {
+                if let Some(handle) = self.yell(audio_sender, rng, *position) {
+                    occlusion.track(handle, vec_to_array(*position));
+
+                    //A fatal fall leaves the yell where the Totoro landed
+                    let totoro_ref = totoros.reference(*idx);
+                    if totoros.get_by_ref(totoro_ref).is_some() {
+                        attachments.attach(handle, SoundAnchor::Totoro(totoro_ref));
+                    }
+                }
+            }
+            TotoroEvent::Damaged { .. } => {}       //Continuous damage already has its own feedback (e.g. the drowning sfx)
+            TotoroEvent::Died { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use crate::audio::spawn_with_backend;
+    use crate::audio::null::{AudioRecording, RecordingBackend};
+    use crate::damage::TotoroEventQueue;
+    use crate::gamestate::TotoroState;
+    use super::*;
+
+    #[test]
+    fn startled_totoro_yells() {
+        let (audio_sender, audio_receiver) = mpsc::channel();
+        let (event_sender, _event_receiver) = mpsc::channel();
+        let recording = AudioRecording::new();
+        let audio_thread = spawn_with_backend(audio_receiver, RecordingBackend::new(recording.clone(), event_sender));
+        let voices = TotoroVoices::load(&audio_sender, "sfx/totoro/yells");
+
+        let mut rng = GameRng::new(0);
+        let mut totoros = EntityList::new();
+        let idx = totoros.insert(Totoro::new(glm::vec3(0.0, 0.0, 0.0), 1.0, 0.0, &mut rng));
+        let mut events = TotoroEventQueue::new();
+        let audio_events = events.subscribe();
+
+        let totoro = totoros.get_mut_element(idx).unwrap();
+        totoro.state = TotoroState::Startled;
+        totoro.startle(idx, &glm::vec3(5.0, 0.0, 0.0), 1.0, &mut events);
+        assert!(matches!(totoro.state, TotoroState::PrePanicking));
+
+        let mut attachments = SoundAttachments::new();
+        let mut occlusion = SoundOcclusion::new();
+        for event in events.drain(audio_events) {
+            voices.react(&event, &audio_sender, &mut rng, &totoros, &mut attachments, &mut occlusion);
+        }
+        drop(audio_sender);
+        audio_thread.join().unwrap();
+
+        let played = recording.played_sfx();
+        assert_eq!(played.len(), 1);
+        assert_eq!(played[0].1.bus, Bus::Voice);
+        assert!(played[0].1.path.starts_with("sfx/totoro/yells/"));
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+
+//Native climbing: either hand can grab onto grabbable terrain, and letting go throws the player using the hands' recent motion
+
+pub const GRIP_RADIUS: f32 = 0.08;                  //How close a hand must be to a triangle to grab onto it
+pub const GRIP_THRESHOLD: f32 = 0.5;                //Squeeze value past which the hand closes
+const HISTORY_LENGTH: usize = 10;                   //Frames of hand motion used to compute the throw velocity
+const THROW_MULTIPLIER: f32 = 1.5;
+
+//What a hand wants to do this frame
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GripRequest {
+    pub held: bool,
+    pub any_surface: bool                           //Allows grabbing triangles that aren't flagged as grabbable
+}
+
+//Recent tracking space positions of one hand
+pub struct HandHistory {
+    samples: VecDeque<(glm::TVec3<f32>, f32)>       //Position and the delta time of the frame it was recorded on
+}
+
+impl HandHistory {
+    pub fn new() -> Self {
+        HandHistory {
+            samples: VecDeque::with_capacity(HISTORY_LENGTH)
+        }
+    }
+
+    pub fn record(&mut self, position: Option<glm::TVec3<f32>>, delta_time: f32) {
+        match position {
+            Some(p) => {
+                if self.samples.len() == HISTORY_LENGTH {
+                    self.samples.pop_front();
+                }
+                self.samples.push_back((p, delta_time));
+            }
+            None => {
+                //Lost tracking makes the history meaningless
+                self.samples.clear();
+            }
+        }
+    }
+
+    //Average velocity of the hand over the recorded frames
+    pub fn velocity(&self) -> glm::TVec3<f32> {
+        if self.samples.len() < 2 {
+            return glm::zero();
+        }
+
+        let mut elapsed = 0.0;
+        for i in 1..self.samples.len() {
+            elapsed += self.samples[i].1;
+        }
+        if elapsed <= 0.0 {
+            return glm::zero();
+        }
+
+        let first = self.samples[0].0;
+        let last = self.samples[self.samples.len() - 1].0;
+        (last - first) / elapsed
+    }
+
+    //The player's body moves opposite to a hand that is holding onto something
+    pub fn throw_velocity(&self) -> glm::TVec3<f32> {
+        -self.velocity() * THROW_MULTIPLIER
+    }
+}
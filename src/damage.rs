@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+
+//Damage model for Totoros and the player
+//Totoro damage is reported through TotoroEvents so that audio and UI can react without the AI code knowing about them
+
+pub const REGENERATION_RATE: f32 = 5.0;             //Health per second while Relaxed
+pub const SAFE_IMPACT_SPEED: f32 = 8.0;             //Landing faster than this (m/s) causes fall damage
+pub const FALL_DAMAGE_PER_SPEED: f32 = 12.0;        //Health lost per m/s of impact speed above SAFE_IMPACT_SPEED
+pub const MAX_BREATH: f32 = 3.0;                    //Seconds a Totoro can stay submerged before it starts drowning
+pub const BREATH_RECOVERY_RATE: f32 = 1.0;          //Seconds of breath regained per second above water
+pub const DROWNING_DAMAGE_RATE: f32 = 20.0;         //Health per second once out of breath
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DamageSource {
+    Water,
+    Fall,
+    Drowning
+}
+
+impl DamageSource {
+    //Bigger Totoros resist damage better, though size helps less against falls than against water
+    pub fn size_multiplier(&self, scale: f32) -> f32 {
+        let exponent = match self {
+            DamageSource::Water => { 1.0 }
+            DamageSource::Fall => { 0.5 }
+            DamageSource::Drowning => { 1.0 }
+        };
+        1.0 / f32::powf(f32::max(scale, 0.1), exponent)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TotoroEvent {
+    Startled {
+        idx: usize,
+        position: glm::TVec3<f32>
+    },
+    Damaged {
+        idx: usize,
+        source: DamageSource,
+        amount: f32,
+        position: glm::TVec3<f32>
+    },
+    Died {
+        idx: usize,
+        cause: Option<DamageSource>,            //None when the Totoro left the level instead of being killed
+        position: glm::TVec3<f32>
+    }
+}
+
+impl TotoroEvent {
+    //What the event log shows for this event, if anything
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            TotoroEvent::Startled { .. } => { None }
+            TotoroEvent::Damaged { idx, source: DamageSource::Fall, amount, .. } => { Some(format!("Totoro #{} took {:.1} fall damage", idx, amount)) }
+            TotoroEvent::Damaged { .. } => { None }
+            TotoroEvent::Died { idx, cause, .. } => {
+                let message = match cause {
+                    Some(DamageSource::Water) => { format!("Totoro #{} was drowned by the water cannon", idx) }
+                    Some(DamageSource::Fall) => { format!("Totoro #{} died from a fall", idx) }
+                    Some(DamageSource::Drowning) => { format!("Totoro #{} drowned", idx) }
+                    None => { format!("Totoro #{} left the level", idx) }
+                };
+                Some(message)
+            }
+        }
+    }
+}
+
+//Identifies one reader of a TotoroEventQueue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TotoroSubscriber(usize);
+
+//Delivers every TotoroEvent to each subscriber, which reads them whenever it's ready
+//Subscribers don't know about each other, so adding a reaction doesn't mean touching the code that raises events
+pub struct TotoroEventQueue {
+    pending: Vec<VecDeque<TotoroEvent>>     //Indexed by subscriber
+}
+
+impl TotoroEventQueue {
+    pub fn new() -> Self {
+        TotoroEventQueue {
+            pending: Vec::new()
+        }
+    }
+
+    pub fn subscribe(&mut self) -> TotoroSubscriber {
+        self.pending.push(VecDeque::new());
+        TotoroSubscriber(self.pending.len() - 1)
+    }
+
+    pub fn push(&mut self, event: TotoroEvent) {
+        for queue in self.pending.iter_mut() {
+            queue.push_back(event);
+        }
+    }
+
+    //Takes every event the subscriber hasn't read yet, oldest first
+    pub fn drain(&mut self, subscriber: TotoroSubscriber) -> Vec<TotoroEvent> {
+        self.pending[subscriber.0].drain(..).collect()
+    }
+}
+
+pub const PLAYER_MAX_HEALTH: f32 = 100.0;
+pub const PLAYER_SAFE_IMPACT_SPEED: f32 = 12.0;     //The player can survive harder landings than a Totoro
+pub const PLAYER_FALL_DAMAGE_PER_SPEED: f32 = 8.0;
+pub const PLAYER_REGENERATION_RATE: f32 = 10.0;     //Health per second
+pub const PLAYER_REGENERATION_DELAY: f32 = 4.0;     //Seconds after taking damage before health starts coming back
+pub const DEATH_FADE_TIME: f32 = 1.5;               //Seconds to fade to black after dying
+pub const RESPAWN_FADE_TIME: f32 = 0.75;            //Seconds to fade back in after respawning
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LifeState {
+    Alive,
+    Dying(f32),                                     //Seconds since death
+    Respawning(f32)                                 //Seconds since respawn
+}
+
+impl LifeState {
+    //How black the player's view is, from zero to one
+    pub fn fade(&self) -> f32 {
+        match self {
+            LifeState::Alive => { 0.0 }
+            LifeState::Dying(t) => { f32::min(t / DEATH_FADE_TIME, 1.0) }
+            LifeState::Respawning(t) => { f32::max(1.0 - t / RESPAWN_FADE_TIME, 0.0) }
+        }
+    }
+}
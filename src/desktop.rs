@@ -0,0 +1,180 @@
+use glfw::{CursorMode, Key, Window};
+use crate::gamepad::GamepadControls;
+use crate::structs::Camera;
+
+//First-person controls for playing without a headset
+//Keyboard and mouse input is turned into the same action states and poses the OpenXR runtime would report,
+//so the gameplay code can't tell the difference between desktop mode and VR
+
+const EYE_HEIGHT: f32 = 1.7;                        //Height of the virtual head above the tracking space floor
+const HAND_FORWARD: f32 = 0.35;                     //Offsets of the virtual hands from the head
+const HAND_SIDE: f32 = 0.2;
+const HAND_DROP: f32 = 0.3;
+const MIN_PITCH: f32 = 0.05;                        //Keeps the view from pointing straight up or down, where the movement direction is undefined
+
+//Control values for one frame
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Controls {
+    move_stick: [f32; 2],
+    triggers: [f32; 2],
+    switches: [bool; 2],
+    grab: f32,
+    respawn: bool
+}
+
+//Indices into DesktopPlayer::movement_keys
+const FORWARD: usize = 0;
+const BACK: usize = 1;
+const RIGHT: usize = 2;
+const LEFT: usize = 3;
+
+pub struct DesktopPlayer {
+    pub enabled: bool,
+    held: Controls,                                 //Updated as window events arrive
+    movement_keys: [bool; 4],                       //W, S, D and A, which held.move_stick is derived from
+    current: Controls,
+    last: Controls
+}
+
+impl DesktopPlayer {
+    pub fn new() -> Self {
+        DesktopPlayer {
+            enabled: false,
+            held: Controls::default(),
+            movement_keys: [false; 4],
+            current: Controls::default(),
+            last: Controls::default()
+        }
+    }
+
+    //Desktop mode steals the mouse for looking around, so the freecam stops wherever it was
+    pub fn set_enabled(&mut self, enabled: bool, window: &mut Window, camera: &mut Camera) {
+        self.enabled = enabled;
+        self.release_all();
+        camera.view_space_velocity = glm::zero();
+        camera.using_mouselook = enabled;
+        if enabled {
+            window.set_cursor_mode(CursorMode::Hidden);
+        } else {
+            window.set_cursor_mode(CursorMode::Normal);
+        }
+    }
+
+    //Returns true if the key is one of the desktop controls
+    pub fn handle_key(&mut self, key: Key, pressed: bool) -> bool {
+        let value = if pressed { 1.0 } else { 0.0 };
+        match key {
+            Key::W => { self.set_movement_key(FORWARD, pressed); }
+            Key::S => { self.set_movement_key(BACK, pressed); }
+            Key::D => { self.set_movement_key(RIGHT, pressed); }
+            Key::A => { self.set_movement_key(LEFT, pressed); }
+            Key::Q => { self.held.switches[0] = pressed; }
+            Key::E => { self.held.switches[1] = pressed; }
+            Key::Space => { self.held.grab = value; }
+            Key::R => { self.held.respawn = pressed; }
+            _ => { return false; }
+        }
+        true
+    }
+
+    //Opposite keys cancel out while both are held, and releasing either one moves toward the other again
+    fn set_movement_key(&mut self, key: usize, pressed: bool) {
+        self.movement_keys[key] = pressed;
+        let keys = self.movement_keys;
+        let axis = |positive: usize, negative: usize| { (keys[positive] as i32 - keys[negative] as i32) as f32 };
+        self.held.move_stick = [axis(RIGHT, LEFT), axis(FORWARD, BACK)];
+    }
+
+    //The left mouse button fires the left hand's gadget and the right mouse button fires the right hand's
+    pub fn handle_mouse_button(&mut self, hand: usize, pressed: bool) {
+        self.held.triggers[hand] = if pressed { 1.0 } else { 0.0 };
+    }
+
+    pub fn release_all(&mut self) {
+        self.held = Controls::default();
+        self.movement_keys = [false; 4];
+    }
+
+    //Latches the controls for this frame, so that changed_since_last_sync works like it does with OpenXR
+    //The gamepad and keyboard can be used at the same time, with the keyboard winning for movement
+    pub fn sync(&mut self, gamepad: &GamepadControls) {
+        self.last = self.current;
+        self.current = self.held;
+        if self.current.move_stick == [0.0; 2] {
+            self.current.move_stick = gamepad.move_stick;
+        }
+        for i in 0..2 {
+            self.current.triggers[i] = f32::max(self.current.triggers[i], gamepad.triggers[i]);
+            self.current.switches[i] |= gamepad.switches[i];
+        }
+        self.current.grab = f32::max(self.current.grab, gamepad.grab);
+        self.current.respawn |= gamepad.respawn;
+    }
+
+    fn state<T: xr::ActionInput>(current_state: T, changed_since_last_sync: bool) -> Option<xr::ActionState<T>> {
+        Some(xr::ActionState {
+            current_state,
+            changed_since_last_sync,
+            last_change_time: xr::Time::from_nanos(0),
+            is_active: true
+        })
+    }
+
+    //A real thumbstick reports a slightly different value every frame, so the stick always counts as changed
+    pub fn move_stick_state(&self) -> Option<xr::ActionState<xr::Vector2f>> {
+        let stick = self.current.move_stick;
+        let mut v = glm::vec2(stick[0], stick[1]);
+        if glm::length(&v) > 1.0 {
+            v = glm::normalize(&v);
+        }
+        Self::state(xr::Vector2f { x: v.x, y: v.y }, true)
+    }
+
+    pub fn trigger_state(&self, hand: usize) -> Option<xr::ActionState<f32>> {
+        Self::state(self.current.triggers[hand], self.current.triggers[hand] != self.last.triggers[hand])
+    }
+
+    pub fn switch_state(&self, hand: usize) -> Option<xr::ActionState<bool>> {
+        Self::state(self.current.switches[hand], self.current.switches[hand] != self.last.switches[hand])
+    }
+
+    //Both hands grab at once, since there's only one key for it
+    pub fn grab_state(&self) -> Option<xr::ActionState<f32>> {
+        Self::state(self.current.grab, self.current.grab != self.last.grab)
+    }
+
+    pub fn respawn_state(&self) -> Option<xr::ActionState<bool>> {
+        Self::state(self.current.respawn, self.current.respawn != self.last.respawn)
+    }
+
+    //The head looks wherever the mouse-controlled camera does, relative to tracking space
+    fn head_transform(camera: &Camera, tracking_rotation: f32) -> glm::TMat4<f32> {
+        let pitch = f32::clamp(camera.orientation.y, -glm::pi::<f32>() + MIN_PITCH, -MIN_PITCH);
+        glm::translation(&glm::vec3(0.0, 0.0, EYE_HEIGHT)) *
+        glm::rotation(-tracking_rotation - camera.orientation.x, &glm::vec3(0.0, 0.0, 1.0)) *
+        glm::rotation(-pitch, &glm::vec3(1.0, 0.0, 0.0))
+    }
+
+    pub fn head_pose(camera: &Camera, tracking_rotation: f32) -> xr::Posef {
+        mat4_to_pose(&Self::head_transform(camera, tracking_rotation))
+    }
+
+    //The virtual hands float in front of the head and aim where the view is pointing
+    //Aim space points down the hand's y axis with z up, while view space points down -z with y up
+    pub fn hand_pose(camera: &Camera, tracking_rotation: f32, hand: usize) -> xr::Posef {
+        let head = Self::head_transform(camera, tracking_rotation);
+        let side = if hand == 0 { -HAND_SIDE } else { HAND_SIDE };
+        let hand_transform = head *
+                             glm::translation(&glm::vec3(side, -HAND_DROP, -HAND_FORWARD)) *
+                             glm::rotation(-glm::half_pi::<f32>(), &glm::vec3(1.0, 0.0, 0.0));
+        mat4_to_pose(&hand_transform)
+    }
+}
+
+fn mat4_to_pose(transform: &glm::TMat4<f32>) -> xr::Posef {
+    let q = glm::to_quat(transform);
+    xr::Posef {
+        orientation: xr::Quaternionf { x: q.coords.x, y: q.coords.y, z: q.coords.z, w: q.coords.w },
+        position: xr::Vector3f { x: transform[12], y: transform[13], z: transform[14] }
+    }
+}
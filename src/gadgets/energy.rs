@@ -0,0 +1,147 @@
+use crate::gamestate::{MoveState, Player};
+use super::GadgetSettings;
+
+//How a gadget gets its energy back on its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Recharge {
+    Never,                                  //Only pickups refill it
+    WhileGrounded,                          //Refills completely whenever the player isn't airborne
+    OverTime {
+        rate: f32,                          //Units per second
+        delay: f32                          //Seconds after the last drain before recharging starts
+    }
+}
+
+impl Recharge {
+    pub const NAMES: [&'static str; 3] = ["Never", "While grounded", "Over time"];
+
+    pub fn index(&self) -> usize {
+        match self {
+            Recharge::Never => { 0 }
+            Recharge::WhileGrounded => { 1 }
+            Recharge::OverTime { .. } => { 2 }
+        }
+    }
+
+    pub fn from_index(idx: usize) -> Option<Self> {
+        match idx {
+            0 => { Some(Recharge::Never) }
+            1 => { Some(Recharge::WhileGrounded) }
+            2 => { Some(Recharge::OverTime { rate: 1.0, delay: 1.0 }) }
+            _ => { None }
+        }
+    }
+}
+
+//The tunable part of a gadget's energy
+//Each gadget has its own defaults, which can be changed from the gadget settings while playing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnergyRules {
+    pub capacity: f32,
+    pub drain_scale: f32,                   //Multiplies how much each use of the gadget drains
+    pub recharge: Recharge
+}
+
+impl EnergyRules {
+    //Written as capacity:drain_scale:recharge, where recharge is never, grounded, or over_time:rate:delay
+    pub fn to_config_value(&self) -> String {
+        let recharge = match self.recharge {
+            Recharge::Never => { String::from("never") }
+            Recharge::WhileGrounded => { String::from("grounded") }
+            Recharge::OverTime { rate, delay } => { format!("over_time:{}:{}", rate, delay) }
+        };
+        format!("{}:{}:{}", self.capacity, self.drain_scale, recharge)
+    }
+
+    pub fn from_config_value(value: &str) -> Option<Self> {
+        let fields: Vec<&str> = value.split(':').collect();
+        let number = |i: usize| { fields.get(i).and_then(|field| field.parse::<f32>().ok()) };
+        let recharge = match fields.get(2) {
+            Some(&"never") => { Recharge::Never }
+            Some(&"grounded") => { Recharge::WhileGrounded }
+            Some(&"over_time") => { Recharge::OverTime { rate: number(3)?, delay: number(4)? } }
+            _ => { return None; }
+        };
+        Some(EnergyRules {
+            capacity: number(0)?,
+            drain_scale: number(1)?,
+            recharge
+        })
+    }
+}
+
+//A gadget's resource, such as the water in the water cannon or the charges of the net
+pub struct Energy {
+    pub current: f32,
+    pub capacity: f32,
+    pub drain_scale: f32,
+    pub recharge: Recharge,
+    idle_time: f32                          //Seconds since energy was last drained
+}
+
+impl Energy {
+    pub fn new(rules: EnergyRules) -> Self {
+        Energy {
+            current: rules.capacity,
+            capacity: rules.capacity,
+            drain_scale: rules.drain_scale,
+            recharge: rules.recharge,
+            idle_time: 0.0
+        }
+    }
+
+    pub fn rules(&self) -> EnergyRules {
+        EnergyRules {
+            capacity: self.capacity,
+            drain_scale: self.drain_scale,
+            recharge: self.recharge
+        }
+    }
+
+    //Shrinking the capacity takes away whatever no longer fits
+    pub fn apply_rules(&mut self, rules: EnergyRules) {
+        self.capacity = rules.capacity;
+        self.drain_scale = rules.drain_scale;
+        self.recharge = rules.recharge;
+        self.current = f32::min(self.current, self.capacity);
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.capacity > 0.0 { self.current / self.capacity }
+        else { 0.0 }
+    }
+
+    pub fn has(&self, amount: f32) -> bool {
+        self.current >= amount * self.drain_scale
+    }
+
+    //Infinite ammo still counts as using the gadget so that recharge delays behave the same
+    pub fn drain(&mut self, amount: f32, settings: &GadgetSettings) {
+        if !settings.infinite_ammo {
+            self.current = f32::max(self.current - amount * self.drain_scale, 0.0);
+        }
+        self.idle_time = 0.0;
+    }
+
+    //Adds a fraction of the capacity
+    pub fn refill(&mut self, fraction: f32) {
+        self.current = f32::min(self.current + fraction * self.capacity, self.capacity);
+    }
+
+    pub fn update(&mut self, player: &Player, delta_time: f32) {
+        self.idle_time += delta_time;
+        match self.recharge {
+            Recharge::Never => {}
+            Recharge::WhileGrounded => {
+                if player.movement_state != MoveState::Falling {
+                    self.current = self.capacity;
+                }
+            }
+            Recharge::OverTime { rate, delay } => {
+                if self.idle_time >= delay {
+                    self.current = f32::min(self.current + rate * delta_time, self.capacity);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use ozy::collision::{Ray, ray_hit_terrain};
+use crate::gamestate::MoveState;
+use super::*;
+
+const FIRE_THRESHOLD: f32 = 0.5;            //Trigger value past which the hook is fired, and past which the rope is reeled in
+const RELEASE_THRESHOLD: f32 = 0.2;         //Trigger value below which the hook lets go
+const ROPE_BEADS: usize = 32;
+const BEAD_SCALE: f32 = 0.2;
+
+//Fires a hook along the hand's aim direction that attaches to terrain
+//While attached, the rope keeps the hand within rope_length of the anchor, and squeezing the trigger reels the player in
+pub struct GrapplingHook {
+    model: usize,
+    rope_model: usize,
+    anchor: Option<glm::TVec3<f32>>,
+    rope_length: f32,
+    trigger_held: bool,             //So that holding the trigger after a miss doesn't refire every frame
+    rope_visible: bool
+}
+
+impl GrapplingHook {
+    pub const NAME: &'static str = "Grappling hook";
+    pub const DEFAULT_RANGE: f32 = 40.0;
+    pub const DEFAULT_REEL_SPEED: f32 = 8.0;            //Meters per second of rope pulled in at full trigger
+    pub const DEFAULT_MIN_ROPE_LENGTH: f32 = 1.0;
+
+    pub fn create(loader: &mut GadgetLoader) -> Box<dyn Gadget> {
+        Box::new(GrapplingHook {
+            model: loader.load_model(Self::NAME, "models/wand.ozy"),
+            rope_model: loader.load_instanced_model("models/sphere.ozy", ROPE_BEADS),
+            anchor: None,
+            rope_length: 0.0,
+            trigger_held: false,
+            rope_visible: false
+        })
+    }
+
+    fn fire(&mut self, ctx: &GadgetContext, hand_position: glm::TVec3<f32>, aim_direction: glm::TVec3<f32>) {
+        let ray = Ray {
+            origin: hand_position,
+            direction: aim_direction
+        };
+        if let Some(collision) = ray_hit_terrain(&ctx.collision.terrain, &ray) {
+            let distance = glm::distance(&hand_position, &collision.point);
+            let attachable = ctx.settings.grapple_any_terrain || ctx.collision.grabbable_flags[collision.triangle_index];
+            if distance <= ctx.settings.grapple_range && attachable {
+                self.anchor = Some(collision.point);
+                self.rope_length = distance;
+            }
+        }
+    }
+
+    fn hide_rope(&mut self, scene_data: &mut SceneData) {
+        if self.rope_visible {
+            if let Some(entity) = scene_data.opaque_entities.get_mut_element(self.rope_model) {
+                for i in 0..ROPE_BEADS {
+                    unsafe { entity.update_single_transform(i, &glm::zero()); }
+                }
+            }
+            self.rope_visible = false;
+        }
+    }
+}
+
+impl Gadget for GrapplingHook {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn model(&self) -> usize { self.model }
+
+    fn unequip(&mut self, ctx: &mut GadgetContext) {
+        self.anchor = None;
+        self.trigger_held = false;
+        self.hide_rope(ctx.scene_data);
+        hide_model(ctx.scene_data, self.model, ctx.hand);
+    }
+
+    fn trigger(&mut self, ctx: &mut GadgetContext, input: &TriggerInput) {
+        let hand_transform = match ctx.aim_transform {
+            Some(t) => { t }
+            None => { return; }
+        };
+
+        if input.value < RELEASE_THRESHOLD {
+            //Letting go keeps whatever momentum the swing built up
+            self.anchor = None;
+            self.trigger_held = false;
+            return;
+        }
+
+        match self.anchor {
+            None => {
+                if input.value > FIRE_THRESHOLD && !self.trigger_held {
+                    let hand_position = glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 0.0, 0.0, 1.0)));
+                    let aim_direction = glm::normalize(&glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 1.0, 0.0, 0.0))));
+                    self.fire(ctx, hand_position, aim_direction);
+                    self.trigger_held = true;
+                }
+            }
+            Some(_) => {
+                //Between the release and fire thresholds the rope length stays fixed so the player can swing
+                if input.value > FIRE_THRESHOLD {
+                    let reel_amount = ctx.settings.reel_speed * (input.value - FIRE_THRESHOLD) / (1.0 - FIRE_THRESHOLD) * ctx.delta_time;
+                    self.rope_length = f32::max(self.rope_length - reel_amount, ctx.settings.min_rope_length);
+                }
+            }
+        }
+    }
+
+    fn update(&mut self, ctx: &mut GadgetContext) {
+        //Enforce the rope constraint on the hand holding the hook
+        if let (Some(anchor), Some(hand_transform)) = (self.anchor, ctx.aim_transform) {
+            let player = &mut ctx.player;
+            if player.is_climbing() {
+                return;
+            }
+
+            let hand_position = glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 0.0, 0.0, 1.0)));
+            let offset = hand_position - anchor;
+            let distance = glm::length(&offset);
+            if distance > self.rope_length {
+                let rope_direction = offset / distance;
+                player.tracking_position -= rope_direction * (distance - self.rope_length);
+
+                //Cancel any velocity pulling away from the anchor, which leaves only the swing
+                let radial_speed = glm::dot(&player.tracking_velocity, &rope_direction);
+                if radial_speed > 0.0 {
+                    player.tracking_velocity -= rope_direction * radial_speed;
+                }
+                player.movement_state = MoveState::Falling;
+            }
+        }
+    }
+
+    fn render(&mut self, ctx: &mut GadgetRenderContext) {
+        if let Some(transform) = &ctx.grip_transform {
+            place_model(ctx.scene_data, self.model, ctx.hand, transform);
+        }
+
+        //Draw the rope as a string of beads from the hand to the anchor
+        match (self.anchor, &ctx.aim_transform) {
+            (Some(anchor), Some(hand_transform)) => {
+                let hand_position = glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 0.0, 0.0, 1.0)));
+                if let Some(entity) = ctx.scene_data.opaque_entities.get_mut_element(self.rope_model) {
+                    for i in 0..ROPE_BEADS {
+                        let t = (i + 1) as f32 / ROPE_BEADS as f32;
+                        let bead = glm::translation(&(hand_position + (anchor - hand_position) * t)) * glm::scaling(&glm::vec3(BEAD_SCALE, BEAD_SCALE, BEAD_SCALE));
+                        unsafe { entity.update_single_transform(i, &bead); }
+                    }
+                }
+                self.rope_visible = true;
+            }
+            _ => {
+                self.hide_rope(ctx.scene_data);
+            }
+        }
+    }
+}
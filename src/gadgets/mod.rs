@@ -0,0 +1,452 @@
+use gl::types::*;
+use std::collections::HashMap;
+use ozy::collision::Capsule;
+use ozy::render::TextureKeeper;
+use crate::climbing::GripRequest;
+use crate::gamestate::{Player, StaticCollision};
+use ozy::routines::uniform_scale;
+use crate::render::{billboard_entity, RenderEntity, SceneData, DEBUG_COLOR_ATTRIBUTE, DEBUG_HIGHLIGHTED_ATTRIBUTE, DEBUG_TRANSFORM_ATTRIBUTE, STANDARD_TRANSFORM_ATTRIBUTE};
+use crate::routines::{write_matrix_to_buffer, write_vec4_to_buffer};
+use crate::structs::Configuration;
+use energy::{Energy, EnergyRules};
+use wheel::{SelectionWheel, WheelInput, QUICK_SWAP_TIME};
+
+pub mod energy;
+pub mod grappling_hook;
+pub mod net;
+pub mod sticky_hand;
+pub mod water_cannon;
+pub mod wheel;
+
+const HUD_PIPS: usize = 10;
+const HUD_PIP_RADIUS: f32 = 0.005;
+const HUD_PIP_SPACING: f32 = 0.012;
+const PREVIEW_SCALE: f32 = 0.5;
+const HIGHLIGHTED_PREVIEW_SCALE: f32 = 0.8;
+const WHEEL_MARKER_RADIUS: f32 = 0.03;
+
+//Type of the function each gadget module exposes to create an instance of its gadget
+pub type GadgetConstructor = fn(&mut GadgetLoader) -> Box<dyn Gadget>;
+
+//Every gadget the player can equip, in the order the switch button cycles through them
+//Adding a gadget means writing its module and adding its constructor here
+pub const REGISTRY: [GadgetConstructor; 4] = [
+    net::Net::create,
+    sticky_hand::StickyHand::create,
+    water_cannon::WaterCannon::create,
+    grappling_hook::GrapplingHook::create
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hand {
+    Left,
+    Right
+}
+
+impl Hand {
+    pub const COUNT: usize = 2;
+
+    pub fn index(&self) -> usize {
+        match self {
+            Hand::Left => { 0 }
+            Hand::Right => { 1 }
+        }
+    }
+
+    //Whether this hand is currently holding onto the terrain
+    pub fn is_gripping(&self, player: &Player) -> bool {
+        player.grips[self.index()].is_some()
+    }
+}
+
+//Analog trigger input, decoupled from OpenXR's ActionState
+#[derive(Clone, Copy, Debug)]
+pub struct TriggerInput {
+    pub value: f32,
+    pub changed: bool           //Whether the value changed since the last frame
+}
+
+//User-facing options that change how gadgets behave
+pub struct GadgetSettings {
+    pub infinite_ammo: bool,
+    pub grapple_any_terrain: bool,              //If false, the grappling hook only attaches to grabbable triangles
+    pub grapple_range: f32,
+    pub reel_speed: f32,
+    pub min_rope_length: f32,
+    pub energy_rules: Vec<(&'static str, EnergyRules)>      //Keyed by gadget name, starting out as each gadget's defaults
+}
+
+impl GadgetSettings {
+    pub fn from_config(config: &Configuration, energy_rules: Vec<(&'static str, EnergyRules)>) -> Self {
+        let float_or = |key: &str, default: f32| {
+            match config.float_options.get(key) {
+                Some(v) => { *v }
+                None => { default }
+            }
+        };
+
+        //Saved rules override the defaults gadget by gadget
+        let mut energy_rules = energy_rules;
+        if let Some(spec) = config.string_options.get(Configuration::GADGET_ENERGY) {
+            for entry in spec.split(',') {
+                let mut parts = entry.splitn(2, ':');
+                match (parts.next().map(Configuration::unescape), parts.next().and_then(EnergyRules::from_config_value)) {
+                    (Some(name), Some(rules)) => {
+                        match energy_rules.iter_mut().find(|(gadget_name, _)| *gadget_name == name) {
+                            Some((_, gadget_rules)) => { *gadget_rules = rules; }
+                            None => { println!("Unknown gadget \"{}\" in energy rules", name); }
+                        }
+                    }
+                    _ => { println!("Malformed gadget energy rules \"{}\"", entry); }
+                }
+            }
+        }
+
+        GadgetSettings {
+            infinite_ammo: false,
+            grapple_any_terrain: match config.int_options.get(Configuration::GRAPPLE_ANY_TERRAIN) {
+                Some(flag) => { *flag != 0 }
+                None => { false }
+            },
+            grapple_range: float_or(Configuration::GRAPPLE_RANGE, grappling_hook::GrapplingHook::DEFAULT_RANGE),
+            reel_speed: float_or(Configuration::GRAPPLE_REEL_SPEED, grappling_hook::GrapplingHook::DEFAULT_REEL_SPEED),
+            min_rope_length: float_or(Configuration::GRAPPLE_MIN_ROPE_LENGTH, grappling_hook::GrapplingHook::DEFAULT_MIN_ROPE_LENGTH),
+            energy_rules
+        }
+    }
+
+    //Infinite ammo is a cheat for the current session, so it isn't saved
+    pub fn save_to_config(&self, config: &mut Configuration) {
+        config.int_options.insert(String::from(Configuration::GRAPPLE_ANY_TERRAIN), self.grapple_any_terrain as u32);
+        config.float_options.insert(String::from(Configuration::GRAPPLE_RANGE), self.grapple_range);
+        config.float_options.insert(String::from(Configuration::GRAPPLE_REEL_SPEED), self.reel_speed);
+        config.float_options.insert(String::from(Configuration::GRAPPLE_MIN_ROPE_LENGTH), self.min_rope_length);
+
+        let energy_rules: Vec<String> = self.energy_rules.iter().map(|(name, rules)| {
+            format!("{}:{}", Configuration::escape(name), rules.to_config_value())
+        }).collect();
+        config.string_options.insert(String::from(Configuration::GADGET_ENERGY), energy_rules.join(","));
+    }
+
+    pub fn energy_rules(&self, gadget_name: &str) -> Option<EnergyRules> {
+        self.energy_rules.iter().find(|(name, _)| *name == gadget_name).map(|(_, rules)| *rules)
+    }
+}
+
+//Everything a gadget is allowed to touch while handling input
+pub struct GadgetContext<'a> {
+    pub hand: Hand,
+    pub player: &'a mut Player,
+    pub scene_data: &'a mut SceneData,
+    pub collision: &'a StaticCollision,
+    pub settings: &'a GadgetSettings,
+    pub aim_transform: Option<glm::TMat4<f32>>,         //World space transform of the hand's aim pose, if it's being tracked
+    pub delta_time: f32,
+    pub grip_request: &'a mut GripRequest               //Whether this hand is trying to hold onto whatever it's touching this frame
+}
+
+pub struct GadgetRenderContext<'a> {
+    pub hand: Hand,
+    pub scene_data: &'a mut SceneData,
+    pub grip_transform: Option<glm::TMat4<f32>>,
+    pub aim_transform: Option<glm::TMat4<f32>>
+}
+
+pub trait Gadget {
+    fn name(&self) -> &'static str;
+
+    //Index of the gadget's model in SceneData::opaque_entities
+    fn model(&self) -> usize;
+
+    fn equip(&mut self, _ctx: &mut GadgetContext) {}
+
+    fn unequip(&mut self, ctx: &mut GadgetContext) {
+        hide_model(ctx.scene_data, self.model(), ctx.hand);
+    }
+
+    //Called each frame the hand's trigger is being tracked
+    fn trigger(&mut self, _ctx: &mut GadgetContext, _input: &TriggerInput) {}
+
+    //Called every frame while equipped, after trigger()
+    fn update(&mut self, _ctx: &mut GadgetContext) {}
+
+    //Gadgets without a limited resource return None
+    fn energy(&self) -> Option<&Energy> { None }
+
+    fn energy_mut(&mut self) -> Option<&mut Energy> { None }
+
+    //Capsules that soak any Totoro they touch
+    fn colliders(&self) -> Vec<Capsule> { Vec::new() }
+
+    fn render(&mut self, ctx: &mut GadgetRenderContext) {
+        if let Some(transform) = &ctx.grip_transform {
+            place_model(ctx.scene_data, self.model(), ctx.hand, transform);
+        }
+    }
+}
+
+//Used by gadget constructors to load their graphics resources
+pub struct GadgetLoader<'a> {
+    pub scene_data: &'a mut SceneData,
+    pub texture_keeper: &'a mut TextureKeeper,
+    pub program: GLuint,
+    pub particle_program: GLuint,
+    pub debug_program: GLuint,
+    pub models: HashMap<(&'static str, String), usize>      //Models already loaded by load_model, by gadget name and path
+}
+
+impl<'a> GadgetLoader<'a> {
+    //Loads a gadget's model, which starts out hidden
+    //Both hands' copies of the gadget share the model, each drawing its own instance of it
+    pub fn load_model(&mut self, gadget_name: &'static str, path: &str) -> usize {
+        let key = (gadget_name, String::from(path));
+        if let Some(model) = self.models.get(&key) {
+            return *model;
+        }
+        let model = self.load_instanced_model(path, Hand::COUNT);
+        self.models.insert(key, model);
+        model
+    }
+
+    pub fn load_instanced_model(&mut self, path: &str, instances: usize) -> usize {
+        let mut entity = RenderEntity::from_ozy(path, self.program, instances, STANDARD_TRANSFORM_ATTRIBUTE, self.texture_keeper, &crate::DEFAULT_TEX_PARAMS);
+        for i in 0..instances {
+            unsafe { entity.update_single_transform(i, &glm::zero()); }
+        }
+        self.scene_data.opaque_entities.insert(entity)
+    }
+
+    //Creates an empty set of billboards in SceneData::transparent_entities
+    pub fn load_particles(&mut self, max_particles: usize) -> usize {
+        let entity = unsafe { billboard_entity(self.particle_program, max_particles) };
+        self.scene_data.transparent_entities.insert(entity)
+    }
+
+    //Creates a set of colored spheres for things like the energy gauge and the selection wheel's markers
+    fn load_marker_spheres(&mut self, count: usize) -> usize {
+        let entity = unsafe {
+            let segments = 8;
+            let rings = 8;
+            let vao = ozy::prims::debug_sphere_vao(1.0, segments, rings);
+            let mut re = RenderEntity::from_vao(vao, self.debug_program, ozy::prims::sphere_index_count(segments, rings), count, DEBUG_TRANSFORM_ATTRIBUTE, false);
+            re.init_new_instanced_buffer(4, DEBUG_COLOR_ATTRIBUTE, RenderEntity::COLOR_BUFFER_INDEX);
+            re.init_new_instanced_buffer(1, DEBUG_HIGHLIGHTED_ATTRIBUTE, RenderEntity::HIGHLIGHTED_BUFFER_INDEX);
+            re.active_instances = 0;
+            re
+        };
+        self.scene_data.transparent_entities.insert(entity)
+    }
+}
+
+//Places the given hand's instance of a model loaded with GadgetLoader::load_model
+pub fn place_model(scene_data: &mut SceneData, model: usize, hand: Hand, transform: &glm::TMat4<f32>) {
+    if let Some(entity) = scene_data.opaque_entities.get_mut_element(model) {
+        unsafe { entity.update_single_transform(hand.index(), transform); }
+    }
+}
+
+pub fn hide_model(scene_data: &mut SceneData, model: usize, hand: Hand) {
+    place_model(scene_data, model, hand, &glm::zero());
+}
+
+fn update_marker_spheres(scene_data: &mut SceneData, model: usize, transforms: &[f32], colors: &[f32]) {
+    if let Some(entity) = scene_data.transparent_entities.get_mut_element(model) {
+        entity.update_color_buffer(colors, DEBUG_COLOR_ATTRIBUTE);
+        entity.update_highlight_buffer(&vec![0.0; colors.len() / 4], DEBUG_HIGHLIGHTED_ATTRIBUTE);
+        entity.update_transform_buffer(transforms, DEBUG_TRANSFORM_ATTRIBUTE);
+    }
+}
+
+fn hide_marker_spheres(scene_data: &mut SceneData, model: usize) {
+    if let Some(entity) = scene_data.transparent_entities.get_mut_element(model) {
+        entity.active_instances = 0;
+    }
+}
+
+//The set of gadgets held by one hand, only one of which is equipped at a time
+pub struct GadgetHand {
+    pub hand: Hand,
+    pub gadgets: Vec<Box<dyn Gadget>>,
+    pub current: usize,
+    pub recent: [Option<usize>; 2],             //The last two gadgets this hand held, most recent first
+    pub wheel: Option<SelectionWheel>,
+    hud_model: usize,
+    wheel_model: usize
+}
+
+impl GadgetHand {
+    pub fn new(hand: Hand, loader: &mut GadgetLoader, starting_gadget: &str) -> Self {
+        let mut gadgets = Vec::with_capacity(REGISTRY.len());
+        for constructor in REGISTRY.iter() {
+            gadgets.push(constructor(loader));
+        }
+
+        let mut current = 0;
+        for i in 0..gadgets.len() {
+            if gadgets[i].name() == starting_gadget {
+                current = i;
+                break;
+            }
+        }
+
+        GadgetHand {
+            hand,
+            gadgets,
+            current,
+            recent: [None; 2],
+            wheel: None,
+            hud_model: loader.load_marker_spheres(HUD_PIPS),
+            wheel_model: loader.load_marker_spheres(REGISTRY.len())
+        }
+    }
+
+    pub fn current(&self) -> &dyn Gadget { self.gadgets[self.current].as_ref() }
+
+    pub fn current_mut(&mut self) -> &mut dyn Gadget { self.gadgets[self.current].as_mut() }
+
+    pub fn switch_to(&mut self, idx: usize, ctx: &mut GadgetContext) {
+        if idx == self.current || idx >= self.gadgets.len() {
+            return;
+        }
+
+        //Remember the gadget being put away, without letting the new one appear in its own history
+        let previous = self.current;
+        let candidates = [Some(previous), self.recent[0], self.recent[1]];
+        let mut recent = [None; 2];
+        let mut count = 0;
+        for candidate in candidates.iter() {
+            if count < recent.len() && *candidate != Some(idx) && !recent.contains(candidate) {
+                recent[count] = *candidate;
+                count += 1;
+            }
+        }
+        self.recent = recent;
+
+        self.gadgets[self.current].unequip(ctx);
+        self.current = idx;
+        self.gadgets[self.current].equip(ctx);
+    }
+
+    pub fn quick_swap(&mut self, ctx: &mut GadgetContext) {
+        if let Some(idx) = self.recent[0] {
+            self.switch_to(idx, ctx);
+        }
+    }
+
+    //Holding the switch button opens the selection wheel, and releasing it equips the highlighted gadget
+    //A quick tap that doesn't highlight anything swaps back to the last gadget instead
+    pub fn update_wheel(&mut self, input: &WheelInput, ctx: &mut GadgetContext) {
+        let hand_position = ctx.aim_transform.map(|t| glm::vec4_to_vec3(&(t * glm::vec4(0.0, 0.0, 0.0, 1.0))));
+        if input.held {
+            match &mut self.wheel {
+                Some(wheel) => { wheel.update(input, hand_position, ctx.delta_time); }
+                None => {
+                    if let Some(anchor) = ctx.aim_transform {
+                        self.wheel = Some(SelectionWheel::new(anchor, self.gadgets.len()));
+                    }
+                }
+            }
+        } else if let Some(wheel) = self.wheel.take() {
+            //Put the previews away before equipping anything
+            for i in 0..self.gadgets.len() {
+                if i != self.current {
+                    hide_model(ctx.scene_data, self.gadgets[i].model(), self.hand);
+                }
+            }
+            hide_marker_spheres(ctx.scene_data, self.wheel_model);
+
+            match wheel.highlighted {
+                Some(idx) => { self.switch_to(idx, ctx); }
+                None => {
+                    if wheel.held_time < QUICK_SWAP_TIME {
+                        self.quick_swap(ctx);
+                    }
+                }
+            }
+        }
+    }
+
+    //Updates the equipped gadget, and recharges every gadget the hand is holding
+    pub fn update(&mut self, ctx: &mut GadgetContext) {
+        self.gadgets[self.current].update(ctx);
+        for gadget in self.gadgets.iter_mut() {
+            let name = gadget.name();
+            if let Some(energy) = gadget.energy_mut() {
+                if let Some(rules) = ctx.settings.energy_rules(name) {
+                    energy.apply_rules(rules);
+                }
+                energy.update(ctx.player, ctx.delta_time);
+            }
+        }
+    }
+
+    //The default energy rules of every gadget that uses energy
+    pub fn energy_rules(&self) -> Vec<(&'static str, EnergyRules)> {
+        self.gadgets.iter().filter_map(|gadget| gadget.energy().map(|energy| (gadget.name(), energy.rules()))).collect()
+    }
+
+    pub fn refill(&mut self, fraction: f32) {
+        for gadget in self.gadgets.iter_mut() {
+            if let Some(energy) = gadget.energy_mut() {
+                energy.refill(fraction);
+            }
+        }
+    }
+
+    //Renders the equipped gadget along with a gauge of its energy along the top of the controller
+    pub fn render(&mut self, ctx: &mut GadgetRenderContext) {
+        self.gadgets[self.current].render(ctx);
+        self.render_wheel(ctx);
+
+        let fraction = match (self.gadgets[self.current].energy(), &ctx.grip_transform) {
+            (Some(energy), Some(_)) => { Some(energy.fraction()) }
+            _ => { None }
+        };
+        match (fraction, &ctx.grip_transform) {
+            (Some(fraction), Some(grip_transform)) => {
+                //Pips fade from green to red as the energy runs out
+                let filled_color = glm::vec4(1.0 - fraction, fraction, 0.1, 0.9);
+                let empty_color = glm::vec4(0.2, 0.2, 0.2, 0.5);
+                let filled_pips = fraction * HUD_PIPS as f32;
+
+                let mut transforms = vec![0.0; HUD_PIPS * 16];
+                let mut colors = vec![0.0; HUD_PIPS * 4];
+                for i in 0..HUD_PIPS {
+                    let offset = glm::vec3(0.0, 0.04, 0.03 - i as f32 * HUD_PIP_SPACING);
+                    let (color, scale) = if (i as f32) < filled_pips { (filled_color, 1.0) }
+                                         else { (empty_color, 0.6) };
+                    write_matrix_to_buffer(&mut transforms, i, grip_transform * glm::translation(&offset) * uniform_scale(HUD_PIP_RADIUS * scale));
+                    write_vec4_to_buffer(&mut colors, i, color);
+                }
+                update_marker_spheres(ctx.scene_data, self.hud_model, &transforms, &colors);
+            }
+            _ => {
+                hide_marker_spheres(ctx.scene_data, self.hud_model);
+            }
+        }
+    }
+
+    //Previews every gadget's model in its slot, with a marker behind each one
+    fn render_wheel(&mut self, ctx: &mut GadgetRenderContext) {
+        let wheel = match &self.wheel {
+            Some(w) => { w }
+            None => { return; }
+        };
+
+        let mut transforms = vec![0.0; self.gadgets.len() * 16];
+        let mut colors = vec![0.0; self.gadgets.len() * 4];
+        for i in 0..self.gadgets.len() {
+            let highlighted = wheel.highlighted == Some(i);
+            let scale = if highlighted { HIGHLIGHTED_PREVIEW_SCALE } else { PREVIEW_SCALE };
+            place_model(ctx.scene_data, self.gadgets[i].model(), self.hand, &(wheel.slot_transform(i) * uniform_scale(scale)));
+
+            let color = if highlighted { glm::vec4(1.0, 0.85, 0.2, 0.6) }
+                        else if i == self.current { glm::vec4(0.2, 0.9, 0.3, 0.4) }
+                        else if self.recent.contains(&Some(i)) { glm::vec4(0.3, 0.5, 1.0, 0.4) }
+                        else { glm::vec4(0.6, 0.6, 0.6, 0.3) };
+            write_matrix_to_buffer(&mut transforms, i, glm::translation(&wheel.slot_position(i)) * uniform_scale(WHEEL_MARKER_RADIUS));
+            write_vec4_to_buffer(&mut colors, i, color);
+        }
+        update_marker_spheres(ctx.scene_data, self.wheel_model, &transforms, &colors);
+    }
+}
@@ -0,0 +1,47 @@
+use super::*;
+use super::energy::{EnergyRules, Recharge};
+
+const CHARGES: f32 = 3.0;
+const CHARGE_RATE: f32 = 0.5;               //Charges per second
+const RECHARGE_DELAY: f32 = 1.5;
+
+//Pulling the trigger all the way performs a midair jump, using up one of the net's charges
+pub struct Net {
+    model: usize,
+    charges: Energy
+}
+
+impl Net {
+    pub const NAME: &'static str = "Net";
+    pub const ENERGY_RULES: EnergyRules = EnergyRules {
+        capacity: CHARGES,
+        drain_scale: 1.0,
+        recharge: Recharge::OverTime { rate: CHARGE_RATE, delay: RECHARGE_DELAY }
+    };
+
+    pub fn create(loader: &mut GadgetLoader) -> Box<dyn Gadget> {
+        Box::new(Net {
+            model: loader.load_model(Self::NAME, "models/sphere.ozy"),
+            charges: Energy::new(Self::ENERGY_RULES)
+        })
+    }
+}
+
+impl Gadget for Net {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn model(&self) -> usize { self.model }
+
+    fn trigger(&mut self, ctx: &mut GadgetContext, input: &TriggerInput) {
+        let player = &mut ctx.player;
+        if input.changed && input.value == 1.0 && player.jumps_remaining > 0 && self.charges.has(1.0) {
+            player.tracking_velocity.z = 10.0;
+            player.jumps_remaining -= 1;
+            self.charges.drain(1.0, ctx.settings);
+        }
+    }
+
+    fn energy(&self) -> Option<&Energy> { Some(&self.charges) }
+
+    fn energy_mut(&mut self) -> Option<&mut Energy> { Some(&mut self.charges) }
+}
@@ -0,0 +1,59 @@
+use super::*;
+use super::energy::{EnergyRules, Recharge};
+
+const DURABILITY: f32 = 8.0;                //Seconds the hand can hold on for
+const RECOVERY_RATE: f32 = 2.0;             //Seconds of durability regained per second
+const RECOVERY_DELAY: f32 = 0.5;
+
+//Holding the trigger sticks the hand to any wall, not just grabbable ones, until the stick wears out
+pub struct StickyHand {
+    model: usize,
+    holding: bool,          //Whether the trigger is asking to hold on this frame
+    durability: Energy
+}
+
+impl StickyHand {
+    pub const NAME: &'static str = "Sticky hand";
+    pub const ENERGY_RULES: EnergyRules = EnergyRules {
+        capacity: DURABILITY,
+        drain_scale: 1.0,
+        recharge: Recharge::OverTime { rate: RECOVERY_RATE, delay: RECOVERY_DELAY }
+    };
+
+    pub fn create(loader: &mut GadgetLoader) -> Box<dyn Gadget> {
+        Box::new(StickyHand {
+            model: loader.load_model(Self::NAME, "models/stick.ozy"),
+            holding: false,
+            durability: Energy::new(Self::ENERGY_RULES)
+        })
+    }
+}
+
+impl Gadget for StickyHand {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn model(&self) -> usize { self.model }
+
+    fn unequip(&mut self, ctx: &mut GadgetContext) {
+        self.holding = false;
+        hide_model(ctx.scene_data, self.model, ctx.hand);
+    }
+
+    fn trigger(&mut self, ctx: &mut GadgetContext, input: &TriggerInput) {
+        self.holding = input.value > 0.5 && self.durability.current > 0.0;
+        if self.holding {
+            ctx.grip_request.held = true;
+            ctx.grip_request.any_surface = true;
+        }
+    }
+
+    fn update(&mut self, ctx: &mut GadgetContext) {
+        if self.holding && ctx.hand.is_gripping(ctx.player) {
+            self.durability.drain(ctx.delta_time, ctx.settings);
+        }
+    }
+
+    fn energy(&self) -> Option<&Energy> { Some(&self.durability) }
+
+    fn energy_mut(&mut self) -> Option<&mut Energy> { Some(&mut self.durability) }
+}
@@ -0,0 +1,296 @@
+use ozy::collision::{LineSegment, Ray, Terrain, ray_hit_terrain};
+use crate::gamestate::{MoveState, set_player_falling};
+use crate::render::PARTICLE_TRANSFORM_ATTRIBUTE;
+use crate::routines::{floats_equal, write_matrix_to_buffer};
+use ozy::routines::uniform_scale;
+use super::*;
+use super::energy::{EnergyRules, Recharge};
+
+const MAX_WATER_PRESSURE: f32 = 30.0;
+const DRAIN_SPEED: f32 = 2.0;
+const WATER_CAPACITY: f32 = 100.0;
+
+const MAX_DROPLETS: usize = 512;
+const EMISSION_RATE: f32 = 120.0;           //Droplets per second at full pressure
+const NOZZLE_SPEED: f32 = 15.0;             //Meters per second at full pressure
+const DROPLET_RADIUS: f32 = 0.06;
+const DROPLET_LIFETIME: f32 = 1.5;
+const SOAK_RADIUS: f32 = 0.15;              //Radius of the capsules swept out by droplets when testing against Totoros
+const PREDICTION_STEP: f32 = 0.25;          //Seconds of flight covered by each raycast when predicting where the stream lands
+const SPLASH_DROPLETS: usize = 5;
+const SPLASH_SPEED: f32 = 3.0;
+const SPLASH_RADIUS: f32 = 0.04;
+const SPLASH_LIFETIME: f32 = 0.35;
+
+#[derive(Clone, Copy)]
+struct Impact {
+    time: f32,                      //Droplet age at which it hits the terrain
+    point: glm::TVec3<f32>,
+    normal: glm::TVec3<f32>
+}
+
+struct Droplet {
+    position: glm::TVec3<f32>,
+    previous_position: glm::TVec3<f32>,
+    velocity: glm::TVec3<f32>,
+    radius: f32,
+    age: f32,
+    lifetime: f32,
+    impact: Option<Impact>,
+    is_splash: bool                 //Splash droplets are purely cosmetic, so they neither collide nor soak Totoros
+}
+
+//Sprays a stream of water droplets that pushes the player in the opposite direction and soaks Totoros
+pub struct WaterCannon {
+    model: usize,
+    particles_model: usize,
+    force: glm::TVec3<f32>,
+    pressure: f32,                  //Trigger value this frame, or zero if the cannon isn't firing
+    emission_accumulator: f32,      //Fractional droplets carried between frames
+    splash_phase: f32,
+    droplets: Vec<Droplet>,
+    water: Energy
+}
+
+impl WaterCannon {
+    pub const NAME: &'static str = "Water cannon";
+    pub const ENERGY_RULES: EnergyRules = EnergyRules {
+        capacity: WATER_CAPACITY,
+        drain_scale: 1.0,
+        recharge: Recharge::WhileGrounded
+    };
+
+    pub fn create(loader: &mut GadgetLoader) -> Box<dyn Gadget> {
+        Box::new(WaterCannon {
+            model: loader.load_model(Self::NAME, "models/wand.ozy"),
+            particles_model: loader.load_particles(MAX_DROPLETS),
+            force: glm::zero(),
+            pressure: 0.0,
+            emission_accumulator: 0.0,
+            splash_phase: 0.0,
+            droplets: Vec::with_capacity(MAX_DROPLETS),
+            water: Energy::new(Self::ENERGY_RULES)
+        })
+    }
+
+    fn emit(&mut self, terrain: &Terrain, nozzle: glm::TVec3<f32>, velocity: glm::TVec3<f32>, radius: f32, delta_time: f32) {
+        self.emission_accumulator += EMISSION_RATE * self.pressure * delta_time;
+        let count = self.emission_accumulator as usize;
+        self.emission_accumulator -= count as f32;
+        if count == 0 {
+            return;
+        }
+
+        //Every droplet emitted this frame follows the same arc, so the landing spot only needs to be predicted once
+        let impact = predict_impact(terrain, &nozzle, &velocity);
+        for i in 0..count {
+            if self.droplets.len() >= MAX_DROPLETS {
+                break;
+            }
+
+            //Stagger the droplets along the nozzle so a frame's worth of water doesn't clump together
+            let head_start = i as f32 / count as f32 * delta_time;
+            self.droplets.push(Droplet {
+                position: nozzle + velocity * head_start,
+                previous_position: nozzle,
+                velocity,
+                radius,
+                age: head_start,
+                lifetime: DROPLET_LIFETIME,
+                impact,
+                is_splash: false
+            });
+        }
+    }
+
+    fn splash(&mut self, point: glm::TVec3<f32>, normal: glm::TVec3<f32>, incoming_velocity: glm::TVec3<f32>) {
+        //Build a basis around the surface normal
+        let reference = if f32::abs(normal.z) < 0.9 { glm::vec3(0.0, 0.0, 1.0) } else { glm::vec3(1.0, 0.0, 0.0) };
+        let tangent = glm::normalize(&glm::cross(&normal, &reference));
+        let bitangent = glm::cross(&normal, &tangent);
+        let reflected = incoming_velocity - 2.0 * glm::dot(&incoming_velocity, &normal) * normal;
+
+        //Spread the droplets in a ring, rotating the ring each splash so consecutive splashes don't line up
+        for i in 0..SPLASH_DROPLETS {
+            if self.droplets.len() >= MAX_DROPLETS {
+                break;
+            }
+
+            let angle = self.splash_phase + i as f32 * glm::two_pi::<f32>() / SPLASH_DROPLETS as f32;
+            let direction = glm::normalize(&(normal + 0.8 * (f32::cos(angle) * tangent + f32::sin(angle) * bitangent)));
+            let velocity = direction * SPLASH_SPEED + reflected * 0.15;
+            self.droplets.push(Droplet {
+                position: point + normal * SPLASH_RADIUS,
+                previous_position: point,
+                velocity,
+                radius: SPLASH_RADIUS,
+                age: 0.0,
+                lifetime: SPLASH_LIFETIME,
+                impact: None,
+                is_splash: true
+            });
+        }
+        self.splash_phase += 2.39996;           //Golden angle
+    }
+
+    fn simulate(&mut self, delta_time: f32) {
+        let mut impacts = Vec::new();
+        let gravity = glm::vec3(0.0, 0.0, -crate::ACCELERATION_GRAVITY);
+        let mut i = 0;
+        while i < self.droplets.len() {
+            let droplet = &mut self.droplets[i];
+            droplet.previous_position = droplet.position;
+            droplet.velocity += gravity * delta_time;
+            droplet.position += droplet.velocity * delta_time;
+            droplet.age += delta_time;
+
+            let mut dead = droplet.age >= droplet.lifetime;
+            if let Some(impact) = &droplet.impact {
+                if droplet.age >= impact.time {
+                    impacts.push((impact.point, impact.normal, droplet.velocity));
+                    dead = true;
+                }
+            }
+
+            if dead {
+                self.droplets.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        for (point, normal, velocity) in impacts {
+            self.splash(point, normal, velocity);
+        }
+    }
+}
+
+//Marches along the droplets' ballistic arc to find where it first meets the terrain
+fn predict_impact(terrain: &Terrain, origin: &glm::TVec3<f32>, velocity: &glm::TVec3<f32>) -> Option<Impact> {
+    let gravity = glm::vec3(0.0, 0.0, -crate::ACCELERATION_GRAVITY);
+    let position_at = |t: f32| { origin + velocity * t + 0.5 * gravity * t * t };
+
+    let mut t = 0.0;
+    while t < DROPLET_LIFETIME {
+        let start = position_at(t);
+        let end = position_at(t + PREDICTION_STEP);
+        let segment = end - start;
+        let segment_length = glm::length(&segment);
+        if floats_equal(segment_length, 0.0) {
+            t += PREDICTION_STEP;
+            continue;
+        }
+
+        let ray = Ray {
+            origin: start,
+            direction: segment / segment_length
+        };
+        if let Some(collision) = ray_hit_terrain(terrain, &ray) {
+            let distance = glm::distance(&start, &collision.point);
+            if distance <= segment_length {
+                return Some(Impact {
+                    time: t + distance / segment_length * PREDICTION_STEP,
+                    point: collision.point,
+                    normal: terrain.face_normals[collision.triangle_index]
+                });
+            }
+        }
+        t += PREDICTION_STEP;
+    }
+    None
+}
+
+impl Gadget for WaterCannon {
+    fn name(&self) -> &'static str { Self::NAME }
+
+    fn model(&self) -> usize { self.model }
+
+    fn unequip(&mut self, ctx: &mut GadgetContext) {
+        self.force = glm::zero();
+        self.pressure = 0.0;
+        self.emission_accumulator = 0.0;
+        self.droplets.clear();
+        hide_model(ctx.scene_data, self.model, ctx.hand);
+        if let Some(entity) = ctx.scene_data.transparent_entities.get_mut_element(self.particles_model) {
+            entity.active_instances = 0;
+        }
+    }
+
+    fn trigger(&mut self, ctx: &mut GadgetContext, input: &TriggerInput) {
+        //Calculate the force of shooting the water gun
+        if let Some(hand_transform) = &ctx.aim_transform {
+            let world_space_vec = hand_transform * glm::vec4(0.0, 1.0, 0.0, 0.0);
+            self.force = glm::vec4_to_vec3(&(-input.value * world_space_vec));
+
+            if input.value > 0.0 {
+                if ctx.player.movement_state != MoveState::Falling {
+                    set_player_falling(ctx.player);
+                }
+            }
+        }
+
+        //Apply watergun force to player
+        self.pressure = 0.0;
+        if !floats_equal(glm::length(&self.force), 0.0) && self.water.current > 0.0 {
+            let update_force = self.force * ctx.delta_time * MAX_WATER_PRESSURE;
+            self.water.drain(glm::length(&update_force) * DRAIN_SPEED, ctx.settings);
+            ctx.player.tracking_velocity += update_force;
+            self.pressure = input.value;
+        }
+    }
+
+    fn update(&mut self, ctx: &mut GadgetContext) {
+        //Spray water out of the nozzle
+        if self.pressure > 0.0 {
+            if let Some(hand_transform) = &ctx.aim_transform {
+                let nozzle = glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 0.0, 0.0, 1.0)));
+                let direction = glm::normalize(&glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 1.0, 0.0, 0.0))));
+
+                //The stream weakens and thins out as the tank runs dry
+                let velocity = direction * NOZZLE_SPEED * (0.25 + 0.75 * self.pressure) + ctx.player.tracking_velocity;
+                let radius = DROPLET_RADIUS * f32::max(self.water.fraction(), 0.3);
+                self.emit(&ctx.collision.terrain, nozzle, velocity, radius, ctx.delta_time);
+            }
+        }
+        self.pressure = 0.0;
+        self.simulate(ctx.delta_time);
+    }
+
+    fn energy(&self) -> Option<&Energy> { Some(&self.water) }
+
+    fn energy_mut(&mut self) -> Option<&mut Energy> { Some(&mut self.water) }
+
+    //Each stream droplet soaks whatever it passed through this frame
+    fn colliders(&self) -> Vec<Capsule> {
+        let mut capsules = Vec::new();
+        for droplet in self.droplets.iter() {
+            if droplet.is_splash || floats_equal(glm::distance(&droplet.previous_position, &droplet.position), 0.0) {
+                continue;
+            }
+
+            capsules.push(Capsule {
+                segment: LineSegment {
+                    p0: droplet.previous_position,
+                    p1: droplet.position
+                },
+                radius: SOAK_RADIUS
+            });
+        }
+        capsules
+    }
+
+    fn render(&mut self, ctx: &mut GadgetRenderContext) {
+        if let Some(transform) = &ctx.grip_transform {
+            place_model(ctx.scene_data, self.model, ctx.hand, transform);
+        }
+
+        if let Some(entity) = ctx.scene_data.transparent_entities.get_mut_element(self.particles_model) {
+            let mut transforms = vec![0.0; self.droplets.len() * 16];
+            for i in 0..self.droplets.len() {
+                let droplet = &self.droplets[i];
+                write_matrix_to_buffer(&mut transforms, i, glm::translation(&droplet.position) * uniform_scale(droplet.radius));
+            }
+            entity.update_transform_buffer(&transforms, PARTICLE_TRANSFORM_ATTRIBUTE);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//Radial menu for picking a gadget, opened by holding a hand's switch button
+
+const RADIUS: f32 = 0.12;                   //Distance from the wheel's center to each slot
+const FORWARD_OFFSET: f32 = 0.15;           //Distance in front of the hand the wheel is centered
+const STICK_DEADZONE: f32 = 0.5;
+const MOTION_DEADZONE: f32 = 0.04;          //Meters the hand must move from the center before it picks a slot
+pub const QUICK_SWAP_TIME: f32 = 0.3;       //Releasing the switch button sooner than this without picking anything swaps to the last gadget
+
+//Input driving an open wheel
+#[derive(Clone, Copy, Debug)]
+pub struct WheelInput {
+    pub held: bool,                         //Whether the switch button is down
+    pub stick: Option<glm::TVec2<f32>>      //The hand's thumbstick or trackpad, if it has one
+}
+
+pub struct SelectionWheel {
+    pub anchor: glm::TMat4<f32>,            //Hand aim transform at the moment the wheel was opened
+    pub held_time: f32,
+    pub highlighted: Option<usize>,
+    slots: usize
+}
+
+impl SelectionWheel {
+    pub fn new(anchor: glm::TMat4<f32>, slots: usize) -> Self {
+        SelectionWheel {
+            anchor,
+            held_time: 0.0,
+            highlighted: None,
+            slots
+        }
+    }
+
+    //The wheel lies in the plane of the hand's x and z axes, in front of where the hand was when it opened
+    fn axes(&self) -> (glm::TVec3<f32>, glm::TVec3<f32>) {
+        let right = glm::normalize(&glm::vec4_to_vec3(&(self.anchor * glm::vec4(1.0, 0.0, 0.0, 0.0))));
+        let up = glm::normalize(&glm::vec4_to_vec3(&(self.anchor * glm::vec4(0.0, 0.0, 1.0, 0.0))));
+        (right, up)
+    }
+
+    pub fn center(&self) -> glm::TVec3<f32> {
+        glm::vec4_to_vec3(&(self.anchor * glm::vec4(0.0, FORWARD_OFFSET, 0.0, 1.0)))
+    }
+
+    //Slots run clockwise starting from the top of the wheel
+    fn slot_angle(&self, slot: usize) -> f32 {
+        glm::half_pi::<f32>() - slot as f32 * glm::two_pi::<f32>() / self.slots as f32
+    }
+
+    pub fn slot_position(&self, slot: usize) -> glm::TVec3<f32> {
+        let (right, up) = self.axes();
+        let angle = self.slot_angle(slot);
+        self.center() + RADIUS * (f32::cos(angle) * right + f32::sin(angle) * up)
+    }
+
+    //Transform for a gadget's model previewed in a slot, keeping the orientation the hand had when the wheel opened
+    pub fn slot_transform(&self, slot: usize) -> glm::TMat4<f32> {
+        let mut rotation = self.anchor;
+        rotation[12] = 0.0;
+        rotation[13] = 0.0;
+        rotation[14] = 0.0;
+        glm::translation(&self.slot_position(slot)) * rotation
+    }
+
+    fn slot_from_direction(&self, direction: glm::TVec2<f32>) -> usize {
+        let slot_width = glm::two_pi::<f32>() / self.slots as f32;
+        let angle = glm::half_pi::<f32>() - f32::atan2(direction.y, direction.x);
+        let angle = angle.rem_euclid(glm::two_pi::<f32>());
+        ((angle + 0.5 * slot_width) / slot_width) as usize % self.slots
+    }
+
+    //Picks the slot the stick is pointing at, or failing that the slot the hand has moved towards
+    pub fn update(&mut self, input: &WheelInput, hand_position: Option<glm::TVec3<f32>>, delta_time: f32) {
+        self.held_time += delta_time;
+
+        if let Some(stick) = input.stick {
+            if glm::length(&stick) > STICK_DEADZONE {
+                self.highlighted = Some(self.slot_from_direction(stick));
+                return;
+            }
+        }
+
+        if let Some(position) = hand_position {
+            let (right, up) = self.axes();
+            let offset = position - self.center();
+            let planar = glm::vec2(glm::dot(&offset, &right), glm::dot(&offset, &up));
+            if glm::length(&planar) > MOTION_DEADZONE {
+                self.highlighted = Some(self.slot_from_direction(planar));
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use glfw::{Action, GamepadAxis, GamepadButton, GamepadState, Glfw, JoystickId};
+use imgui::NavInput;
+use crate::structs::Configuration;
+
+//Controller input through GLFW's gamepad database, for flying the freecam, desktop play and navigating the dev gui
+//Every control can be rebound in the config file with a line like "gamepad_mappings = grab:x,respawn:-left_y"
+
+pub const LOOK_SPEED: f32 = 2.5;                    //Radians per second at full deflection
+const STICK_DEADZONE: f32 = 0.15;
+const PRESS_THRESHOLD: f32 = 0.5;                   //Analog value past which an input counts as pressed
+const JOYSTICKS: [JoystickId; 4] = [JoystickId::Joystick1, JoystickId::Joystick2, JoystickId::Joystick3, JoystickId::Joystick4];
+
+//A single button or axis on the gamepad
+#[derive(Clone, Copy)]
+pub enum GamepadInput {
+    Button(GamepadButton),
+    Axis(GamepadAxis, bool)                         //The flag inverts the axis
+}
+
+impl GamepadInput {
+    //Parses names like "a", "left_bumper" or "-left_y"
+    pub fn parse(name: &str) -> Option<Self> {
+        let (inverted, name) = match name.strip_prefix('-') {
+            Some(rest) => { (true, rest) }
+            None => { (false, name) }
+        };
+
+        let button = match name {
+            "a" => { Some(GamepadButton::ButtonA) }
+            "b" => { Some(GamepadButton::ButtonB) }
+            "x" => { Some(GamepadButton::ButtonX) }
+            "y" => { Some(GamepadButton::ButtonY) }
+            "left_bumper" => { Some(GamepadButton::ButtonLeftBumper) }
+            "right_bumper" => { Some(GamepadButton::ButtonRightBumper) }
+            "back" => { Some(GamepadButton::ButtonBack) }
+            "start" => { Some(GamepadButton::ButtonStart) }
+            "guide" => { Some(GamepadButton::ButtonGuide) }
+            "left_thumb" => { Some(GamepadButton::ButtonLeftThumb) }
+            "right_thumb" => { Some(GamepadButton::ButtonRightThumb) }
+            "dpad_up" => { Some(GamepadButton::ButtonDpadUp) }
+            "dpad_right" => { Some(GamepadButton::ButtonDpadRight) }
+            "dpad_down" => { Some(GamepadButton::ButtonDpadDown) }
+            "dpad_left" => { Some(GamepadButton::ButtonDpadLeft) }
+            _ => { None }
+        };
+        if let Some(button) = button {
+            //Buttons can't be inverted
+            return if inverted { None } else { Some(GamepadInput::Button(button)) };
+        }
+
+        let axis = match name {
+            "left_x" => { GamepadAxis::AxisLeftX }
+            "left_y" => { GamepadAxis::AxisLeftY }
+            "right_x" => { GamepadAxis::AxisRightX }
+            "right_y" => { GamepadAxis::AxisRightY }
+            "left_trigger" => { GamepadAxis::AxisLeftTrigger }
+            "right_trigger" => { GamepadAxis::AxisRightTrigger }
+            _ => { return None; }
+        };
+        Some(GamepadInput::Axis(axis, inverted))
+    }
+
+    //Buttons read as zero or one, sticks from negative one to one, and triggers from zero to one
+    pub fn value(&self, state: &GamepadState) -> f32 {
+        match *self {
+            GamepadInput::Button(button) => {
+                if state.get_button_state(button) == Action::Press { 1.0 }
+                else { 0.0 }
+            }
+            GamepadInput::Axis(axis, inverted) => {
+                let value = match axis {
+                    //GLFW reports a released trigger as -1
+                    GamepadAxis::AxisLeftTrigger | GamepadAxis::AxisRightTrigger => { (state.get_axis(axis) + 1.0) / 2.0 }
+                    _ => { state.get_axis(axis) }
+                };
+                if inverted { -value }
+                else { value }
+            }
+        }
+    }
+
+    pub fn pressed(&self, state: &GamepadState) -> bool {
+        self.value(state) > PRESS_THRESHOLD
+    }
+}
+
+pub struct GamepadMappings {
+    pub move_x: GamepadInput,
+    pub move_y: GamepadInput,                       //Positive is forward
+    pub look_x: GamepadInput,
+    pub look_y: GamepadInput,                       //Positive looks down, like moving the mouse down
+    pub fly_up: GamepadInput,                       //Freecam only
+    pub fly_down: GamepadInput,
+    pub left_gadget: GamepadInput,
+    pub right_gadget: GamepadInput,
+    pub left_switch: GamepadInput,
+    pub right_switch: GamepadInput,
+    pub grab: GamepadInput,
+    pub respawn: GamepadInput,
+    pub toggle_desktop: GamepadInput,
+    pub toggle_menu: GamepadInput                   //Hands the gamepad over to the dev gui
+}
+
+impl Default for GamepadMappings {
+    fn default() -> Self {
+        GamepadMappings {
+            move_x: GamepadInput::Axis(GamepadAxis::AxisLeftX, false),
+            move_y: GamepadInput::Axis(GamepadAxis::AxisLeftY, true),
+            look_x: GamepadInput::Axis(GamepadAxis::AxisRightX, false),
+            look_y: GamepadInput::Axis(GamepadAxis::AxisRightY, false),
+            fly_up: GamepadInput::Button(GamepadButton::ButtonDpadUp),
+            fly_down: GamepadInput::Button(GamepadButton::ButtonDpadDown),
+            left_gadget: GamepadInput::Axis(GamepadAxis::AxisLeftTrigger, false),
+            right_gadget: GamepadInput::Axis(GamepadAxis::AxisRightTrigger, false),
+            left_switch: GamepadInput::Button(GamepadButton::ButtonLeftBumper),
+            right_switch: GamepadInput::Button(GamepadButton::ButtonRightBumper),
+            grab: GamepadInput::Button(GamepadButton::ButtonA),
+            respawn: GamepadInput::Button(GamepadButton::ButtonY),
+            toggle_desktop: GamepadInput::Button(GamepadButton::ButtonBack),
+            toggle_menu: GamepadInput::Button(GamepadButton::ButtonStart)
+        }
+    }
+}
+
+impl GamepadMappings {
+    //Starts from the default layout and applies any overrides from the config file
+    pub fn from_config(config: &Configuration) -> Self {
+        let mut mappings = Self::default();
+        if let Some(spec) = config.string_options.get(Configuration::GAMEPAD_MAPPINGS) {
+            for entry in spec.split(',') {
+                let mut parts = entry.split(':');
+                match (parts.next(), parts.next().and_then(GamepadInput::parse)) {
+                    (Some(action), Some(input)) => {
+                        if !mappings.set(action, input) {
+                            println!("Unknown gamepad action \"{}\"", action);
+                        }
+                    }
+                    _ => { println!("Malformed gamepad mapping \"{}\"", entry); }
+                }
+            }
+        }
+        mappings
+    }
+
+    fn set(&mut self, action: &str, input: GamepadInput) -> bool {
+        let binding = match action {
+            "move_x" => { &mut self.move_x }
+            "move_y" => { &mut self.move_y }
+            "look_x" => { &mut self.look_x }
+            "look_y" => { &mut self.look_y }
+            "fly_up" => { &mut self.fly_up }
+            "fly_down" => { &mut self.fly_down }
+            "left_gadget" => { &mut self.left_gadget }
+            "right_gadget" => { &mut self.right_gadget }
+            "left_switch" => { &mut self.left_switch }
+            "right_switch" => { &mut self.right_switch }
+            "grab" => { &mut self.grab }
+            "respawn" => { &mut self.respawn }
+            "toggle_desktop" => { &mut self.toggle_desktop }
+            "toggle_menu" => { &mut self.toggle_menu }
+            _ => { return false; }
+        };
+        *binding = input;
+        true
+    }
+}
+
+//What the gamepad is asking for this frame
+#[derive(Clone, Copy, Default)]
+pub struct GamepadControls {
+    pub move_stick: [f32; 2],
+    pub look: [f32; 2],
+    pub fly: f32,
+    pub triggers: [f32; 2],
+    pub switches: [bool; 2],
+    pub grab: f32,
+    pub respawn: bool
+}
+
+pub struct Gamepad {
+    pub mappings: GamepadMappings,
+    pub menu_mode: bool,                            //While set, the gamepad only drives the dev gui
+    pub desktop_toggled: bool,                      //Set on the frame the toggle was pressed
+    pub menu_toggled: bool,
+    state: Option<GamepadState>,
+    was_toggling_desktop: bool,
+    was_toggling_menu: bool
+}
+
+impl Gamepad {
+    pub fn new(mappings: GamepadMappings) -> Self {
+        Gamepad {
+            mappings,
+            menu_mode: false,
+            desktop_toggled: false,
+            menu_toggled: false,
+            state: None,
+            was_toggling_desktop: false,
+            was_toggling_menu: false
+        }
+    }
+
+    //Reads the first connected joystick that GLFW recognizes as a gamepad
+    pub fn poll(&mut self, glfw: &Glfw) {
+        self.state = None;
+        for id in JOYSTICKS.iter() {
+            let joystick = glfw.get_joystick(*id);
+            if joystick.is_gamepad() {
+                self.state = joystick.get_gamepad_state();
+                break;
+            }
+        }
+
+        let (toggling_desktop, toggling_menu) = match &self.state {
+            Some(state) => { (self.mappings.toggle_desktop.pressed(state), self.mappings.toggle_menu.pressed(state)) }
+            None => { (false, false) }
+        };
+        self.desktop_toggled = toggling_desktop && !self.was_toggling_desktop && !self.menu_mode;
+        self.menu_toggled = toggling_menu && !self.was_toggling_menu;
+        self.was_toggling_desktop = toggling_desktop;
+        self.was_toggling_menu = toggling_menu;
+        if self.menu_toggled {
+            self.menu_mode = !self.menu_mode;
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.is_some()
+    }
+
+    pub fn controls(&self) -> GamepadControls {
+        let state = match (&self.state, self.menu_mode) {
+            (Some(state), false) => { state }
+            _ => { return GamepadControls::default(); }
+        };
+        let m = &self.mappings;
+        GamepadControls {
+            move_stick: apply_deadzone(m.move_x.value(state), m.move_y.value(state)),
+            look: apply_deadzone(m.look_x.value(state), m.look_y.value(state)),
+            fly: m.fly_up.value(state) - m.fly_down.value(state),
+            triggers: [m.left_gadget.value(state), m.right_gadget.value(state)],
+            switches: [m.left_switch.pressed(state), m.right_switch.pressed(state)],
+            grab: m.grab.value(state),
+            respawn: m.respawn.pressed(state)
+        }
+    }
+
+    //Imgui's gamepad navigation uses its own fixed layout, so this ignores the mappings
+    pub fn update_imgui_nav(&self, io: &mut imgui::Io) {
+        for input in io.nav_inputs.iter_mut() {
+            *input = 0.0;
+        }
+
+        let state = match (&self.state, self.menu_mode) {
+            (Some(state), true) => { state }
+            _ => { return; }
+        };
+        let button = |b: GamepadButton| {
+            if state.get_button_state(b) == Action::Press { 1.0 }
+            else { 0.0 }
+        };
+        let stick = apply_deadzone(state.get_axis(GamepadAxis::AxisLeftX), state.get_axis(GamepadAxis::AxisLeftY));
+        let trigger = |a: GamepadAxis| { (state.get_axis(a) + 1.0) / 2.0 };
+
+        let nav = &mut io.nav_inputs;
+        nav[NavInput::Activate as usize] = button(GamepadButton::ButtonA);
+        nav[NavInput::Cancel as usize] = button(GamepadButton::ButtonB);
+        nav[NavInput::Menu as usize] = button(GamepadButton::ButtonX);
+        nav[NavInput::Input as usize] = button(GamepadButton::ButtonY);
+        nav[NavInput::DpadLeft as usize] = button(GamepadButton::ButtonDpadLeft);
+        nav[NavInput::DpadRight as usize] = button(GamepadButton::ButtonDpadRight);
+        nav[NavInput::DpadUp as usize] = button(GamepadButton::ButtonDpadUp);
+        nav[NavInput::DpadDown as usize] = button(GamepadButton::ButtonDpadDown);
+        nav[NavInput::FocusPrev as usize] = button(GamepadButton::ButtonLeftBumper);
+        nav[NavInput::FocusNext as usize] = button(GamepadButton::ButtonRightBumper);
+        nav[NavInput::LStickLeft as usize] = f32::max(-stick[0], 0.0);
+        nav[NavInput::LStickRight as usize] = f32::max(stick[0], 0.0);
+        nav[NavInput::LStickUp as usize] = f32::max(-stick[1], 0.0);
+        nav[NavInput::LStickDown as usize] = f32::max(stick[1], 0.0);
+        nav[NavInput::TweakSlow as usize] = trigger(GamepadAxis::AxisLeftTrigger);
+        nav[NavInput::TweakFast as usize] = trigger(GamepadAxis::AxisRightTrigger);
+    }
+}
+
+//Radial deadzone that rescales the remaining range back to zero through one
+fn apply_deadzone(x: f32, y: f32) -> [f32; 2] {
+    let v = glm::vec2(x, y);
+    let length = glm::length(&v);
+    if length < STICK_DEADZONE {
+        return [0.0, 0.0];
+    }
+    let scaled = v / length * f32::min((length - STICK_DEADZONE) / (1.0 - STICK_DEADZONE), 1.0);
+    [scaled.x, scaled.y]
+}
@@ -1,11 +1,14 @@
 use ozy::structs::OptionVec;
 use ozy::collision::{LineSegment, Terrain};
-use strum::EnumCount;
 use ozy::collision::*;
-use xr::Posef;
+use crate::ai::{Activity, Needs, PointOfInterest};
+use crate::animation::TotoroAnimator;
+use crate::audio::{ReverbPreset, SfxHandle};
+use crate::damage::*;
+use crate::rng::{GameRng, RngStream};
 use crate::traits::SphereCollider;
 use crate::routines::*;
-use crate::structs::{Camera, EntityList};
+use crate::structs::{Camera, EntityList, EntityRef};
 
 #[derive(PartialEq, Eq)]
 pub enum MoveState {
@@ -18,13 +21,21 @@ pub struct WorldState {
     pub freecam: Camera,
     pub collision: StaticCollision,
     pub totoros: EntityList<Totoro>,
+    pub totoro_spawners: EntityList<TotoroSpawner>,
+    pub points_of_interest: EntityList<PointOfInterest>,
+    pub water_volumes: EntityList<WaterVolume>,
+    pub energy_pickups: EntityList<EnergyPickup>,
+    pub hazard_regions: EntityList<HazardRegion>,
+    pub respawn_points: EntityList<RespawnPoint>,
+    pub reverb_zones: EntityList<ReverbZone>,
     pub opaque_terrain_indices: Vec<usize>,     //Indices of the terrain's graphics data in a RenderEntities array
     pub transparent_terrain_indices: Vec<usize>,     //Indices of the terrain's graphics data in a RenderEntities array
     pub collision_re_index: usize,
     pub skybox_strings: Vec<String>,
     pub level_name: String,
     pub active_skybox_index: usize,
-    pub delta_timescale: f32
+    pub delta_timescale: f32,
+    pub rng: GameRng                            //All gameplay randomness comes from here
 }
 
 pub struct StaticCollision {
@@ -45,13 +56,20 @@ impl StaticCollision {
 pub struct Player {
     pub tracking_position: glm::TVec3<f32>,
     pub tracking_velocity: glm::TVec3<f32>,
+    pub tracking_rotation: f32,                     //Yaw of tracking space about the world's z axis, changed by turning
     pub spawn_position: glm::TVec3<f32>,
     pub tracked_segment: LineSegment,
     pub last_tracked_segment: LineSegment,
     pub movement_state: MoveState,
-    pub stick_data: Option<StickData>,
+    pub grips: [Option<glm::TVec3<f32>>; 2],       //World space points each hand is holding onto, indexed by Hand::index()
     pub jumps_remaining: usize,
-    pub was_holding_jump: bool
+    pub was_holding_jump: bool,
+    pub health: f32,
+    pub life_state: LifeState,
+    pub time_since_damage: f32,
+    pub touching_ground: bool,                      //Whether the player landed on a walkable triangle this frame
+    pub impact_speed: f32,                          //Downward speed the player landed with this frame
+    pub safe_position: Option<glm::TVec3<f32>>      //Where the player's feet last stood on the ground outside of any hazard
 }
 
 impl Player {
@@ -62,21 +80,63 @@ impl Player {
         Player {
             tracking_position: pos,
             tracking_velocity: glm::zero(),
+            tracking_rotation: 0.0,
             spawn_position,
             tracked_segment: LineSegment::zero(),
             last_tracked_segment: LineSegment::zero(),
             movement_state: MoveState::Falling,
-            stick_data: None,
+            grips: [None; 2],
             jumps_remaining: Player::MAX_JUMPS,
-            was_holding_jump: false
+            was_holding_jump: false,
+            health: PLAYER_MAX_HEALTH,
+            life_state: LifeState::Alive,
+            time_since_damage: 0.0,
+            touching_ground: false,
+            impact_speed: 0.0,
+            safe_position: None
         }
     }
+
+    pub fn is_climbing(&self) -> bool {
+        self.grips[0].is_some() || self.grips[1].is_some()
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.life_state == LifeState::Alive
+    }
+
+    //Returns true if this damage killed the player
+    pub fn damage(&mut self, amount: f32) -> bool {
+        if !self.is_alive() || amount <= 0.0 {
+            return false;
+        }
+
+        self.health -= amount;
+        self.time_since_damage = 0.0;
+        if self.health <= 0.0 {
+            self.kill();
+            return true;
+        }
+        false
+    }
+
+    pub fn kill(&mut self) {
+        if self.is_alive() {
+            self.health = 0.0;
+            self.life_state = LifeState::Dying(0.0);
+        }
+    }
+
+    pub fn world_from_tracking(&self) -> glm::TMat4<f32> {
+        glm::translation(&self.tracking_position) * glm::rotation(self.tracking_rotation, &glm::vec3(0.0, 0.0, 1.0))
+    }
 }
 
-pub fn ground_player(player: &mut Player, max_energy: &mut f32) {    
+pub fn ground_player(player: &mut Player) {    
+    player.impact_speed = f32::max(player.impact_speed, -player.tracking_velocity.z);
+    player.touching_ground = true;
     player.tracking_velocity = glm::zero();
     player.jumps_remaining = Player::MAX_JUMPS;
-    *max_energy = Gadget::MAX_ENERGY;
 }
 
 pub fn set_player_falling(player: &mut Player) {
@@ -91,6 +151,43 @@ pub fn reset_player_position(player: &mut Player) {
     player.last_tracked_segment = LineSegment::zero();
     player.jumps_remaining = Player::MAX_JUMPS;
     player.movement_state = MoveState::Falling;
+    player.grips = [None; 2];
+    player.health = PLAYER_MAX_HEALTH;
+    player.time_since_damage = 0.0;
+    player.life_state = LifeState::Alive;
+}
+
+//Brings the player back to life with their feet at the target, or at the level's spawn if there's nowhere safe to go
+pub fn respawn_player(player: &mut Player, target: Option<glm::TVec3<f32>>) {
+    let feet_offset = player.tracked_segment.p1 - player.tracking_position;
+    reset_player_position(player);
+    if let Some(target) = target {
+        player.tracking_position = target - feet_offset;
+    }
+    player.life_state = LifeState::Respawning(0.0);
+}
+
+//Respawn points are preferred over the last safe position so that level designers can decide where falls send the player
+//The one closest to where the player last stood safely is used
+pub fn choose_respawn_position(world_state: &WorldState) -> Option<glm::TVec3<f32>> {
+    let reference = match world_state.player.safe_position {
+        Some(p) => { p }
+        None => { world_state.player.spawn_position }
+    };
+
+    let mut closest = None;
+    let mut min_distance = f32::INFINITY;
+    for point in world_state.respawn_points.entities.iter() {
+        if let Some(point) = point {
+            let distance = glm::distance(&point.position, &reference);
+            if distance < min_distance {
+                min_distance = distance;
+                closest = Some(point.position);
+            }
+        }
+    }
+
+    closest.or(world_state.player.safe_position)
 }
 
 /*
@@ -127,17 +224,32 @@ pub struct Totoro {
     pub state: TotoroState,
     pub state_timer: f32,
     pub relax_duration: f32,
-    pub drown_sfx_id: Option<usize>,
+    pub drown_sfx: Option<SfxHandle>,
     pub saw_player_last: f32,
+    pub spawner: Option<EntityRef>,         //The TotoroSpawner that created this Totoro, if any
+    pub animator: TotoroAnimator,
+    pub needs: Needs,
+    pub activity: Activity,
+    pub breath: f32,                                    //Seconds of air left while submerged
+    pub last_damage_source: Option<DamageSource>
 }
 
 impl Totoro {
     pub const MAX_HEALTH: f32 = 100.0;
+    pub const DEFAULT_MIN_SCALE: f32 = 0.5;
+    pub const DEFAULT_MAX_SCALE: f32 = 2.0;
+
+    pub fn new(position: glm::TVec3<f32>, scale: f32, creation_time: f32, rng: &mut GameRng) -> Self {
+        //Generate random orientation
+        let forward = glm::normalize(&glm::vec3(rng.ranged_f32(RngStream::Spawning, -1.0, 1.0), rng.ranged_f32(RngStream::Spawning, -1.0, 1.0), 0.0));
+        let idle_phase = rng.random_f32(RngStream::Spawning) * glm::two_pi::<f32>();
 
-    pub fn new(position: glm::TVec3<f32>, creation_time: f32) -> Self {
-        //Generate random orientation and scale
-        let forward = glm::normalize(&glm::vec3(rand::random::<f32>() * 2.0 - 1.0, ranged_randomf32(-1.0, 1.0), 0.0));
-        let scale = ranged_randomf32(0.5, 2.0);
+        //Start with staggered needs so that a group of Totoros doesn't act in lockstep
+        let needs = Needs::new(
+            rng.ranged_f32(RngStream::Spawning, 0.0, 0.5),
+            rng.ranged_f32(RngStream::Spawning, 0.0, 0.5),
+            rng.ranged_f32(RngStream::Spawning, 0.0, 0.5)
+        );
         
         Totoro {
             position,
@@ -151,9 +263,36 @@ impl Totoro {
             state: TotoroState::Relaxed,
             relax_duration: 2.0,
             saw_player_last: 0.0,
-            drown_sfx_id: None
+            drown_sfx: None,
+            spawner: None,
+            animator: TotoroAnimator::new(idle_phase),
+            needs,
+            activity: Activity::Wander,
+            breath: MAX_BREATH,
+            last_damage_source: None
         }
     }
+
+    //Turns to face the player and hops in surprise, right before running away
+    pub fn startle(&mut self, idx: usize, player_position: &glm::TVec3<f32>, elapsed_time: f32, events: &mut TotoroEventQueue) {
+        self.forward = {
+            let mut f = player_position - self.position;
+            f.z = 0.0;
+            glm::normalize(&f)
+        };
+        self.velocity = glm::vec3(0.0, 0.0, 3.0);
+        self.state = TotoroState::PrePanicking;
+        self.state_timer = elapsed_time;
+        events.push(TotoroEvent::Startled { idx, position: self.position });
+    }
+
+    //Applies damage after this Totoro's size-based resistance and returns how much was actually dealt
+    pub fn damage(&mut self, source: DamageSource, amount: f32) -> f32 {
+        let dealt = f32::min(amount * source.size_multiplier(self.scale), f32::max(self.health, 0.0));
+        self.health -= dealt;
+        self.last_damage_source = Some(source);
+        dealt
+    }
 }
 
 impl SphereCollider for Totoro {
@@ -166,38 +305,275 @@ impl SphereCollider for Totoro {
     }
 }
 
-pub struct Gadget {
-    pub energy_remaining: f32,
-    pub pose: Posef,
-    pub entity_index: usize,
-    pub current_type: GadgetType
+pub struct TotoroSpawner {
+    pub position: glm::TVec3<f32>,
+    pub radius: f32,                    //Totoros are spawned within this distance of position
+    pub max_population: u32,
+    pub respawn_delay: f32,             //Seconds between spawns while the population is below max_population
+    pub min_scale: f32,
+    pub max_scale: f32,
+    pub population: u32,                //Number of living Totoros from this spawner. Recounted every frame
+    pub respawn_timer: f32
 }
 
-impl Gadget {
-    pub const MAX_ENERGY: f32 = 100.0;
+impl TotoroSpawner {
+    pub const COLLISION_RADIUS: f32 = 0.4;
+
+    pub fn new(position: glm::TVec3<f32>) -> Self {
+        let respawn_delay = 10.0;
+        TotoroSpawner {
+            position,
+            radius: 5.0,
+            max_population: 5,
+            respawn_delay,
+            min_scale: Totoro::DEFAULT_MIN_SCALE,
+            max_scale: Totoro::DEFAULT_MAX_SCALE,
+            population: 0,
+            respawn_timer: respawn_delay        //So that the first Totoro appears immediately
+        }
+    }
+
+    //Creates a Totoro at a random point within the spawn radius, with a scale drawn from this spawner's distribution
+    pub fn spawn_totoro(&self, terrain: &Terrain, creation_time: f32, rng: &mut GameRng) -> Totoro {
+        let angle = rng.random_f32(RngStream::Spawning) * glm::two_pi::<f32>();
+        let distance = self.radius * f32::sqrt(rng.random_f32(RngStream::Spawning));
+        let mut position = self.position + glm::vec3(distance * f32::cos(angle), distance * f32::sin(angle), 0.0);
+
+        //Drop the Totoro onto whatever terrain is below the chosen point
+        let ray = Ray {
+            origin: position + glm::vec3(0.0, 0.0, 10.0),
+            direction: glm::vec3(0.0, 0.0, -1.0)
+        };
+        if let Some(collision) = ray_hit_terrain(terrain, &ray) {
+            position = collision.point;
+        }
+
+        let scale = rng.ranged_f32(RngStream::Spawning, self.min_scale, self.max_scale);
+        Totoro::new(position, scale, creation_time, rng)
+    }
+}
+
+impl SphereCollider for TotoroSpawner {
+    fn sphere(&self) -> Sphere {
+        Sphere {
+            focus: self.position,
+            radius: Self::COLLISION_RADIUS
+        }
+    }
+}
+
+//A vertical cylinder of water, described by the center of its surface
+//Collision geometry carries no material information, so water is placed by hand in the level editor
+pub struct WaterVolume {
+    pub position: glm::TVec3<f32>,
+    pub radius: f32,
+    pub depth: f32
 }
 
-#[derive(Copy, Clone, Debug, Hash, EnumCount, PartialEq, Eq)]
-pub enum GadgetType {
-    Net,
-    StickyHand,
-    WaterCannon
+impl WaterVolume {
+    pub const COLLISION_RADIUS: f32 = 0.4;
+
+    pub fn new(position: glm::TVec3<f32>) -> Self {
+        WaterVolume {
+            position,
+            radius: 3.0,
+            depth: 2.0
+        }
+    }
+
+    pub fn contains(&self, point: &glm::TVec3<f32>) -> bool {
+        let horizontal_distance = glm::length(&glm::vec2(point.x - self.position.x, point.y - self.position.y));
+        horizontal_distance < self.radius && point.z <= self.position.z && point.z >= self.position.z - self.depth
+    }
 }
 
-impl GadgetType {
-    //I hate Rust
-    pub fn from_usize(i: usize) -> Self {
-        match i {
-            0 => { GadgetType::Net }
-            1 => { GadgetType::StickyHand }
-            2 => { GadgetType::WaterCannon }
-            _ => { panic!("{} is out of range", i); }
+impl SphereCollider for WaterVolume {
+    fn sphere(&self) -> Sphere {
+        Sphere {
+            focus: self.position,
+            radius: Self::COLLISION_RADIUS
         }
     }
 }
 
-#[derive(Debug)]
-pub enum StickData {
-    Left(glm::TVec3<f32>),
-    Right(glm::TVec3<f32>)
-}
\ No newline at end of file
+//Floating orb that refills the energy of every gadget the player is holding
+pub struct EnergyPickup {
+    pub position: glm::TVec3<f32>,
+    pub amount: f32,                //Fraction of each gadget's capacity that is restored
+    pub respawn_delay: f32,
+    pub cooldown: f32               //Seconds until the pickup can be collected again
+}
+
+impl EnergyPickup {
+    pub const COLLISION_RADIUS: f32 = 0.3;
+    pub const PICKUP_RADIUS: f32 = 0.5;
+
+    pub fn new(position: glm::TVec3<f32>) -> Self {
+        EnergyPickup {
+            position,
+            amount: 0.5,
+            respawn_delay: 10.0,
+            cooldown: 0.0
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.cooldown <= 0.0
+    }
+
+    //Whether the player's body, represented by a line segment from head to feet, is touching the pickup
+    pub fn touches(&self, segment: &LineSegment) -> bool {
+        distance_to_segment(&self.position, segment) < Self::PICKUP_RADIUS
+    }
+}
+
+fn distance_to_segment(point: &glm::TVec3<f32>, segment: &LineSegment) -> f32 {
+    let along = segment.p1 - segment.p0;
+    let length_squared = glm::dot(&along, &along);
+    let t = if length_squared > 0.0 { f32::clamp(glm::dot(&(point - segment.p0), &along) / length_squared, 0.0, 1.0) }
+            else { 0.0 };
+    glm::distance(&(segment.p0 + along * t), point)
+}
+
+impl SphereCollider for EnergyPickup {
+    fn sphere(&self) -> Sphere {
+        Sphere {
+            focus: self.position,
+            radius: Self::COLLISION_RADIUS
+        }
+    }
+}
+
+//Spherical region that hurts the player while they're inside it, such as lava or spikes
+pub struct HazardRegion {
+    pub position: glm::TVec3<f32>,
+    pub radius: f32,
+    pub damage_per_second: f32
+}
+
+impl HazardRegion {
+    pub const COLLISION_RADIUS: f32 = 0.3;
+
+    pub fn new(position: glm::TVec3<f32>) -> Self {
+        HazardRegion {
+            position,
+            radius: 2.0,
+            damage_per_second: 40.0
+        }
+    }
+
+    pub fn touches(&self, segment: &LineSegment) -> bool {
+        distance_to_segment(&self.position, segment) < self.radius
+    }
+}
+
+impl SphereCollider for HazardRegion {
+    fn sphere(&self) -> Sphere {
+        Sphere {
+            focus: self.position,
+            radius: Self::COLLISION_RADIUS
+        }
+    }
+}
+
+//Spherical region that gives sounds heard inside it the reverb of a kind of space, such as a cave
+pub struct ReverbZone {
+    pub position: glm::TVec3<f32>,
+    pub radius: f32,
+    pub preset: ReverbPreset
+}
+
+impl ReverbZone {
+    pub const COLLISION_RADIUS: f32 = 0.3;
+
+    pub fn new(position: glm::TVec3<f32>, preset: ReverbPreset) -> Self {
+        ReverbZone {
+            position,
+            radius: 10.0,
+            preset
+        }
+    }
+
+    pub fn contains(&self, point: &glm::TVec3<f32>) -> bool {
+        glm::distance(&self.position, point) < self.radius
+    }
+}
+
+impl SphereCollider for ReverbZone {
+    fn sphere(&self) -> Sphere {
+        Sphere {
+            focus: self.position,
+            radius: Self::COLLISION_RADIUS
+        }
+    }
+}
+
+//Editor-placed point the player's feet are put on after dying
+pub struct RespawnPoint {
+    pub position: glm::TVec3<f32>
+}
+
+impl RespawnPoint {
+    pub const COLLISION_RADIUS: f32 = 0.3;
+}
+
+impl SphereCollider for RespawnPoint {
+    fn sphere(&self) -> Sphere {
+        Sphere {
+            focus: self.position,
+            radius: Self::COLLISION_RADIUS
+        }
+    }
+}
+
+//Advances health regeneration and the death sequence
+//The player is moved to their respawn position once the view has faded to black
+pub fn update_player_life(world_state: &mut WorldState, delta_time: f32) {
+    match world_state.player.life_state {
+        LifeState::Alive => {
+            let player = &mut world_state.player;
+            player.time_since_damage += delta_time;
+            if player.time_since_damage >= PLAYER_REGENERATION_DELAY {
+                player.health = f32::min(player.health + PLAYER_REGENERATION_RATE * delta_time, PLAYER_MAX_HEALTH);
+            }
+        }
+        LifeState::Dying(t) => {
+            let t = t + delta_time;
+            if t >= DEATH_FADE_TIME {
+                let target = choose_respawn_position(world_state);
+                respawn_player(&mut world_state.player, target);
+            } else {
+                world_state.player.life_state = LifeState::Dying(t);
+            }
+        }
+        LifeState::Respawning(t) => {
+            let t = t + delta_time;
+            world_state.player.life_state = if t >= RESPAWN_FADE_TIME { LifeState::Alive }
+                                            else { LifeState::Respawning(t) };
+        }
+    }
+}
+
+//Deletes a spawner and orphans its Totoros, so that they're saved with the level instead of vanishing along with their spawner
+pub fn delete_totoro_spawner(world_state: &mut WorldState, idx: usize) {
+    let spawner = world_state.totoro_spawners.reference(idx);
+    world_state.totoro_spawners.delete(idx);
+    for i in 0..world_state.totoros.len() {
+        if let Some(totoro) = world_state.totoros.get_mut_element(i) {
+            if totoro.spawner == Some(spawner) {
+                totoro.spawner = None;
+            }
+        }
+    }
+}
+
+pub fn delete_point_of_interest(world_state: &mut WorldState, idx: usize) {
+    world_state.points_of_interest.delete(idx);
+    for i in 0..world_state.totoros.len() {
+        if let Some(totoro) = world_state.totoros.get_mut_element(i) {
+            if totoro.activity == Activity::Visit(idx) {
+                totoro.activity = Activity::Wander;
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use ozy::collision::{Ray, Terrain, ray_hit_terrain};
+use crate::gamestate::{MoveState, Player};
+use crate::structs::Configuration;
+
+//Comfort options for moving and turning the player, for players who get sick from smooth artificial motion
+
+pub const TELEPORT_STICK_THRESHOLD: f32 = 0.6;      //Pushing the stick forward past this starts aiming a teleport
+const TELEPORT_SPEED: f32 = 10.0;                   //Launch speed of the arc in meters per second
+const TELEPORT_GRAVITY: f32 = 9.8;                  //Lower than the player's gravity so the arc reaches further
+const TELEPORT_STEP: f32 = 0.05;                    //Seconds of flight between arc points
+const TELEPORT_MAX_TIME: f32 = 2.0;
+const MIN_WALKABLE_NORMAL: f32 = 0.7;               //Z component of a triangle's normal needed to land on it
+
+const SNAP_TURN_THRESHOLD: f32 = 0.7;               //Stick deflection that triggers a snap turn
+const SNAP_TURN_REARM: f32 = 0.3;                   //The stick has to come back inside this before another snap turn
+const TURN_DEADZONE: f32 = 0.15;
+
+pub const VIGNETTE_FULL_SPEED: f32 = 5.0;           //Horizontal speed in meters per second at which the vignette is strongest
+const VIGNETTE_MAX_STRENGTH: f32 = 0.9;
+const VIGNETTE_FADE_SPEED: f32 = 4.0;               //Strength units per second
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveMode {
+    Smooth,
+    Teleport
+}
+
+impl Default for MoveMode {
+    fn default() -> Self { MoveMode::Smooth }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TurnMode {
+    Snap,
+    Smooth,
+    Off
+}
+
+impl Default for TurnMode {
+    fn default() -> Self { TurnMode::Snap }
+}
+
+pub struct LocomotionSettings {
+    pub move_mode: MoveMode,
+    pub turn_mode: TurnMode,
+    pub snap_turn_degrees: f32,
+    pub smooth_turn_speed: f32,                     //Degrees per second at full deflection
+    pub comfort_vignette: bool
+}
+
+impl LocomotionSettings {
+    pub fn from_config(config: &Configuration) -> Self {
+        let move_mode = match config.int_options.get(Configuration::TELEPORT_LOCOMOTION) {
+            Some(flag) if *flag != 0 => { MoveMode::Teleport }
+            _ => { MoveMode::Smooth }
+        };
+        let turn_mode = match config.int_options.get(Configuration::TURN_MODE) {
+            Some(1) => { TurnMode::Smooth }
+            Some(2) => { TurnMode::Off }
+            _ => { TurnMode::Snap }
+        };
+        let comfort_vignette = match config.int_options.get(Configuration::COMFORT_VIGNETTE) {
+            Some(flag) => { *flag != 0 }
+            None => { false }
+        };
+
+        let float_or = |key: &str, default: f32| {
+            match config.float_options.get(key) {
+                Some(v) => { *v }
+                None => { default }
+            }
+        };
+
+        LocomotionSettings {
+            move_mode,
+            turn_mode,
+            snap_turn_degrees: float_or(Configuration::SNAP_TURN_DEGREES, 45.0),
+            smooth_turn_speed: float_or(Configuration::SMOOTH_TURN_SPEED, 90.0),
+            comfort_vignette
+        }
+    }
+
+    pub fn save_to_config(&self, config: &mut Configuration) {
+        let turn_mode = match self.turn_mode {
+            TurnMode::Snap => { 0 }
+            TurnMode::Smooth => { 1 }
+            TurnMode::Off => { 2 }
+        };
+        config.int_options.insert(String::from(Configuration::TELEPORT_LOCOMOTION), (self.move_mode == MoveMode::Teleport) as u32);
+        config.int_options.insert(String::from(Configuration::TURN_MODE), turn_mode);
+        config.int_options.insert(String::from(Configuration::COMFORT_VIGNETTE), self.comfort_vignette as u32);
+        config.float_options.insert(String::from(Configuration::SNAP_TURN_DEGREES), self.snap_turn_degrees);
+        config.float_options.insert(String::from(Configuration::SMOOTH_TURN_SPEED), self.smooth_turn_speed);
+    }
+}
+
+pub struct TeleportArc {
+    pub points: Vec<glm::TVec3<f32>>,
+    pub target: Option<glm::TVec3<f32>>             //Where the player lands, if the arc ends on walkable ground
+}
+
+//Traces a projectile arc from the hand until it hits the terrain
+pub fn teleport_arc(terrain: &Terrain, origin: &glm::TVec3<f32>, direction: &glm::TVec3<f32>) -> TeleportArc {
+    let velocity = direction * TELEPORT_SPEED;
+    let gravity = glm::vec3(0.0, 0.0, -TELEPORT_GRAVITY);
+    let position_at = |t: f32| { origin + velocity * t + 0.5 * gravity * t * t };
+
+    let mut points = vec![*origin];
+    let mut t = 0.0;
+    while t < TELEPORT_MAX_TIME {
+        let start = position_at(t);
+        let end = position_at(t + TELEPORT_STEP);
+        let segment = end - start;
+        let segment_length = glm::length(&segment);
+
+        let ray = Ray {
+            origin: start,
+            direction: segment / segment_length
+        };
+        if let Some(collision) = ray_hit_terrain(terrain, &ray) {
+            if glm::distance(&start, &collision.point) <= segment_length {
+                points.push(collision.point);
+                let normal = terrain.face_normals[collision.triangle_index];
+                let target = if normal.z >= MIN_WALKABLE_NORMAL { Some(collision.point) }
+                             else { None };
+                return TeleportArc {
+                    points,
+                    target
+                };
+            }
+        }
+
+        points.push(end);
+        t += TELEPORT_STEP;
+    }
+
+    TeleportArc {
+        points,
+        target: None
+    }
+}
+
+//Moves tracking space so that the player's feet land on the target
+pub fn teleport_player(player: &mut Player, target: &glm::TVec3<f32>) {
+    let feet = player.tracked_segment.p1;
+    player.tracking_position += target - feet;
+    player.tracking_velocity = glm::zero();
+    player.movement_state = MoveState::Falling;
+    player.grips = [None; 2];
+}
+
+//Rotates tracking space about the vertical axis through the player's head, so the view turns in place
+pub fn turn_player(player: &mut Player, angle: f32) {
+    let head = player.tracked_segment.p0;
+    let pivot = glm::vec3(head.x, head.y, player.tracking_position.z);
+    player.tracking_position = pivot + glm::rotate_z_vec3(&(player.tracking_position - pivot), angle);
+    player.tracking_rotation += angle;
+}
+
+pub struct TurnState {
+    snap_armed: bool,
+    pub smooth_turning: bool                        //Whether the player was smooth turned this frame
+}
+
+impl TurnState {
+    pub fn new() -> Self {
+        TurnState {
+            snap_armed: true,
+            smooth_turning: false
+        }
+    }
+
+    //Returns the angle in radians to turn the player by this frame
+    //Pushing the stick right turns clockwise when viewed from above
+    pub fn update(&mut self, settings: &LocomotionSettings, stick_x: f32, delta_time: f32) -> f32 {
+        self.smooth_turning = false;
+        match settings.turn_mode {
+            TurnMode::Snap => {
+                if f32::abs(stick_x) < SNAP_TURN_REARM {
+                    self.snap_armed = true;
+                } else if self.snap_armed && f32::abs(stick_x) > SNAP_TURN_THRESHOLD {
+                    self.snap_armed = false;
+                    return -f32::signum(stick_x) * f32::to_radians(settings.snap_turn_degrees);
+                }
+                0.0
+            }
+            TurnMode::Smooth => {
+                if f32::abs(stick_x) < TURN_DEADZONE {
+                    return 0.0;
+                }
+                self.smooth_turning = true;
+                -stick_x * f32::to_radians(settings.smooth_turn_speed) * delta_time
+            }
+            TurnMode::Off => { 0.0 }
+        }
+    }
+}
+
+//Darkens the edges of the view while the player is being moved artificially
+pub struct ComfortVignette {
+    pub strength: f32
+}
+
+impl ComfortVignette {
+    pub fn new() -> Self {
+        ComfortVignette {
+            strength: 0.0
+        }
+    }
+
+    //Intensity is how strongly the player is being moved, from zero to one
+    pub fn update(&mut self, settings: &LocomotionSettings, intensity: f32, delta_time: f32) {
+        let target = if settings.comfort_vignette { glm::clamp_scalar(intensity, 0.0, 1.0) * VIGNETTE_MAX_STRENGTH }
+                     else { 0.0 };
+        let step = VIGNETTE_FADE_SPEED * delta_time;
+        if self.strength < target {
+            self.strength = f32::min(self.strength + step, target);
+        } else {
+            self.strength = f32::max(self.strength - step, target);
+        }
+    }
+}
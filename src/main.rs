@@ -6,11 +6,21 @@ extern crate tinyfiledialogs as tfd;
 
 extern crate ozy_engine as ozy;
 
+mod ai;
+mod animation;
 mod audio;
+mod climbing;
+mod damage;
+mod desktop;
+mod gadgets;
+mod gamepad;
 mod gamestate;
+mod locomotion;
+mod music;
 mod network;
 mod structs;
 mod render;
+mod rng;
 mod routines;
 mod traits;
 mod xrutil;
@@ -20,8 +30,8 @@ use glfw::{Action, Context, Key, SwapInterval, Window, WindowEvent, WindowHint,
 use gl::types::*;
 use imgui::{ColorEdit, DrawCmd, EditableColor, Font, FontAtlasRefMut, FontConfig, FontSource, MenuItem, Slider, TextureId};
 use noise::NoiseFn;
-use std::collections::HashMap;
-use std::fs::{File, read_dir};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
 use std::io::{ErrorKind, Write};
 use std::path::Path;
 use std::process::exit;
@@ -31,7 +41,6 @@ use std::os::raw::c_void;
 use std::str::FromStr;
 use std::sync::mpsc;
 use std::time::{Instant};
-use strum::EnumCount;
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use ozy::{glutil, io};
 use ozy::render::{Framebuffer, RenderTarget, TextureKeeper};
@@ -39,11 +48,26 @@ use ozy::routines::uniform_scale;
 use ozy::structs::OptionVec;
 use ozy::collision::*;
 
-use crate::audio::{AudioCommand, SoundEffectRequest};
+use crate::ai::{Activity, PoiKind, PointOfInterest, TimeOfDay, SATISFIED_THRESHOLD, choose_activity};
+use crate::audio::{AudioCommand, AudioEvent, Bus, Mixer, ReverbPreset, SfxPriority, SoundEffectRequest};
+use crate::audio::attach::{SoundAnchor, SoundAttachments};
+use crate::audio::environment::{self, SoundOcclusion};
+use crate::audio::spectrum::{self, SpectrumBinding, SpectrumBindings};
+use crate::audio::voices::TotoroVoices;
+use crate::climbing::{GripRequest, HandHistory};
+use crate::desktop::DesktopPlayer;
+use crate::gamepad::{Gamepad, GamepadMappings};
+use crate::damage::{DamageSource, TotoroEvent, TotoroEventQueue};
+use crate::gadgets::{GadgetContext, GadgetHand, GadgetLoader, GadgetRenderContext, GadgetSettings, Hand, TriggerInput};
+use crate::gadgets::energy::Recharge;
+use crate::gadgets::wheel::WheelInput;
 use crate::gamestate::*;
+use crate::locomotion::{ComfortVignette, LocomotionSettings, MoveMode, TeleportArc, TurnMode, TurnState};
+use crate::music::MusicDirector;
 use crate::structs::*;
 use crate::routines::*;
 use crate::render::{PointLight, MAX_POINT_LIGHTS, NEAR_DISTANCE, FAR_DISTANCE, STANDARD_TRANSFORM_ATTRIBUTE, STANDARD_HIGHLIGHTED_ATTRIBUTE, DEBUG_TRANSFORM_ATTRIBUTE, DEBUG_COLOR_ATTRIBUTE, DEBUG_HIGHLIGHTED_ATTRIBUTE};
+use crate::rng::{GameRng, RngStream};
 use crate::render::{CascadedShadowMap, FragmentFlag, PostEffectFlag, RenderEntity, SceneData, ViewData};
 use crate::traits::SphereCollider;
 use crate::network::NetworkCommand;
@@ -54,6 +78,8 @@ use winapi::{um::{winuser::GetWindowDC, wingdi::wglGetCurrentContext}};
 const EPSILON: f32 = 0.00001;
 const VELOCITY_CAP: f32 = 50.0;        //m/s
 const ACCELERATION_GRAVITY: f32 = 20.0;        //20.0 m/s^2
+const TOTORO_EVENT_LOG_LENGTH: usize = 8;
+const DROWN_GAIN: f32 = 500.0;
 
 //Default texture parameters for a 2D image texture
 const DEFAULT_TEX_PARAMS: [(GLenum, GLenum); 4] = [  
@@ -77,6 +103,13 @@ fn queue_debug_sphere(sphere_queue: &mut Vec<DebugSphere>, position: glm::TVec3<
     sphere_queue.push(s);
 }
 
+fn log_totoro_event(log: &mut VecDeque<String>, message: String) {
+    if log.len() >= TOTORO_EVENT_LOG_LENGTH {
+        log.pop_front();
+    }
+    log.push_back(message);
+}
+
 fn LUT_pixels_from_flags(flags: &[bool]) -> Vec<u8> {
     let triangle_count = flags.len();
     let width = get_lookup_texture_pixels(triangle_count);
@@ -94,7 +127,7 @@ fn main() {
     let Z_UP = glm::vec3(0.0, 0.0, 1.0);
 
     //Initialize the configuration data
-    let config = {
+    let mut config = {
         //If we can't read from the config file, we create one with the default values
         match Configuration::from_file(Configuration::CONFIG_FILEPATH) {
             Some(cfg) => { cfg }
@@ -104,7 +137,8 @@ fn main() {
                 let mut string_options = HashMap::new();
                 int_options.insert(String::from(Configuration::WINDOWED_WIDTH), 1280);
                 int_options.insert(String::from(Configuration::WINDOWED_HEIGHT), 720);
-                float_options.insert(String::from(Configuration::BGM_VOLUME), 10.0);
+                float_options.insert(String::from(Configuration::MASTER_VOLUME), 100.0);
+                float_options.insert(String::from(Configuration::BGM_VOLUME), audio::Mixer::DEFAULT_VOLUME);
                 string_options.insert(String::from(Configuration::LEVEL_NAME), String::from("teaching"));
                 string_options.insert(String::from(Configuration::MUSIC_NAME), String::from(audio::DEFAULT_BGM_PATH));
                 let c = Configuration {
@@ -118,6 +152,29 @@ fn main() {
         }
     };
 
+    //Pick the gameplay RNG seed
+    //Priority is --seed on the command line, then the config file, then a fresh random seed
+    let rng_seed = {
+        let args: Vec<String> = std::env::args().collect();
+        let mut cli_seed = None;
+        for i in 0..args.len() {
+            if args[i] == "--seed" {
+                match args.get(i + 1).map(|s| s.parse::<u32>()) {
+                    Some(Ok(seed)) => { cli_seed = Some(seed); }
+                    Some(Err(e)) => { println!("Invalid --seed argument: {}", e); }
+                    None => { println!("--seed requires a value"); }
+                }
+            }
+        }
+
+        match (cli_seed, config.int_options.get(Configuration::RNG_SEED)) {
+            (Some(seed), _) => { seed }
+            (None, Some(seed)) => { *seed }
+            (None, None) => { rand::random::<u32>() }
+        }
+    };
+    println!("Gameplay RNG seed: {}", rng_seed);
+
 
     //Do a bunch of OpenXR initialization
     //Initialize the OpenXR instance
@@ -243,16 +300,22 @@ fn main() {
     let left_grip_pose_path = xrutil::make_path(&xr_instance, xrutil::LEFT_GRIP_POSE);
     let left_aim_pose_path = xrutil::make_path(&xr_instance, xrutil::LEFT_AIM_POSE);
     let left_trigger_path = xrutil::make_path(&xr_instance, xrutil::LEFT_TRIGGER);
+    let left_squeeze_float_path = xrutil::make_path(&xr_instance, xrutil::LEFT_SQUEEZE_FLOAT);
+    let left_squeeze_click_path = xrutil::make_path(&xr_instance, xrutil::LEFT_SQUEEZE_CLICK);
     let left_b_path = xrutil::make_path(&xr_instance, xrutil::LEFT_B_BUTTON);
     let left_y_path = xrutil::make_path(&xr_instance, xrutil::LEFT_Y_BUTTON);
     let left_stick_vector_path = xrutil::make_path(&xr_instance, xrutil::LEFT_STICK_VECTOR2);
     let left_trackpad_vector_path = xrutil::make_path(&xr_instance, xrutil::LEFT_TRACKPAD_VECTOR2);
     let left_trackpad_click_path = xrutil::make_path(&xr_instance, xrutil::LEFT_TRACKPAD_CLICK);
     let right_trigger_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_TRIGGER_FLOAT);
+    let right_squeeze_float_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_SQUEEZE_FLOAT);
+    let right_squeeze_click_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_SQUEEZE_CLICK);
     let right_grip_pose_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_GRIP_POSE);
     let right_aim_pose_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_AIM_POSE);
     let right_trackpad_force_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_TRACKPAD_FORCE);
     let right_trackpad_click_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_TRACKPAD_CLICK);
+    let right_stick_vector_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_STICK_VECTOR2);
+    let right_trackpad_vector_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_TRACKPAD_VECTOR2);
     let right_a_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_A_BUTTON_BOOL);
     let right_b_path = xrutil::make_path(&xr_instance, xrutil::RIGHT_B_BUTTON);
 
@@ -268,9 +331,12 @@ fn main() {
     let player_move_action = xrutil::make_action::<xr::Vector2f>(&left_hand_subaction_path, &xr_standard_actionset, "player_move", "Player movement");
     let left_gadget_action = xrutil::make_action::<f32>(&left_hand_subaction_path, &xr_standard_actionset, "left_hand_gadget", "Left hand gadget");
     let right_gadget_action = xrutil::make_action::<f32>(&right_hand_subaction_path, &xr_standard_actionset, "right_hand_gadget", "Right hand gadget");
+    let left_grab_action = xrutil::make_action::<f32>(&left_hand_subaction_path, &xr_standard_actionset, "left_hand_grab", "Left hand grab");
+    let right_grab_action = xrutil::make_action::<f32>(&right_hand_subaction_path, &xr_standard_actionset, "right_hand_grab", "Right hand grab");
     let left_switch_gadget = xrutil::make_action::<bool>(&left_hand_subaction_path, &xr_standard_actionset, "left_switch_gadget", "Left hand switch gadget");    
     let go_home_action = xrutil::make_action::<bool>(&right_hand_subaction_path, &xr_standard_actionset, "item_menu", "Interact with item menu");
     let right_switch_gadget = xrutil::make_action::<bool>(&right_hand_subaction_path, &xr_standard_actionset, "right_switch_gadget", "Right hand switch gadget");
+    let right_wheel_action = xrutil::make_action::<xr::Vector2f>(&right_hand_subaction_path, &xr_standard_actionset, "right_gadget_wheel", "Right hand gadget wheel");
 
 
     //Suggest interaction profile bindings
@@ -305,12 +371,18 @@ fn main() {
         ];
         let unique_bools = [&index_bools, &vive_bools, &oculus_bools];
 
-        let index_vec2s = [(&player_move_action, left_stick_vector_path)];
-        let vive_vec2s = [(&player_move_action, left_trackpad_vector_path)];
-        let oculus_vec2s = [(&player_move_action, left_stick_vector_path)];
+        let index_vec2s = [(&player_move_action, left_stick_vector_path), (&right_wheel_action, right_stick_vector_path)];
+        let vive_vec2s = [(&player_move_action, left_trackpad_vector_path), (&right_wheel_action, right_trackpad_vector_path)];
+        let oculus_vec2s = [(&player_move_action, left_stick_vector_path), (&right_wheel_action, right_stick_vector_path)];
         let unique_vec2s = [&index_vec2s, &vive_vec2s, &oculus_vec2s];
 
-        let bindings_count = 10;
+        //The Vive wands only have a digital grip button
+        let index_floats = [(&left_grab_action, left_squeeze_float_path), (&right_grab_action, right_squeeze_float_path)];
+        let vive_floats = [(&left_grab_action, left_squeeze_click_path), (&right_grab_action, right_squeeze_click_path)];
+        let oculus_floats = [(&left_grab_action, left_squeeze_float_path), (&right_grab_action, right_squeeze_float_path)];
+        let unique_floats = [&index_floats, &vive_floats, &oculus_floats];
+
+        let bindings_count = 13;
         let mut index_bindings = Vec::with_capacity(bindings_count);
         let mut vive_bindings = Vec::with_capacity(bindings_count);
         let mut oculus_bindings = Vec::with_capacity(bindings_count);
@@ -345,6 +417,11 @@ fn main() {
                 push_binding(binding_arrays[i], *binding);
             }
         }
+        for i in 0..unique_floats.len() {
+            for binding in unique_floats[i] {
+                push_binding(binding_arrays[i], *binding);
+            }
+        }
 
         for i in 0..interaction_profiles.len() {
             xrutil::suggest_bindings(inst, interaction_profiles[i], binding_arrays[i]);
@@ -575,6 +652,15 @@ fn main() {
     let skybox_program = compile_shader_or_crash(&[(gl::VERTEX_SHADER, "shaders/skybox.vert"), (gl::FRAGMENT_SHADER, "shaders/skybox.frag")]);
     let imgui_program = compile_shader_or_crash(&[(gl::VERTEX_SHADER, "shaders/ui/imgui.vert"), (gl::FRAGMENT_SHADER, "shaders/ui/imgui.frag")]);
     let postfx_program = compile_shader_or_crash(&[(gl::COMPUTE_SHADER, "shaders/postfx.comp")]);
+    let particle_program = compile_shader_or_crash(&[(gl::VERTEX_SHADER, "shaders/particle.vert"), (gl::FRAGMENT_SHADER, "shaders/particle.frag")]);
+    let vignette_program = compile_shader_or_crash(&[(gl::VERTEX_SHADER, "shaders/vignette.vert"), (gl::FRAGMENT_SHADER, "shaders/vignette.frag")]);
+
+    //The vignette's fullscreen triangle doesn't read any vertex attributes, but a vao still has to be bound to draw
+    let vignette_vao = unsafe {
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        vao
+    };
     
     //Initialize default framebuffer
     let mut default_framebuffer = Framebuffer {
@@ -632,6 +718,10 @@ fn main() {
         io.key_map[imgui::Key::X as usize] = Key::X as u32;
         io.key_map[imgui::Key::Y as usize] = Key::Y as u32;
         io.key_map[imgui::Key::Z as usize] = Key::Z as u32;
+
+        //The gamepad can take over the gui when it's put in menu mode
+        io.config_flags |= imgui::ConfigFlags::NAV_ENABLE_GAMEPAD;
+        io.backend_flags |= imgui::BackendFlags::HAS_GAMEPAD;
     }
 
     //Create and upload Dear IMGUI font atlas
@@ -794,9 +884,6 @@ fn main() {
     let mut world_from_tracking = glm::identity();
     let mut tracking_from_world;
 
-    let mut left_sticky_grabbing = false;
-    let mut right_sticky_grabbing = false;
-
     let mut world_state = {
         let level_name = match config.string_options.get(Configuration::LEVEL_NAME) {
             Some(name) => { name }
@@ -853,6 +940,13 @@ fn main() {
             player: Player::new(glm::zero(), glm::zero()),
             freecam: camera,
             totoros: EntityList::with_capacity(64),
+            totoro_spawners: EntityList::new(),
+            points_of_interest: EntityList::new(),
+            water_volumes: EntityList::new(),
+            energy_pickups: EntityList::new(),
+            hazard_regions: EntityList::new(),
+            respawn_points: EntityList::new(),
+            reverb_zones: EntityList::new(),
             collision,
             opaque_terrain_indices: Vec::new(),
             transparent_terrain_indices: Vec::new(),
@@ -860,7 +954,8 @@ fn main() {
             skybox_strings: Vec::new(),
             level_name: String::new(),
             active_skybox_index: 0,
-            delta_timescale: 1.0
+            delta_timescale: 1.0,
+            rng: GameRng::new(rng_seed)
         };
 
         //Load the scene data from the level file
@@ -890,46 +985,38 @@ fn main() {
         scene_data.transparent_entities.insert(re)
     };
 
-    //Load gadget models
-    let mut gadget_model_map = {
-        let sp_entity = RenderEntity::from_ozy("models/sphere.ozy", standard_program, 2, STANDARD_TRANSFORM_ATTRIBUTE, &mut texture_keeper, &DEFAULT_TEX_PARAMS);
-        let wand_entity = RenderEntity::from_ozy("models/wand.ozy", standard_program, 2, STANDARD_TRANSFORM_ATTRIBUTE, &mut texture_keeper, &DEFAULT_TEX_PARAMS);
-        let stick_entity = RenderEntity::from_ozy("models/stick.ozy", standard_program, 2, STANDARD_TRANSFORM_ATTRIBUTE, &mut texture_keeper, &DEFAULT_TEX_PARAMS);
-        let sp_index = scene_data.opaque_entities.insert(sp_entity);
-        let wand_index = scene_data.opaque_entities.insert(wand_entity);
-        let stick_index = scene_data.opaque_entities.insert(stick_entity);
-
-        let mut h = HashMap::with_capacity(3);
-        h.insert(GadgetType::Net, sp_index);
-        h.insert(GadgetType::StickyHand, stick_index);
-        h.insert(GadgetType::WaterCannon, wand_index);
-        h
+    //Gadget state setup
+    let mut gadget_hands = {
+        let mut loader = GadgetLoader {
+            scene_data: &mut scene_data,
+            texture_keeper: &mut texture_keeper,
+            program: standard_program,
+            particle_program,
+            debug_program,
+            models: HashMap::new()
+        };
+        [
+            GadgetHand::new(Hand::Left, &mut loader, gadgets::net::Net::NAME),
+            GadgetHand::new(Hand::Right, &mut loader, gadgets::water_cannon::WaterCannon::NAME)
+        ]
     };
 
-    //Gadget state setup
-    let mut left_hand_gadget = GadgetType::Net;
-    let mut right_hand_gadget = GadgetType::WaterCannon;
-
-    //Water gun state
-    const MAX_WATER_PRESSURE: f32 = 30.0;
-    let mut water_gun_force: glm::TVec3<f32> = glm::zero();
-    let mut infinite_ammo = false;
-    let mut remaining_water = Gadget::MAX_ENERGY;
-
-    //Water gun graphics data
-    let mut left_water_pillar_scale: glm::TVec3<f32> = glm::zero();
-    let mut right_water_pillar_scale: glm::TVec3<f32> = glm::zero();
-    let water_cylinder_path = "models/water_cylinder.ozy";
-    let water_cylinder_entity_index = scene_data.opaque_entities.insert(
-        RenderEntity::from_ozy(
-            water_cylinder_path,
-            standard_program,
-            2,
-            STANDARD_TRANSFORM_ATTRIBUTE,
-            &mut texture_keeper,
-            &DEFAULT_TEX_PARAMS
-        )
-    );
+    let mut hand_histories = [HandHistory::new(), HandHistory::new()];
+
+    //Locomotion comfort state
+    let mut locomotion_settings = LocomotionSettings::from_config(&config);
+    let mut turn_state = TurnState::new();
+    let mut comfort_vignette = ComfortVignette::new();
+    let mut teleport_arc: Option<TeleportArc> = None;
+
+    //Keyboard and mouse stand-ins for the headset and controllers
+    let mut desktop_player = DesktopPlayer::new();
+    let mut gamepad = Gamepad::new(GamepadMappings::from_config(&config));
+    let mut music_director = MusicDirector::new();
+
+    let mut gadget_settings = GadgetSettings::from_config(&config, gadget_hands[0].energy_rules());
+
+    let mut seed_string = format!("{}", rng_seed);
 
     //Set up global flags lol
     let mut is_fullscreen = false;
@@ -948,6 +1035,20 @@ fn main() {
     let mut viewing_player_spheres = false;
     let mut showing_shadow_atlas = false;
     let mut viewing_point_lights = false;
+    let mut viewing_totoro_spawners = false;
+    let mut viewing_points_of_interest = false;
+    let mut viewing_water_volumes = false;
+    let mut viewing_energy_pickups = false;
+    let mut viewing_hazard_regions = false;
+    let mut viewing_respawn_points = false;
+    let mut viewing_reverb_zones = false;
+    let mut session_stats = SessionStats::default();
+    let mut totoro_event_log = VecDeque::with_capacity(TOTORO_EVENT_LOG_LENGTH);
+    let mut totoro_events = TotoroEventQueue::new();
+    let audio_totoro_events = totoro_events.subscribe();
+    let ui_totoro_events = totoro_events.subscribe();
+    let mut new_poi_kind = 0;
+    let mut new_reverb_preset = 0;
     if let Some(_) = &xr_instance {
         hmd_pov = true;
         do_vsync = false;
@@ -960,38 +1061,28 @@ fn main() {
     let mut last_xr_render_time = xr::Time::from_nanos(0);
 
     //Init audio system
-    let mut bgm_volume = match config.float_options.get(Configuration::BGM_VOLUME) {
+    let mut master_volume = match config.float_options.get(Configuration::MASTER_VOLUME) {
         Some(v) => { *v }
-        None => { 10.0 }
+        None => { 100.0 }
     };
+    let mut mixer = Mixer::from_config(&config);
+    let mut mixer_changed = false;          //So the new volumes get saved on exit
+    let mut audio_devices: Vec<String> = Vec::new();
+    let mut audio_device: Option<String> = None;       //The device audio is playing on, if there is one
+    let mut audio_device_lost = false;
+    let mut preferred_audio_device = config.string_options.get(Configuration::AUDIO_DEVICE).map(|name| Configuration::unescape(name));
+    let mut audio_device_changed = false;
     let (audio_sender, audio_receiver) = mpsc::channel();
-    audio::audio_main(audio_receiver, &config);          //This spawns a thread to run the audio system
-    let mut next_named_sfx = 0;
+    let (audio_event_sender, audio_event_receiver) = mpsc::channel();
+    let audio_recording = audio::audio_main(audio_receiver, audio_event_sender, &config);          //This spawns a thread to run the audio system
+    let mut sound_attachments = SoundAttachments::new();
+    let mut sound_occlusion = SoundOcclusion::new();
+    let mut listener_reverb = None;
+    let mut music_bands = [0.0; spectrum::BAND_COUNT];
+    let mut spectrum_bindings = SpectrumBindings::new();
 
     //Load totoro sound effects
-    let yell_path = "sfx/totoro/yells";
-    let totoro_yell_paths = match read_dir(yell_path) {
-        Ok(iter) => {
-            let mut paths = Vec::new();
-            for entry in iter {
-                match entry {
-                    Ok(ent) => {
-                        let name = format!("{}/{}", yell_path, ent.file_name().into_string().unwrap());
-                        paths.push(name.clone());
-                        send_or_error(&audio_sender, AudioCommand::LoadSFX(name));
-                    }
-                    Err(e) => {
-                        tfd::message_box_ok("Audio error", &format!("Error reading sfx entry: {}", e), tfd::MessageBoxIcon::Error);
-                    }
-                }
-            }
-            paths
-        }
-        Err(e) => {
-            tfd::message_box_ok("Audio error", &format!("Error reading sfx directory: {}", e), tfd::MessageBoxIcon::Error);
-            Vec::new()
-        }
-    };
+    let totoro_voices = TotoroVoices::load(&audio_sender, "sfx/totoro/yells");
     let totoro_drowning_path = "sfx/totoro/drown.mp3";
     send_or_error(&audio_sender, AudioCommand::LoadSFX(String::from(totoro_drowning_path)));
 
@@ -1031,6 +1122,7 @@ fn main() {
             if imgui_io.delta_time > MAX_DELTA_TIME { MAX_DELTA_TIME }
             else { imgui_io.delta_time * world_state.delta_timescale }
         };
+        let frame_time = imgui_io.delta_time;       //Real seconds since the last frame, ignoring the timescale
         scene_data.elapsed_time += delta_time;
         frame_count += 1;
         let framerate = imgui_io.framerate;
@@ -1042,13 +1134,45 @@ fn main() {
             }
         }
 
+        //Poll the gamepad
+        gamepad.poll(&glfw);
+        let gamepad_controls = gamepad.controls();
+        if let (true, None) = (gamepad.desktop_toggled, &xr_instance) {
+            desktop_player.set_enabled(!desktop_player.enabled, &mut window, &mut world_state.freecam);
+        }
+        if gamepad.menu_toggled && gamepad.menu_mode {
+            do_imgui = true;
+        }
+
         //Get action states
-        let move_stick_state = xrutil::get_actionstate(&xr_session, &player_move_action);
-        let left_trigger_state = xrutil::get_actionstate(&xr_session, &left_gadget_action);
-        let left_switch_state = xrutil::get_actionstate(&xr_session, &left_switch_gadget);
-        let right_switch_state = xrutil::get_actionstate(&xr_session, &right_switch_gadget);
-        let right_trigger_state = xrutil::get_actionstate(&xr_session, &right_gadget_action);
-        let right_trackpad_force_state = xrutil::get_actionstate(&xr_session, &go_home_action);
+        //In desktop mode they come from the keyboard, mouse and gamepad instead
+        desktop_player.sync(&gamepad_controls);
+        let (move_stick_state, left_trigger_state, left_switch_state, right_switch_state, right_trigger_state,
+             right_trackpad_force_state, right_wheel_stick_state, left_grab_state, right_grab_state) = if desktop_player.enabled {
+            (
+                desktop_player.move_stick_state(),
+                desktop_player.trigger_state(0),
+                desktop_player.switch_state(0),
+                desktop_player.switch_state(1),
+                desktop_player.trigger_state(1),
+                desktop_player.respawn_state(),
+                None,
+                desktop_player.grab_state(),
+                desktop_player.grab_state()
+            )
+        } else {
+            (
+                xrutil::get_actionstate(&xr_session, &player_move_action),
+                xrutil::get_actionstate(&xr_session, &left_gadget_action),
+                xrutil::get_actionstate(&xr_session, &left_switch_gadget),
+                xrutil::get_actionstate(&xr_session, &right_switch_gadget),
+                xrutil::get_actionstate(&xr_session, &right_gadget_action),
+                xrutil::get_actionstate(&xr_session, &go_home_action),
+                xrutil::get_actionstate(&xr_session, &right_wheel_action),
+                xrutil::get_actionstate(&xr_session, &left_grab_action),
+                xrutil::get_actionstate(&xr_session, &right_grab_action)
+            )
+        };
 
         //Handle window events
         for (_, event) in glfw::flush_messages(&events) {
@@ -1057,6 +1181,9 @@ fn main() {
                 WindowEvent::Char(c) => { imgui_io.add_input_character(c); }
                 WindowEvent::Key(key, _, Action::Press, _) => {
                     imgui_io.keys_down[key as usize] = true;
+                    if desktop_player.enabled && desktop_player.handle_key(key, true) {
+                        continue;
+                    }
                     match key_directions.get(&key) {
                         Some(dir) => {
                             world_state.freecam.view_space_velocity += dir;
@@ -1070,6 +1197,11 @@ fn main() {
                                 Key::LeftControl => {
                                     world_state.freecam.speed /= 5.0;
                                 }
+                                Key::F1 => {
+                                    if let None = &xr_instance {
+                                        desktop_player.set_enabled(!desktop_player.enabled, &mut window, &mut world_state.freecam);
+                                    }
+                                }
                                 Key::F2 => {
                                     full_screenshot_this_frame = true;
                                 }
@@ -1080,6 +1212,9 @@ fn main() {
                 }
                 WindowEvent::Key(key, _, Action::Release, _) => {
                     imgui_io.keys_down[key as usize] = false;
+                    if desktop_player.enabled && desktop_player.handle_key(key, false) {
+                        continue;
+                    }
                     match key_directions.get(&key) {
                         Some(dir) => {
                             world_state.freecam.view_space_velocity -= dir;
@@ -1097,6 +1232,26 @@ fn main() {
                         }
                     }
                 }
+                WindowEvent::MouseButton(button, action, ..) if desktop_player.enabled => {
+                    //Releases still reach imgui, since the press that turned on desktop play went to it
+                    let pressed = action != Action::Release;
+                    match button {
+                        glfw::MouseButtonLeft => {
+                            desktop_player.handle_mouse_button(0, pressed);
+                            if !pressed {
+                                imgui_io.mouse_down[0] = false;
+                                mouse.clicked = false;
+                            }
+                        }
+                        glfw::MouseButtonRight => {
+                            desktop_player.handle_mouse_button(1, pressed);
+                            if !pressed {
+                                imgui_io.mouse_down[1] = false;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
                 WindowEvent::MouseButton(glfw::MouseButtonLeft, action, ..) => {
                     match action {
                         Action::Press => {
@@ -1147,214 +1302,220 @@ fn main() {
                 _ => {  }
             }
         }
+        gamepad.update_imgui_nav(imgui_io);
+
+        //The right stick looks around the same way the mouse does
+        if gamepad_controls.look != [0.0; 2] {
+            let look = glm::vec2(gamepad_controls.look[0], gamepad_controls.look[1]);
+            world_state.freecam.orientation += look * gamepad::LOOK_SPEED * delta_time / world_state.delta_timescale;
+            world_state.freecam.orientation.y = glm::clamp_scalar(world_state.freecam.orientation.y, -glm::pi::<f32>(), 0.0);
+        }
+
         let imgui_wants_mouse = imgui_io.want_capture_mouse;    //Save whether or not Dear Imgui is using the mouse input as of last frame
         drop(imgui_io);
         
         //Begin drawing imgui frame
         let imgui_ui = imgui_context.frame();
 
+        //Squeezing either hand's grip button tries to grab onto whatever the hand is touching
+        let mut grip_requests = [GripRequest::default(); 2];
+        for (request, state) in grip_requests.iter_mut().zip([left_grab_state, right_grab_state].iter()) {
+            if let Some(state) = state {
+                request.held = state.is_active && state.current_state > climbing::GRIP_THRESHOLD;
+            }
+        }
+
+        //Poses of the head and hands in tracking space, which come from the mouse-controlled camera in desktop mode
+        let (head_pose, aim_poses) = if desktop_player.enabled {
+            let rotation = world_state.player.tracking_rotation;
+            (
+                Some(DesktopPlayer::head_pose(&world_state.freecam, rotation)),
+                [Some(DesktopPlayer::hand_pose(&world_state.freecam, rotation, 0)), Some(DesktopPlayer::hand_pose(&world_state.freecam, rotation, 1))]
+            )
+        } else {
+            (
+                xrutil::locate_space(&view_space, &tracking_space, last_xr_render_time),
+                [xrutil::locate_space(&left_hand_aim_space, &tracking_space, last_xr_render_time), xrutil::locate_space(&right_hand_aim_space, &tracking_space, last_xr_render_time)]
+            )
+        };
+
         //Handle player inputs
-        let mut sticky_action = None;
+        let player_was_alive = world_state.player.is_alive();
         {
             const MOVEMENT_SPEED: f32 = 5.0;
             const DEADZONE_MAGNITUDE: f32 = 0.1;
             let player = &mut world_state.player;
 
             //Responding to the player's input movement vector
-            if let Some(state) = &move_stick_state {
-                if state.changed_since_last_sync {                            
-                    if let Some(pose) = xrutil::locate_space(&left_hand_aim_space, &tracking_space, state.last_change_time) {
-                        let hand_space_vec = glm::vec4(state.current_state.x, state.current_state.y, 0.0, 0.0);
-                        let magnitude = glm::length(&hand_space_vec);
-                        if magnitude < DEADZONE_MAGNITUDE {
-                            if player.movement_state == MoveState::Grounded {                                
-                                player.tracking_velocity.x = 0.0;
-                                player.tracking_velocity.y = 0.0;
+            //The left stick picks from the selection wheel instead while it's open
+            let left_wheel_open = gadget_hands[0].wheel.is_some();
+            match locomotion_settings.move_mode {
+                MoveMode::Smooth => {
+                    teleport_arc = None;
+                    if let (Some(state), false) = (&move_stick_state, left_wheel_open) {
+                        if state.changed_since_last_sync {                            
+                            if let Some(pose) = aim_poses[0] {
+                                let hand_space_vec = glm::vec4(state.current_state.x, state.current_state.y, 0.0, 0.0);
+                                let magnitude = glm::length(&hand_space_vec);
+                                if magnitude < DEADZONE_MAGNITUDE {
+                                    if player.movement_state == MoveState::Grounded {                                
+                                        player.tracking_velocity.x = 0.0;
+                                        player.tracking_velocity.y = 0.0;
+                                    }
+                                } else {
+                                    //World space untreated vector
+                                    let untreated = xrutil::pose_to_mat4(&pose, &world_from_tracking) * hand_space_vec;
+                                    let ugh = glm::normalize(&glm::vec3(untreated.x, untreated.y, 0.0)) * MOVEMENT_SPEED * magnitude;
+                                    player.tracking_velocity = glm::vec3(ugh.x, ugh.y, player.tracking_velocity.z);
+                                    player.movement_state = MoveState::Falling;
+                                }
                             }
-                        } else {
-                            //World space untreated vector
-                            let untreated = xrutil::pose_to_mat4(&pose, &world_from_tracking) * hand_space_vec;
-                            let ugh = glm::normalize(&glm::vec3(untreated.x, untreated.y, 0.0)) * MOVEMENT_SPEED * magnitude;
-                            player.tracking_velocity = glm::vec3(ugh.x, ugh.y, player.tracking_velocity.z);
-                            player.movement_state = MoveState::Falling;
                         }
                     }
                 }
-            }
-
-            //Gadget switching
-            {
-                let gadgets = [&mut left_hand_gadget, &mut right_hand_gadget];
-                let states = [left_switch_state, right_switch_state];
-                for i in 0..states.len() {
-                    if let Some(state) = states[i] {
-                        //If switch button was pressed for this hand
-                        if state.changed_since_last_sync {
-                            if state.current_state {
-                                match gadget_model_map.get(gadgets[i]) {
-                                    Some(entity_idx) => {
-                                        if let Some(entity) = scene_data.opaque_entities.get_mut_element(*entity_idx) {
-                                            unsafe { entity.update_single_transform(i, &glm::zero()); }
-                                        }
-                                    }
-                                    None => { println!("No model for gadget {:?}", gadgets[i]); }
-                                }
+                MoveMode::Teleport => {
+                    //Pushing the stick forward aims an arc from the left hand, and letting go of the stick teleports to where it lands
+                    let mut aiming = false;
+                    if let (Some(state), false) = (&move_stick_state, left_wheel_open) {
+                        if state.is_active && state.current_state.y > locomotion::TELEPORT_STICK_THRESHOLD {
+                            if let Some(pose) = aim_poses[0] {
+                                let hand_transform = xrutil::pose_to_mat4(&pose, &world_from_tracking);
+                                let origin = glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 0.0, 0.0, 1.0)));
+                                let direction = glm::normalize(&glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 1.0, 0.0, 0.0))));
+                                teleport_arc = Some(locomotion::teleport_arc(&world_state.collision.terrain, &origin, &direction));
+                                aiming = true;
+                            }
+                        }
+                    }
 
-                                let new = (*gadgets[i] as usize + 1) % GadgetType::COUNT;
-                                *gadgets[i] = GadgetType::from_usize(new);
+                    if !aiming {
+                        //Opening the wheel cancels the teleport
+                        if let (Some(arc), false) = (teleport_arc.take(), left_wheel_open) {
+                            if let Some(target) = arc.target {
+                                locomotion::teleport_player(player, &target);
                             }
                         }
                     }
                 }
             }
 
-            //Handle gadget input
+            //Turning with the right stick, unless it's picking from the selection wheel
+            let right_wheel_open = gadget_hands[1].wheel.is_some();
+            turn_state.smooth_turning = false;
+            if let (Some(state), false) = (&right_wheel_stick_state, right_wheel_open) {
+                if state.is_active {
+                    let angle = turn_state.update(&locomotion_settings, state.current_state.x, delta_time);
+                    if angle != 0.0 {
+                        locomotion::turn_player(player, angle);
+                    }
+                }
+            }
+
+            //Gadget input
             {
+                let switch_states = [left_switch_state, right_switch_state];
+                let wheel_stick_states = [move_stick_state, right_wheel_stick_state];
                 let trigger_states = [left_trigger_state, right_trigger_state];
-                let aim_spaces = [&left_hand_aim_space, &right_hand_aim_space];
-                let gadgets = [&left_hand_gadget, &right_hand_gadget];
-                let pillar_scales = [&mut left_water_pillar_scale, &mut right_water_pillar_scale];
+                for i in 0..gadget_hands.len() {
+                    let gadget_hand = &mut gadget_hands[i];
+                    let mut ctx = GadgetContext {
+                        hand: gadget_hand.hand,
+                        player: &mut *player,
+                        scene_data: &mut scene_data,
+                        collision: &world_state.collision,
+                        settings: &gadget_settings,
+                        aim_transform: aim_poses[i].map(|pose| xrutil::pose_to_mat4(&pose, &world_from_tracking)),
+                        delta_time,
+                        grip_request: &mut grip_requests[i]
+                    };
 
-                for i in 0..trigger_states.len() {
-                    if let Some(state) = trigger_states[i] {
-                        match gadgets[i] {
-                            GadgetType::Net => {
-                                if state.changed_since_last_sync && state.current_state == 1.0 {
-                                    if player.jumps_remaining > 0 {
-                                        player.tracking_velocity.z = 10.0;
-                                        player.jumps_remaining -= 1;
-                                    }
-                                }
-                            }
-                            GadgetType::StickyHand => {
-                                if let Some(pose) = xrutil::locate_space(aim_spaces[i], &tracking_space, last_xr_render_time) {
-                                    if state.current_state > 0.5 {
-                                        let hand_transform = xrutil::pose_to_mat4(&pose, &world_from_tracking);
-                                        let grip_position = glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 0.0, 0.0, 1.0)));
-
-                                        if i == 0 && !left_sticky_grabbing {
-                                            match player.stick_data {
-                                                Some(StickData::Left(_)) => {}
-                                                _ => {
-                                                    sticky_action = Some(StickData::Left(grip_position));
-                                                }
-                                            }
-                                        } else if i == 1 && !right_sticky_grabbing {
-                                            match player.stick_data {
-                                                Some(StickData::Right(_)) => {}
-                                                _ => {
-                                                    sticky_action = Some(StickData::Right(grip_position));
-                                                }
-                                            }
-                                        }
-                                    } else {
-                                        let unstick = |player: &mut Player| {                                            
-                                            player.stick_data = None;
-                                            player.tracking_velocity = (player.tracked_segment.p0 - player.last_tracked_segment.p0) / delta_time * 2.0;
-                                        };
-
-                                        if i == 0 { left_sticky_grabbing = false; }
-                                        else if i == 1 { right_sticky_grabbing = false; }
-
-                                        match &player.stick_data {
-                                            Some(StickData::Left(_)) => {
-                                                if i == 0 { unstick(player); }
-                                            }
-                                            Some(StickData::Right(_)) => {
-                                                if i == 1 { unstick(player); }
-                                            }
-                                            None => {}
-                                        }
-                                    }
-                                }
-                            }
-                            GadgetType::WaterCannon => {
-                                //Calculate the force of shooting the water gun for the left hand
-                                if let Some(pose) = xrutil::locate_space(aim_spaces[i], &tracking_space, last_xr_render_time) {
-                                    let hand_transform = xrutil::pose_to_mat4(&pose, &world_from_tracking);
-                                    let hand_space_vec = glm::vec4(0.0, 1.0, 0.0, 0.0);
-                                    let world_space_vec = hand_transform * hand_space_vec;
-                    
-                                    //Calculate water gun force vector
-                                    water_gun_force = glm::vec4_to_vec3(&(-state.current_state * world_space_vec));
-                    
-                                    if state.current_state > 0.0 {
-                                        pillar_scales[i].y = 0.75;
-                                        if player.movement_state != MoveState::Falling {
-                                            set_player_falling(player);
-                                        }
-                                    }
-                                }
-        
-                                //Apply watergun force to player
-                                if !floats_equal(glm::length(&water_gun_force), 0.0) && remaining_water > 0.0 {
-                                    let drain_speed = 2.0;
-                                    let update_force = water_gun_force * delta_time * MAX_WATER_PRESSURE;
-                                    if !infinite_ammo {
-                                        remaining_water -= glm::length(&update_force) * drain_speed;
-                                    }
-                                    let xz_scale = remaining_water / Gadget::MAX_ENERGY;
-                                    pillar_scales[i].x = xz_scale;
-                                    pillar_scales[i].z = xz_scale;
-                                    player.tracking_velocity += update_force;
-        
-                                    if let Some(entity) = scene_data.opaque_entities.get_mut_element(water_cylinder_entity_index) {
-                                        //Update the water gun's pillar of water
-                                        entity.uv_offset += glm::vec2(0.0, 5.0) * delta_time;
-                                        entity.uv_scale.y = pillar_scales[i].y;
-                                    }
-                                } else {
-                                    *pillar_scales[i] = glm::zero();
-                                }
-                            }
+                    //Holding the switch button opens this hand's selection wheel
+                    let wheel_input = WheelInput {
+                        held: match switch_states[i] {
+                            Some(state) => { state.is_active && state.current_state }
+                            None => { false }
+                        },
+                        stick: match wheel_stick_states[i] {
+                            Some(state) if state.is_active => { Some(glm::vec2(state.current_state.x, state.current_state.y)) }
+                            _ => { None }
                         }
+                    };
+                    gadget_hand.update_wheel(&wheel_input, &mut ctx);
+
+                    if let Some(state) = trigger_states[i] {
+                        let input = TriggerInput {
+                            value: state.current_state,
+                            changed: state.changed_since_last_sync
+                        };
+                        gadget_hand.current_mut().trigger(&mut ctx, &input);
                     }
+                    gadget_hand.update(&mut ctx);
                 }
             }
 
-            if player.movement_state != MoveState::Falling {
-                remaining_water = Gadget::MAX_ENERGY;
-            }
-
             //Emergency respawn button
             if let Some(state) = right_trackpad_force_state {
                 if state.changed_since_last_sync && state.current_state {
-                    reset_player_position(player);
+                    let target = choose_respawn_position(&world_state);
+                    respawn_player(&mut world_state.player, target);
                 }
             }
 
-            if player.tracking_position.z < -100.0 {
-                reset_player_position(player);
+            //Falling out of the level is fatal
+            if world_state.player.tracking_position.z < -100.0 {
+                world_state.player.kill();
             }
         }
 
-        //Match the player's stuck hand to the stick position
+        //Offsets of the hands from the tracking space origin in world space, used for climbing
+        let hand_tracking_positions = {
+            let mut positions = [None; 2];
+            for h in 0..aim_poses.len() {
+                if let Some(pose) = aim_poses[h] {
+                    let position = glm::vec3(pose.position.x, pose.position.y, pose.position.z);
+                    positions[h] = Some(glm::rotate_z_vec3(&position, world_state.player.tracking_rotation));
+                }
+                hand_histories[h].record(positions[h], delta_time);
+            }
+            positions
+        };
+
+        //Move the player so that the gripping hands stay on their grip points
         //Apply gravity otherwise
-        match &world_state.player.stick_data {
-            Some(data) => {
-                let move_to_grip = |stick_point: &glm::TVec3<f32>, aim_space: &Option<xr::Space>| {
-                    let mut res = glm::zero();
-                    if let Some(pose) = xrutil::locate_space(aim_space, &tracking_space, last_xr_render_time) {
-                        let hand_transform = xrutil::pose_to_mat4(&pose, &world_from_tracking);
-                        let grip_position = glm::vec4_to_vec3(&(hand_transform * glm::vec4(0.0, 0.0, 0.0, 1.0)));
-                        res = stick_point - grip_position;
-                    }
-                    res
-                };
+        {
+            let player = &mut world_state.player;
+            let was_climbing = player.is_climbing();
+            let mut released_hand = None;
+            for h in 0..player.grips.len() {
+                if player.grips[h].is_some() && !grip_requests[h].held {
+                    player.grips[h] = None;
+                    released_hand = Some(h);
+                }
+            }
 
-                match data {
-                    StickData::Left(stick_point) => {
-                        world_state.player.tracking_position += move_to_grip(&stick_point, &left_hand_aim_space);
-                    }                    
-                    StickData::Right(stick_point) => {
-                        world_state.player.tracking_position += move_to_grip(&stick_point, &right_hand_aim_space);
+            if player.is_climbing() {
+                let mut offset = glm::zero();
+                let mut count = 0;
+                for h in 0..player.grips.len() {
+                    if let (Some(grip_point), Some(hand_position)) = (player.grips[h], hand_tracking_positions[h]) {
+                        offset += grip_point - (player.tracking_position + hand_position);
+                        count += 1;
                     }
                 }
-            }
-            None => {
+                if count > 0 {
+                    player.tracking_position += offset / count as f32;
+                }
+                player.tracking_velocity = glm::zero();
+            } else {
+                //Letting go with the last hand throws the player
+                if let (true, Some(h)) = (was_climbing, released_hand) {
+                    player.tracking_velocity = hand_histories[h].throw_velocity();
+                    player.movement_state = MoveState::Falling;
+                }
+
                 //Apply gravity to the player's velocity
-                if world_state.player.movement_state != MoveState::Grounded {
-                    world_state.player.tracking_velocity.z -= ACCELERATION_GRAVITY * delta_time;
+                if player.movement_state != MoveState::Grounded {
+                    player.tracking_velocity.z -= ACCELERATION_GRAVITY * delta_time;
                 }
             }
         }
@@ -1381,83 +1542,133 @@ fn main() {
             }
         }
 
-        //Create capsule collider(s) for water guns
-        let water_gun_colliders = {
-            let trigger_states = [&left_trigger_state, &right_trigger_state];
-            let gadgets = [&left_hand_gadget, &right_hand_gadget];
-            let pillar_scales = [&mut left_water_pillar_scale, &mut right_water_pillar_scale];
-            let aim_spaces = [&left_hand_aim_space, &right_hand_aim_space];
-            let mut colliders = [None, None];
-
-            for i in 0..2 {
-                if let Some(state) = trigger_states[i] {
-                    if state.current_state > 0.0 {
-                        if let GadgetType::WaterCannon = gadgets[i] {
-                            let mut capsule_segment = LineSegment {
-                                p0: glm::zero(),
-                                p1: glm::vec3(0.0, pillar_scales[i].y * 2.0, 0.0)
-                            };
-
-                            if let Some(hand_aim_pose) = xrutil::locate_space(aim_spaces[i], &tracking_space, last_xr_render_time) {
-                                let transform = xrutil::pose_to_mat4(&hand_aim_pose, &world_from_tracking);
-                                capsule_segment.p0 = glm::vec4_to_vec3(&(transform * glm::vec4(capsule_segment.p0.x, capsule_segment.p0.y, capsule_segment.p0.z, 1.0)));
-                                capsule_segment.p1 = glm::vec4_to_vec3(&(transform * glm::vec4(capsule_segment.p1.x, capsule_segment.p1.y, capsule_segment.p1.z, 1.0)));
-                            }
+        //Gather the colliders of the equipped gadgets
+        let gadget_colliders = {
+            let mut colliders = Vec::new();
+            for gadget_hand in gadget_hands.iter() {
+                for capsule in gadget_hand.current().colliders() {
+                    if viewing_collision_spheres {
+                        queue_debug_sphere(&mut debug_sphere_queue, capsule.segment.p0, glm::vec4(0.0, 0.3, 1.0, 0.5), capsule.radius, false);
+                        queue_debug_sphere(&mut debug_sphere_queue, capsule.segment.p1, glm::vec4(0.0, 0.4, 1.0, 0.5), capsule.radius, false);
+                    }
+                    colliders.push(capsule);
+                }
+            }
+            colliders
+        };
 
-                            let radius = pillar_scales[i].x * 0.2;
-                            if viewing_collision_spheres {
-                                queue_debug_sphere(&mut debug_sphere_queue, capsule_segment.p0, glm::vec4(0.0, 0.3, 1.0, 0.5), radius, false);
-                                queue_debug_sphere(&mut debug_sphere_queue, capsule_segment.p1, glm::vec4(0.0, 0.4, 1.0, 0.5), radius, false);
+        //Forget about sounds the audio thread is done with, and keep up with the music
+        while let Ok(event) = audio_event_receiver.try_recv() {
+            match event {
+                AudioEvent::SfxStarted(..) => {}
+                AudioEvent::Spectrum(bands) => { music_bands = bands; }
+                AudioEvent::OutputDevices(devices) => { audio_devices = devices; }
+                AudioEvent::OutputDeviceOpened(name) => {
+                    audio_device = Some(name);
+                    audio_device_lost = false;
+                }
+                AudioEvent::OutputDeviceLost(_) => {
+                    audio_device = None;
+                    audio_device_lost = true;
+                }
+                AudioEvent::SfxFinished(handle) | AudioEvent::SfxStolen(handle) | AudioEvent::SfxDropped(handle) => {
+                    sound_attachments.detach(handle);
+                    sound_occlusion.forget(handle);
+                    for i in 0..world_state.totoros.len() {
+                        if let Some(totoro) = world_state.totoros.get_mut_element(i) {
+                            if totoro.drown_sfx == Some(handle) {
+                                totoro.drown_sfx = None;
                             }
-
-                            colliders[i] = Some(
-                                Capsule {
-                                    segment: capsule_segment,
-                                    radius
-                                }
-                            );
                         }
                     }
                 }
             }
+        }
 
-            colliders
+        //Let the music drive whichever scene parameters are bound to it
+        scene_data.flicker_scale = 1.0 + spectrum_bindings.flicker_amplitude.amount(&music_bands);
+        scene_data.sun_size_scale = 1.0 + spectrum_bindings.sun_size.amount(&music_bands);
+        scene_data.glitch_intensity = match spectrum_bindings.glitch_intensity.band {
+            Some(_) => { f32::min(spectrum_bindings.glitch_intensity.amount(&music_bands), 1.0) }
+            None => { 1.0 }
         };
 
         //Totoro update
         let totoro_base_speed = 2.0;
         let totoro_awareness_radius = 5.0;
+        let totoro_max_travel_time = 30.0;          //Totoros give up on reaching a point of interest after this many seconds
+        let time_of_day = TimeOfDay::from_sun_pitch(scene_data.sun_pitch);
         for i in 0..world_state.totoros.len() {
+            let totoro_ref = world_state.totoros.reference(i);
             if let Some(totoro) = world_state.totoros.entities.get_mut_element(i) {
+                //Velocity here has already been corrected by last frame's collision, which is what landing detection needs
+                totoro.animator.update(&totoro.state, &totoro.velocity, delta_time);
+
                 let ai_time = scene_data.elapsed_time - totoro.state_timer;     //Time since last state change
                 let player_is_near = glm::distance(&world_state.player.tracked_segment.p1, &totoro.position) < totoro_awareness_radius;
 
+                //Needs grow constantly, and are satisfied by relaxing at a matching point of interest
+                totoro.needs.update(&time_of_day, delta_time);
+                let target_poi = match totoro.activity {
+                    Activity::Visit(idx) => {
+                        match &world_state.points_of_interest.entities[idx] {
+                            Some(poi) => { Some((poi.position, poi.kind, poi.contains(&totoro.position))) }
+                            None => { None }
+                        }
+                    }
+                    Activity::Wander => { None }
+                };
+                if let None = target_poi {
+                    totoro.activity = Activity::Wander;
+                }
+
+                //Totoros can hold their breath for a little while before they start drowning
+                let submerged = {
+                    let focus = totoro.sphere().focus;
+                    let mut res = false;
+                    for j in 0..world_state.water_volumes.len() {
+                        if let Some(volume) = &world_state.water_volumes.entities[j] {
+                            if volume.contains(&focus) {
+                                res = true;
+                                break;
+                            }
+                        }
+                    }
+                    res
+                };
+                if submerged {
+                    totoro.breath = f32::max(totoro.breath - delta_time, 0.0);
+                    if totoro.breath <= 0.0 {
+                        let amount = totoro.damage(DamageSource::Drowning, damage::DROWNING_DAMAGE_RATE * delta_time);
+                        totoro_events.push(TotoroEvent::Damaged { idx: i, source: DamageSource::Drowning, amount, position: totoro.position });
+                    }
+                } else {
+                    totoro.breath = f32::min(totoro.breath + damage::BREATH_RECOVERY_RATE * delta_time, damage::MAX_BREATH);
+                }
+
                 //Check if the player is hitting this one
                 let being_hit_by_water = {
                     let mut res = false;
                     let tot_sphere = totoro.sphere();
 
-                    //Both hands
-                    for i in 0..2 {
-                        if let Some(water_gun_capsule) = &water_gun_colliders[i] {
-                            let segment = &water_gun_capsule.segment;
-                            let t_vector = tot_sphere.focus - segment.p0;
-                            let l_vector = segment.p1 - segment.p0;
-                            let length = glm::length(&l_vector);
-                            let t = f32::clamp(glm::dot(&t_vector, &(l_vector / length)) / length, 0.0, 1.0);
-
-                            let test_sphere = Sphere {
-                                focus: segment.p0 + t * l_vector,
-                                radius: water_gun_capsule.radius
-                            };
-                            if viewing_collision_spheres {
-                                queue_debug_sphere(&mut debug_sphere_queue, test_sphere.focus, glm::vec4(1.0, 0.0, 0.0, 0.6), test_sphere.radius, false);
-                            }
+                    for water_gun_capsule in gadget_colliders.iter() {
+                        let segment = &water_gun_capsule.segment;
+                        let t_vector = tot_sphere.focus - segment.p0;
+                        let l_vector = segment.p1 - segment.p0;
+                        let length = glm::length(&l_vector);
+                        let t = f32::clamp(glm::dot(&t_vector, &(l_vector / length)) / length, 0.0, 1.0);
 
-                            if spheres_collide(&test_sphere, &tot_sphere) {
-                                res = true;
-                                break;
-                            }
+                        let test_sphere = Sphere {
+                            focus: segment.p0 + t * l_vector,
+                            radius: water_gun_capsule.radius
+                        };
+                        if viewing_collision_spheres {
+                            queue_debug_sphere(&mut debug_sphere_queue, test_sphere.focus, glm::vec4(1.0, 0.0, 0.0, 0.6), test_sphere.radius, false);
+                        }
+
+                        if spheres_collide(&test_sphere, &tot_sphere) {
+                            res = true;
+                            break;
                         }
                     }
 
@@ -1467,26 +1678,69 @@ fn main() {
                 //Do behavior based on AI state
                 match totoro.state {
                     TotoroState::Relaxed => {
+                        if !submerged {
+                            totoro.health = f32::min(totoro.health + damage::REGENERATION_RATE * delta_time, Totoro::MAX_HEALTH);
+                        }
+
+                        //Stay put while a need is being satisfied
+                        let busy_at_poi = match target_poi {
+                            Some((poi_position, kind, true)) => {
+                                let need = kind.need();
+                                totoro.needs.satisfy(need, delta_time);
+
+                                //The last place a Totoro slept becomes its home
+                                if kind == PoiKind::SleepingSpot {
+                                    totoro.home = poi_position;
+                                }
+                                totoro.needs.get(need) > SATISFIED_THRESHOLD
+                            }
+                            _ => { false }
+                        };
+
                         if player_is_near {
                             totoro.state = TotoroState::Startled;
                         } else if being_hit_by_water {
                             totoro.state = TotoroState::StartDying;
                             totoro.velocity = glm::zero();
-                        } else if ai_time >= totoro.relax_duration {
+                        } else if ai_time >= totoro.relax_duration && !busy_at_poi {
                             totoro.state_timer = scene_data.elapsed_time;
                             totoro.state = TotoroState::Meandering;
-                            if glm::distance(&totoro.home, &totoro.position) > EPSILON {
-                                totoro.desired_forward = glm::normalize(&(totoro.home - totoro.position));
-                                totoro.desired_forward.z = 0.0;
+                            totoro.activity = choose_activity(&totoro.position, &totoro.needs, &world_state.points_of_interest, &time_of_day);
+
+                            let destination = match totoro.activity {
+                                Activity::Visit(idx) => {
+                                    match &world_state.points_of_interest.entities[idx] {
+                                        Some(poi) => { poi.position }
+                                        None => { totoro.home }
+                                    }
+                                }
+                                Activity::Wander => { totoro.home }
+                            };
+                            let mut to_destination = destination - totoro.position;
+                            to_destination.z = 0.0;
+                            if glm::length(&to_destination) > EPSILON {
+                                totoro.desired_forward = glm::normalize(&to_destination);
                             }
                         }
                     }
                     TotoroState::Meandering => {
-                        if ai_time >= 3.0 {
+                        let arrived = match target_poi {
+                            Some((_, _, inside)) => { inside }
+                            None => { false }
+                        };
+                        let travel_time_limit = match target_poi {
+                            Some(_) => { totoro_max_travel_time }
+                            None => { 3.0 }
+                        };
+
+                        if arrived || ai_time >= travel_time_limit {
+                            if !arrived {
+                                totoro.activity = Activity::Wander;
+                            }
                             totoro.state_timer = scene_data.elapsed_time;
                             totoro.velocity = glm::vec3(0.0, 0.0, totoro.velocity.z);
                             totoro.state = TotoroState::Relaxed;
-                            totoro.relax_duration = rand::random::<f32>() * 2.0 + 1.0;
+                            totoro.relax_duration = world_state.rng.ranged_f32(RngStream::Ai, 1.0, 3.0);
                         } else {
                             //Check if the player is nearby
                             if player_is_near {
@@ -1498,8 +1752,20 @@ fn main() {
                                 let turn_speed = totoro_base_speed * 2.0;
                                 totoro.forward = glm::normalize(&lerp(&totoro.forward, &totoro.desired_forward, turn_speed * delta_time));
                                 
-                                if ai_time >= 1.0 {
-                                    totoro.desired_forward = glm::mat4_to_mat3(&glm::rotation(0.25 * glm::quarter_pi::<f32>() * rand_binomial(), &Z_UP)) * totoro.desired_forward;
+                                match target_poi {
+                                    Some((poi_position, _, _)) => {
+                                        //Head straight for the point of interest
+                                        let mut to_poi = poi_position - totoro.position;
+                                        to_poi.z = 0.0;
+                                        if glm::length(&to_poi) > EPSILON {
+                                            totoro.desired_forward = glm::normalize(&to_poi);
+                                        }
+                                    }
+                                    None => {
+                                        if ai_time >= 1.0 {
+                                            totoro.desired_forward = glm::mat4_to_mat3(&glm::rotation(0.25 * glm::quarter_pi::<f32>() * world_state.rng.binomial(RngStream::Ai), &Z_UP)) * totoro.desired_forward;
+                                        }
+                                    }
                                 }
 
                                 let v = totoro.forward * totoro_base_speed;
@@ -1508,26 +1774,7 @@ fn main() {
                         }
                     }
                     TotoroState::Startled => {
-                        totoro.forward = {
-                            let mut f = world_state.player.tracked_segment.p1 - totoro.position;
-                            f.z = 0.0;
-                            glm::normalize(&f)
-                        };
-                        totoro.velocity = glm::vec3(0.0, 0.0, 3.0);
-                        totoro.state = TotoroState::PrePanicking;
-                        totoro.state_timer = scene_data.elapsed_time;
-
-                        if totoro_yell_paths.len() > 0 {
-                            let path = totoro_yell_paths[rand::random::<usize>() % totoro_yell_paths.len()].clone();
-                            let yell_req = SoundEffectRequest {
-                                id: None,
-                                path,
-                                position: vec_to_array(totoro.position),
-                                linear_gain: 200.0,
-                                looping: false
-                            };
-                            send_or_error(&audio_sender, AudioCommand::PlaySFX(yell_req));
-                        }
+                        totoro.startle(i, &world_state.player.tracked_segment.p1, scene_data.elapsed_time, &mut totoro_events);
                     }
                     TotoroState::PrePanicking => {
                         if being_hit_by_water {
@@ -1549,7 +1796,7 @@ fn main() {
                             let mut new_forward = glm::normalize(&(totoro.position - world_state.player.tracked_segment.p1));
                             new_forward.z = 0.0;
                             new_forward = glm::normalize(&new_forward);
-                            totoro.desired_forward = glm::vec4_to_vec3(&(glm::rotation(rand_binomial(), &Z_UP) * glm::vec3_to_vec4(&new_forward)));
+                            totoro.desired_forward = glm::vec4_to_vec3(&(glm::rotation(world_state.rng.binomial(RngStream::Ai), &Z_UP) * glm::vec3_to_vec4(&new_forward)));
                             
                             let turn_speed = totoro_base_speed * 2.0;
                             totoro.forward = lerp(&totoro.forward, &totoro.desired_forward, turn_speed * delta_time);
@@ -1564,32 +1811,42 @@ fn main() {
                         }
                     }
                     TotoroState::StartDying => {
+                        //Drowning gets priority over the yells, since it keeps going until the Totoro is dead or escapes
                         let drown_req = SoundEffectRequest {
-                            id: Some(next_named_sfx),
                             path: String::from(totoro_drowning_path),
                             position: vec_to_array(totoro.position),
-                            linear_gain: 500.0,
-                            looping: true
+                            linear_gain: DROWN_GAIN,
+                            looping: true,
+                            bus: Bus::Sfx,
+                            priority: SfxPriority::High
                         };
-                        totoro.drown_sfx_id = Some(next_named_sfx);
+                        let handle = audio::play_sfx(&audio_sender, drown_req);
+                        sound_attachments.attach(handle, SoundAnchor::Totoro(totoro_ref));
+                        sound_occlusion.track(handle, vec_to_array(totoro.position));
+                        totoro.drown_sfx = Some(handle);
                         totoro.state = TotoroState::Dying;
                         totoro.velocity = glm::zero();
-                        next_named_sfx += 1;
-                        send_or_error(&audio_sender, AudioCommand::PlaySFX(drown_req));
                     }
                     TotoroState::Dying => {
                         if being_hit_by_water {
                             let base_spin_rate = glm::pi::<f32>() * 4.0;
                             let spin_rate = base_spin_rate * ((Totoro::MAX_HEALTH - totoro.health) / Totoro::MAX_HEALTH * 5.0 + 1.0);
                             let depletion_rate = Totoro::MAX_HEALTH * 4.0 / 3.0;
-                            totoro.health -= depletion_rate * delta_time;
+                            let amount = totoro.damage(DamageSource::Water, depletion_rate * delta_time);
+                            totoro_events.push(TotoroEvent::Damaged { idx: i, source: DamageSource::Water, amount, position: totoro.position });
                             let ford = glm::rotation(spin_rate * delta_time, &Z_UP) * glm::vec3_to_vec4(&totoro.forward);
                             totoro.forward = glm::vec4_to_vec3(&ford);
+
+                            //The gurgling gets louder and more frantic as the Totoro runs out of health
+                            if let Some(handle) = totoro.drown_sfx {
+                                let lost = (Totoro::MAX_HEALTH - totoro.health) / Totoro::MAX_HEALTH;
+                                send_or_error(&audio_sender, AudioCommand::SetSFXGain(handle, DROWN_GAIN * (1.0 + lost)));
+                                send_or_error(&audio_sender, AudioCommand::SetSFXPitch(handle, 1.0 + lost * 0.5));
+                            }
                         } else {
                             totoro.state = TotoroState::Panicking;
-                            if let Some(id) = totoro.drown_sfx_id {
-                                send_or_error(&audio_sender, AudioCommand::StopSFX(id));
-                                totoro.drown_sfx_id = None;
+                            if let Some(handle) = totoro.drown_sfx.take() {
+                                send_or_error(&audio_sender, AudioCommand::StopSFX(handle));
                             }
                         }
                     }
@@ -1616,119 +1873,410 @@ fn main() {
                 }
 
                 //Kill if below a certain point or health depleted
+                //Any sounds attached to the Totoro stop on their own once it's deleted
                 if totoro.position.z < -100.0 || totoro.health <= 0.0 {
-                    //Stop the drowning sfx
-                    if let Some(id) = totoro.drown_sfx_id {
-                        send_or_error(&audio_sender, AudioCommand::StopSFX(id));
-                        totoro.drown_sfx_id = None;
-                    }
-
+                    let cause = if totoro.health <= 0.0 { totoro.last_damage_source } else { None };
+                    totoro_events.push(TotoroEvent::Died { idx: i, cause, position: totoro.position });
                     world_state.totoros.delete(i);
                 }
             }
         }
 
-        //If the user is controlling the camera, force the mouse cursor into the center of the screen
-        if world_state.freecam.using_mouselook {
-            window.set_cursor_pos(window_size.x as f64 / 2.0, window_size.y as f64 / 2.0);
-        }
+        //Totoro spawner update
+        {
+            let spawners = &mut world_state.totoro_spawners;
+            let totoros = &mut world_state.totoros;
 
-        if window_wiggle {
-            glfw.with_primary_monitor_mut(|_, opt_monitor|{
-                if let Some(monitor) = opt_monitor {
-                    let size = monitor.get_physical_size();
-                    window.set_pos(size.0 / 2 + (200.0 * f32::sin(scene_data.elapsed_time*2.5)) as i32, size.1 / 2);
+            //Recount the living population of each spawner
+            for i in 0..spawners.len() {
+                if let Some(spawner) = spawners.get_mut_element(i) {
+                    spawner.population = 0;
                 }
-            });
-        }
-
-        let camera_velocity = world_state.freecam.speed * glm::vec4_to_vec3(&(glm::affine_inverse(world_state.freecam.view_from_world) * glm::vec3_to_vec4(&world_state.freecam.view_space_velocity)));
-        world_state.freecam.position += camera_velocity * delta_time / world_state.delta_timescale;
-
-        //Do click action
-        let click_action_this_frame = !imgui_wants_mouse && mouse.clicked && (!mouse.was_clicked || turbo_clicking);
-        if click_action_this_frame {
-            let terrain = &world_state.collision.terrain;
-            let w = glm::vec2(window_size.x as f32, window_size.y as f32);
-
-            //Compute click ray
-            let click_ray = compute_click_ray(&world_state.freecam, w, &mouse.screen_space_pos, &world_state.freecam.position);
-
-            //Branch based on which click action is active
-            match click_action {
-                ClickAction::CreateTotoro => {
-                    //Create Totoro if the ray hit
-                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
-                        let tot = Totoro::new(collision.point, scene_data.elapsed_time);
-                        let i = world_state.totoros.insert(tot);
-                        world_state.totoros.selected_idx = Some(i);
+            }
+            for i in 0..totoros.len() {
+                if let Some(totoro) = &totoros.entities[i] {
+                    if let Some(spawner_ref) = totoro.spawner {
+                        if let Some(spawner) = spawners.get_mut_by_ref(spawner_ref) {
+                            spawner.population += 1;
+                        }
                     }
                 }
-                ClickAction::Select => {
-                    world_state.totoros.selected_idx = None;
-                    scene_data.point_lights.selected_idx = None;
-                    let mut min_t = f32::INFINITY;
-                    if let Some(hit_info) = get_clicked_object(&world_state.totoros, &click_ray) {
-                        let t = hit_info.0;
-                        if t < min_t {
-                            min_t = hit_info.0;
-                            
-                            scene_data.point_lights.selected_idx = None;
+            }
 
-                            world_state.totoros.selected_idx = Some(hit_info.1);
+            //Spawners below their max population spawn a new Totoro once every respawn_delay seconds
+            let selected_spawner = spawners.selected_idx;
+            for i in 0..spawners.len() {
+                let spawner_ref = spawners.reference(i);
+                if let Some(spawner) = spawners.get_mut_element(i) {
+                    if spawner.population < spawner.max_population {
+                        spawner.respawn_timer += delta_time;
+                        if spawner.respawn_timer >= spawner.respawn_delay {
+                            let mut tot = spawner.spawn_totoro(&world_state.collision.terrain, scene_data.elapsed_time, &mut world_state.rng);
+                            tot.spawner = Some(spawner_ref);
+                            totoros.insert(tot);
+                            spawner.population += 1;
+                            spawner.respawn_timer = 0.0;
                         }
+                    } else {
+                        spawner.respawn_timer = 0.0;
                     }
-                    if let Some(hit_info) = get_clicked_object(&scene_data.point_lights, &click_ray) {
-                        let t = hit_info.0;
-                        if t < min_t {
-                            min_t = hit_info.0;
-
-                            world_state.totoros.selected_idx = None;
 
-                            scene_data.point_lights.selected_idx = Some(hit_info.1);
-                        }
+                    if viewing_totoro_spawners {
+                        let highlighted = match selected_spawner {
+                            Some(idx) => { idx == i }
+                            None => { false }
+                        };
+                        queue_debug_sphere(&mut debug_sphere_queue, spawner.position, glm::vec4(0.8, 0.6, 0.0, 0.6), TotoroSpawner::COLLISION_RADIUS, highlighted);
+                        queue_debug_sphere(&mut debug_sphere_queue, spawner.position, glm::vec4(0.8, 0.6, 0.0, 0.1), spawner.radius, false);
                     }
                 }
-                ClickAction::DeleteObject => {
-                    macro_rules! delete_clicked_object {
-                        ($($list_name:expr)+,$($selected_name:expr)+) => {
-                            {
-                                let mut min_t = f32::INFINITY;
-                                let mut idx = None;
-                                let mut selected_vec = None;
+            }
+        }
 
-                                let mut list_count = 0;
-                                $(
-                                    if let Some(hit_info) = get_clicked_object(&$list_name, &click_ray) {
-                                        let t = hit_info.0;
-                                        if t < min_t {
-                                            min_t = hit_info.0;
-                                            idx = Some(hit_info.1);
-                                            selected_vec = Some(list_count);
-                                        }
-                                    }
-                                    list_count += 1;
-                                )+
+        //Visualize points of interest
+        if viewing_points_of_interest {
+            let pois = &world_state.points_of_interest;
+            for i in 0..pois.len() {
+                if let Some(poi) = &pois.entities[i] {
+                    let highlighted = match pois.selected_idx {
+                        Some(idx) => { idx == i }
+                        None => { false }
+                    };
+                    let color = poi.kind.debug_color();
+                    queue_debug_sphere(&mut debug_sphere_queue, poi.position, color, PointOfInterest::COLLISION_RADIUS, highlighted);
+                    queue_debug_sphere(&mut debug_sphere_queue, poi.position, glm::vec4(color.x, color.y, color.z, 0.1), poi.radius, false);
+                }
+            }
+        }
+
+        //Visualize water volumes as a handle at the center of the surface with a ring of spheres around the rim
+        if viewing_water_volumes {
+            let volumes = &world_state.water_volumes;
+            let rim_spheres = 16;
+            for i in 0..volumes.len() {
+                if let Some(volume) = &volumes.entities[i] {
+                    let highlighted = match volumes.selected_idx {
+                        Some(idx) => { idx == i }
+                        None => { false }
+                    };
+                    let color = glm::vec4(0.1, 0.4, 1.0, 0.5);
+                    queue_debug_sphere(&mut debug_sphere_queue, volume.position, color, WaterVolume::COLLISION_RADIUS, highlighted);
+                    for j in 0..rim_spheres {
+                        let angle = j as f32 * glm::two_pi::<f32>() / rim_spheres as f32;
+                        let offset = glm::vec3(volume.radius * f32::cos(angle), volume.radius * f32::sin(angle), 0.0);
+                        queue_debug_sphere(&mut debug_sphere_queue, volume.position + offset, color, 0.1, false);
+                        queue_debug_sphere(&mut debug_sphere_queue, volume.position + offset - glm::vec3(0.0, 0.0, volume.depth), color, 0.1, false);
+                    }
+                }
+            }
+        }
+
+        //Hazard regions are drawn as their full extent around a handle
+        if viewing_hazard_regions {
+            let hazards = &world_state.hazard_regions;
+            for i in 0..hazards.len() {
+                if let Some(hazard) = &hazards.entities[i] {
+                    let highlighted = match hazards.selected_idx {
+                        Some(idx) => { idx == i }
+                        None => { false }
+                    };
+                    queue_debug_sphere(&mut debug_sphere_queue, hazard.position, glm::vec4(1.0, 0.1, 0.0, 0.6), HazardRegion::COLLISION_RADIUS, highlighted);
+                    queue_debug_sphere(&mut debug_sphere_queue, hazard.position, glm::vec4(1.0, 0.1, 0.0, 0.1), hazard.radius, false);
+                }
+            }
+        }
+
+        if viewing_respawn_points {
+            let points = &world_state.respawn_points;
+            for i in 0..points.len() {
+                if let Some(point) = &points.entities[i] {
+                    let highlighted = match points.selected_idx {
+                        Some(idx) => { idx == i }
+                        None => { false }
+                    };
+                    queue_debug_sphere(&mut debug_sphere_queue, point.position, glm::vec4(0.0, 1.0, 0.5, 0.5), RespawnPoint::COLLISION_RADIUS, highlighted);
+                }
+            }
+        }
+
+        //Reverb zones are drawn as their full extent around a handle, like hazard regions
+        if viewing_reverb_zones {
+            let zones = &world_state.reverb_zones;
+            for i in 0..zones.len() {
+                if let Some(zone) = &zones.entities[i] {
+                    let highlighted = match zones.selected_idx {
+                        Some(idx) => { idx == i }
+                        None => { false }
+                    };
+                    queue_debug_sphere(&mut debug_sphere_queue, zone.position, glm::vec4(0.6, 0.3, 1.0, 0.6), ReverbZone::COLLISION_RADIUS, highlighted);
+                    queue_debug_sphere(&mut debug_sphere_queue, zone.position, glm::vec4(0.6, 0.3, 1.0, 0.1), zone.radius, false);
+                }
+            }
+        }
+
+        //Energy pickups are always drawn while available, bobbing to catch the player's eye
+        //Collected ones are only shown as faint markers while viewing energy pickups
+        {
+            let pickups = &world_state.energy_pickups;
+            for i in 0..pickups.len() {
+                if let Some(pickup) = &pickups.entities[i] {
+                    let highlighted = match pickups.selected_idx {
+                        Some(idx) => { idx == i }
+                        None => { false }
+                    };
+                    if pickup.is_available() {
+                        let bob = glm::vec3(0.0, 0.0, 0.1 * f32::sin(2.0 * scene_data.elapsed_time));
+                        queue_debug_sphere(&mut debug_sphere_queue, pickup.position + bob, glm::vec4(0.2, 0.9, 1.0, 0.7), EnergyPickup::COLLISION_RADIUS, highlighted);
+                    } else if viewing_energy_pickups {
+                        queue_debug_sphere(&mut debug_sphere_queue, pickup.position, glm::vec4(0.2, 0.9, 1.0, 0.15), EnergyPickup::COLLISION_RADIUS, highlighted);
+                    }
+                }
+            }
+        }
+
+        //If the user is controlling the camera, force the mouse cursor into the center of the screen
+        if world_state.freecam.using_mouselook {
+            window.set_cursor_pos(window_size.x as f64 / 2.0, window_size.y as f64 / 2.0);
+        }
+
+        if window_wiggle {
+            glfw.with_primary_monitor_mut(|_, opt_monitor|{
+                if let Some(monitor) = opt_monitor {
+                    let size = monitor.get_physical_size();
+                    window.set_pos(size.0 / 2 + (200.0 * f32::sin(scene_data.elapsed_time*2.5)) as i32, size.1 / 2);
+                }
+            });
+        }
+
+        //The gamepad flies the freecam when it isn't controlling the player
+        let mut view_space_velocity = world_state.freecam.view_space_velocity;
+        if !desktop_player.enabled {
+            view_space_velocity += glm::vec3(gamepad_controls.move_stick[0], gamepad_controls.fly, -gamepad_controls.move_stick[1]);
+        }
+        let camera_velocity = world_state.freecam.speed * glm::vec4_to_vec3(&(glm::affine_inverse(world_state.freecam.view_from_world) * glm::vec3_to_vec4(&view_space_velocity)));
+        world_state.freecam.position += camera_velocity * delta_time / world_state.delta_timescale;
+
+        //Do click action
+        let click_action_this_frame = !imgui_wants_mouse && mouse.clicked && (!mouse.was_clicked || turbo_clicking);
+        if click_action_this_frame {
+            let terrain = &world_state.collision.terrain;
+            let w = glm::vec2(window_size.x as f32, window_size.y as f32);
+
+            //Compute click ray
+            let click_ray = compute_click_ray(&world_state.freecam, w, &mouse.screen_space_pos, &world_state.freecam.position);
+
+            //Branch based on which click action is active
+            match click_action {
+                ClickAction::CreateTotoro => {
+                    //Create Totoro if the ray hit
+                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                        let scale = world_state.rng.ranged_f32(RngStream::Spawning, Totoro::DEFAULT_MIN_SCALE, Totoro::DEFAULT_MAX_SCALE);
+                        let tot = Totoro::new(collision.point, scale, scene_data.elapsed_time, &mut world_state.rng);
+                        let i = world_state.totoros.insert(tot);
+                        clear_selections(&mut world_state, &mut scene_data);
+                        world_state.totoros.selected_idx = Some(i);
+                    }
+                }
+                ClickAction::CreateTotoroSpawner => {
+                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                        let i = world_state.totoro_spawners.insert(TotoroSpawner::new(collision.point));
+                        clear_selections(&mut world_state, &mut scene_data);
+                        world_state.totoro_spawners.selected_idx = Some(i);
+                    }
+                }
+                ClickAction::CreatePointOfInterest => {
+                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                        let kind = PoiKind::from_index(new_poi_kind).unwrap_or(PoiKind::Food);
+                        let i = world_state.points_of_interest.insert(PointOfInterest::new(collision.point, kind));
+                        clear_selections(&mut world_state, &mut scene_data);
+                        world_state.points_of_interest.selected_idx = Some(i);
+                    }
+                }
+                ClickAction::CreateWaterVolume => {
+                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                        let i = world_state.water_volumes.insert(WaterVolume::new(collision.point));
+                        clear_selections(&mut world_state, &mut scene_data);
+                        world_state.water_volumes.selected_idx = Some(i);
+                    }
+                }
+                ClickAction::CreateEnergyPickup => {
+                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                        let i = world_state.energy_pickups.insert(EnergyPickup::new(collision.point + glm::vec3(0.0, 0.0, 1.0)));
+                        clear_selections(&mut world_state, &mut scene_data);
+                        world_state.energy_pickups.selected_idx = Some(i);
+                    }
+                }
+                ClickAction::CreateHazardRegion => {
+                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                        let i = world_state.hazard_regions.insert(HazardRegion::new(collision.point));
+                        clear_selections(&mut world_state, &mut scene_data);
+                        world_state.hazard_regions.selected_idx = Some(i);
+                    }
+                }
+                ClickAction::CreateRespawnPoint => {
+                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                        let i = world_state.respawn_points.insert(RespawnPoint { position: collision.point });
+                        clear_selections(&mut world_state, &mut scene_data);
+                        world_state.respawn_points.selected_idx = Some(i);
+                    }
+                }
+                ClickAction::CreateReverbZone => {
+                    if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                        let preset = ReverbPreset::from_index(new_reverb_preset).unwrap_or(ReverbPreset::Cave);
+                        let i = world_state.reverb_zones.insert(ReverbZone::new(collision.point, preset));
+                        clear_selections(&mut world_state, &mut scene_data);
+                        world_state.reverb_zones.selected_idx = Some(i);
+                    }
+                }
+                ClickAction::Select => {
+                    clear_selections(&mut world_state, &mut scene_data);
+                    let mut min_t = f32::INFINITY;
+                    if let Some(hit_info) = get_clicked_object(&world_state.totoros, &click_ray) {
+                        let t = hit_info.0;
+                        if t < min_t {
+                            min_t = hit_info.0;
+                            
+                            clear_selections(&mut world_state, &mut scene_data);
 
-                                if let (Some(i), Some(sel_v)) = (idx, selected_vec) {
-                                    match sel_v {
-                                        0 => { world_state.totoros.delete(i); }
-                                        1 => { scene_data.point_lights.delete(i); }
-                                        /*
-                                        $(
-                                            list_count => { delete_object(&mut $list_name, &mut $selected_name, i); }
-                                        )+
-                                        */
-                                        _ => {}
+                            world_state.totoros.selected_idx = Some(hit_info.1);
+                        }
+                    }
+                    if let Some(hit_info) = get_clicked_object(&scene_data.point_lights, &click_ray) {
+                        let t = hit_info.0;
+                        if t < min_t {
+                            min_t = hit_info.0;
+
+                            clear_selections(&mut world_state, &mut scene_data);
+
+                            scene_data.point_lights.selected_idx = Some(hit_info.1);
+                        }
+                    }
+                    if viewing_totoro_spawners {
+                        if let Some(hit_info) = get_clicked_object(&world_state.totoro_spawners, &click_ray) {
+                            let t = hit_info.0;
+                            if t < min_t {
+                                min_t = hit_info.0;
+
+                                clear_selections(&mut world_state, &mut scene_data);
+
+                                world_state.totoro_spawners.selected_idx = Some(hit_info.1);
+                            }
+                        }
+                    }
+                    if viewing_points_of_interest {
+                        if let Some(hit_info) = get_clicked_object(&world_state.points_of_interest, &click_ray) {
+                            let t = hit_info.0;
+                            if t < min_t {
+                                min_t = hit_info.0;
+
+                                clear_selections(&mut world_state, &mut scene_data);
+
+                                world_state.points_of_interest.selected_idx = Some(hit_info.1);
+                            }
+                        }
+                    }
+                    if viewing_water_volumes {
+                        if let Some(hit_info) = get_clicked_object(&world_state.water_volumes, &click_ray) {
+                            let t = hit_info.0;
+                            if t < min_t {
+                                min_t = hit_info.0;
+
+                                clear_selections(&mut world_state, &mut scene_data);
+
+                                world_state.water_volumes.selected_idx = Some(hit_info.1);
+                            }
+                        }
+                    }
+                    if viewing_energy_pickups {
+                        if let Some(hit_info) = get_clicked_object(&world_state.energy_pickups, &click_ray) {
+                            let t = hit_info.0;
+                            if t < min_t {
+                                min_t = hit_info.0;
+
+                                clear_selections(&mut world_state, &mut scene_data);
+
+                                world_state.energy_pickups.selected_idx = Some(hit_info.1);
+                            }
+                        }
+                    }
+                    if viewing_hazard_regions {
+                        if let Some(hit_info) = get_clicked_object(&world_state.hazard_regions, &click_ray) {
+                            let t = hit_info.0;
+                            if t < min_t {
+                                min_t = hit_info.0;
+
+                                clear_selections(&mut world_state, &mut scene_data);
+
+                                world_state.hazard_regions.selected_idx = Some(hit_info.1);
+                            }
+                        }
+                    }
+                    if viewing_respawn_points {
+                        if let Some(hit_info) = get_clicked_object(&world_state.respawn_points, &click_ray) {
+                            let t = hit_info.0;
+                            if t < min_t {
+                                min_t = hit_info.0;
+
+                                clear_selections(&mut world_state, &mut scene_data);
+
+                                world_state.respawn_points.selected_idx = Some(hit_info.1);
+                            }
+                        }
+                    }
+                    if viewing_reverb_zones {
+                        if let Some(hit_info) = get_clicked_object(&world_state.reverb_zones, &click_ray) {
+                            let t = hit_info.0;
+                            if t < min_t {
+                                min_t = hit_info.0;
+
+                                clear_selections(&mut world_state, &mut scene_data);
+
+                                world_state.reverb_zones.selected_idx = Some(hit_info.1);
+                            }
+                        }
+                    }
+                }
+                ClickAction::DeleteObject => {
+                    //Each list is given along with how to delete from it
+                    macro_rules! delete_clicked_object {
+                        ($($list:expr => |$i:ident| $delete:expr),+) => {
+                            {
+                                //Find the closest object along the ray and which list it's in...
+                                let mut closest: Option<(f32, Option<usize>, usize)> = None;
+                                let mut list_ids = 0..;
+                                $(
+                                    let list_id = list_ids.next();
+                                    if let Some((t, i)) = get_clicked_object(&$list, &click_ray) {
+                                        if closest.map_or(true, |(min_t, _, _)| t < min_t) {
+                                            closest = Some((t, list_id, i));
+                                        }
                                     }
+                                )+
+
+                                //...then delete it from that list
+                                if let Some((_, hit_list, hit_idx)) = closest {
+                                    let mut list_ids = 0..;
+                                    $(
+                                        if list_ids.next() == hit_list {
+                                            let $i = hit_idx;
+                                            $delete;
+                                        }
+                                    )+
                                 }
                             }
                         };
                     }
 
                     delete_clicked_object!(
-                        world_state.totoros scene_data.point_lights,
-                        world_state.totoros.selected_idx scene_data.selected_point_light
+                        world_state.totoros => |i| world_state.totoros.delete(i),
+                        scene_data.point_lights => |i| scene_data.point_lights.delete(i),
+                        world_state.totoro_spawners => |i| delete_totoro_spawner(&mut world_state, i),
+                        world_state.points_of_interest => |i| delete_point_of_interest(&mut world_state, i),
+                        world_state.water_volumes => |i| world_state.water_volumes.delete(i),
+                        world_state.energy_pickups => |i| world_state.energy_pickups.delete(i),
+                        world_state.hazard_regions => |i| world_state.hazard_regions.delete(i),
+                        world_state.respawn_points => |i| world_state.respawn_points.delete(i),
+                        world_state.reverb_zones => |i| world_state.reverb_zones.delete(i)
                     );
                 }
                 ClickAction::MoveSelectedTotoro => {
@@ -1741,6 +2289,69 @@ fn main() {
                         }
                     }
                 }
+                ClickAction::MoveSelectedTotoroSpawner => {
+                    if let Some(idx) = world_state.totoro_spawners.selected_idx {
+                        if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                            if let Some(spawner) = world_state.totoro_spawners.get_mut_element(idx) {
+                                spawner.position = collision.point;
+                            }
+                        }
+                    }
+                }
+                ClickAction::MoveSelectedPointOfInterest => {
+                    if let Some(idx) = world_state.points_of_interest.selected_idx {
+                        if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                            if let Some(poi) = world_state.points_of_interest.get_mut_element(idx) {
+                                poi.position = collision.point;
+                            }
+                        }
+                    }
+                }
+                ClickAction::MoveSelectedWaterVolume => {
+                    if let Some(idx) = world_state.water_volumes.selected_idx {
+                        if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                            if let Some(volume) = world_state.water_volumes.get_mut_element(idx) {
+                                volume.position = collision.point;
+                            }
+                        }
+                    }
+                }
+                ClickAction::MoveSelectedEnergyPickup => {
+                    if let Some(idx) = world_state.energy_pickups.selected_idx {
+                        if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                            if let Some(pickup) = world_state.energy_pickups.get_mut_element(idx) {
+                                pickup.position = collision.point + glm::vec3(0.0, 0.0, 1.0);
+                            }
+                        }
+                    }
+                }
+                ClickAction::MoveSelectedHazardRegion => {
+                    if let Some(idx) = world_state.hazard_regions.selected_idx {
+                        if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                            if let Some(hazard) = world_state.hazard_regions.get_mut_element(idx) {
+                                hazard.position = collision.point;
+                            }
+                        }
+                    }
+                }
+                ClickAction::MoveSelectedRespawnPoint => {
+                    if let Some(idx) = world_state.respawn_points.selected_idx {
+                        if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                            if let Some(point) = world_state.respawn_points.get_mut_element(idx) {
+                                point.position = collision.point;
+                            }
+                        }
+                    }
+                }
+                ClickAction::MoveSelectedReverbZone => {
+                    if let Some(idx) = world_state.reverb_zones.selected_idx {
+                        if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
+                            if let Some(zone) = world_state.reverb_zones.get_mut_element(idx) {
+                                zone.position = collision.point;
+                            }
+                        }
+                    }
+                }
                 ClickAction::MovePlayerSpawn => {
                     if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
                         world_state.player.spawn_position = collision.point;
@@ -1749,7 +2360,9 @@ fn main() {
                 ClickAction::CreatePointLight => {
                     if scene_data.point_lights.count() < render::MAX_POINT_LIGHTS { 
                         if let Some(collision) = ray_hit_terrain(terrain, &click_ray) {
-                            let light = PointLight::new(collision.point + glm::vec3(0.0, 0.0, 2.0), [rand::random(), rand::random(), rand::random()], 3.0);
+                            let rng = &mut world_state.rng;
+                            let color = [rng.random_f32(RngStream::Lighting), rng.random_f32(RngStream::Lighting), rng.random_f32(RngStream::Lighting)];
+                            let light = PointLight::new(collision.point + glm::vec3(0.0, 0.0, 2.0), color, 3.0);
                             let i = scene_data.point_lights.insert(light);
                             scene_data.point_lights.selected_idx = Some(i);
                         }
@@ -1798,7 +2411,29 @@ fn main() {
         
         //Update tracking space location
         world_state.player.tracking_position += world_state.player.tracking_velocity * delta_time;
-        world_from_tracking = glm::translation(&world_state.player.tracking_position);
+        world_from_tracking = world_state.player.world_from_tracking();
+
+        //Fade the comfort vignette in while the player is being moved or smooth turned
+        {
+            let velocity = &world_state.player.tracking_velocity;
+            let intensity = if turn_state.smooth_turning { 1.0 }
+                            else { glm::length(&glm::vec2(velocity.x, velocity.y)) / locomotion::VIGNETTE_FULL_SPEED };
+            comfort_vignette.update(&locomotion_settings, intensity, delta_time);
+        }
+
+        //Draw the teleport arc, green where it can be landed on and red otherwise
+        if let Some(arc) = &teleport_arc {
+            let color = match arc.target {
+                Some(_) => { glm::vec4(0.2, 1.0, 0.3, 0.6) }
+                None => { glm::vec4(1.0, 0.2, 0.2, 0.6) }
+            };
+            for point in arc.points.iter().step_by(2) {
+                queue_debug_sphere(&mut debug_sphere_queue, *point, color, 0.03, false);
+            }
+            if let Some(target) = arc.target {
+                queue_debug_sphere(&mut debug_sphere_queue, target, color, Player::RADIUS, false);
+            }
+        }
 
         if viewing_player_spawn {
             queue_debug_sphere(&mut debug_sphere_queue, world_state.player.spawn_position, glm::vec4(0.0, 0.5, 0.0, 0.5), 0.3, false);
@@ -1833,7 +2468,38 @@ fn main() {
 
         //The user is considered to be always standing on the ground in tracking space        
         world_state.player.last_tracked_segment = world_state.player.tracked_segment.clone();
-        world_state.player.tracked_segment = xrutil::tracked_player_segment(&view_space, &tracking_space, last_xr_render_time, &world_from_tracking);
+        world_state.player.tracked_segment = xrutil::tracked_player_segment(&head_pose, &world_from_tracking);
+
+        //Hurt the player for standing in hazards
+        let mut in_hazard = false;
+        for i in 0..world_state.hazard_regions.len() {
+            if let Some(hazard) = &world_state.hazard_regions.entities[i] {
+                if hazard.touches(&world_state.player.tracked_segment) {
+                    world_state.player.damage(hazard.damage_per_second * delta_time);
+                    in_hazard = true;
+                }
+            }
+        }
+
+        //Collect any energy pickups the player is touching
+        for i in 0..world_state.energy_pickups.len() {
+            if let Some(pickup) = world_state.energy_pickups.get_mut_element(i) {
+                if !pickup.is_available() {
+                    pickup.cooldown -= delta_time;
+                    continue;
+                }
+
+                if pickup.touches(&world_state.player.tracked_segment) {
+                    for gadget_hand in gadget_hands.iter_mut() {
+                        gadget_hand.refill(pickup.amount);
+                    }
+                    pickup.cooldown = pickup.respawn_delay;
+                }
+            }
+        }
+
+        world_state.player.touching_ground = false;
+        world_state.player.impact_speed = 0.0;
 
         //We try to do all work related to terrain collision here in order
         //to avoid iterating over all of the triangles more than once
@@ -1917,7 +2583,7 @@ fn main() {
                             if dot_z_up >= MIN_NORMAL_LIKENESS {
                                 let t = (glm::dot(&triangle.normal, &(triangle.a - capsule_ref)) + Player::RADIUS) / dot_z_up;
                                 world_state.player.tracking_position += Z_UP * t;
-                                ground_player(&mut world_state.player, &mut remaining_water);
+                                ground_player(&mut world_state.player);
                             } else {
                                 world_state.player.tracking_position += vec;
                             }
@@ -1928,47 +2594,29 @@ fn main() {
                 }
             }
 
-            //Resolve player's attempt to stick to a wall
+            //Resolve each hand's attempt to grab onto the triangle
             let triangle_idx = i / 3;
-            if world_state.collision.grabbable_flags[triangle_idx] {
-                if let Some(action) = &sticky_action {
-                    fn grip_triangle(world_state: &mut WorldState, focus: glm::TVec3<f32>, radius: f32, triangle: &Triangle, triangle_sphere: &Sphere, grab_flag: &mut bool, is_left: bool) {
-                        let sphere = Sphere {
-                            focus,
-                            radius: radius
-                        };
-        
-                        if let Some((_, collision_point)) = triangle_sphere_collision_point(&sphere, triangle, triangle_sphere) {
-                            world_state.player.tracking_position += collision_point - sphere.focus;
-                            world_state.player.tracking_velocity = glm::zero();
-                            *grab_flag = true;
-                            
-                            if is_left {
-                                world_state.player.stick_data = Some(StickData::Left(collision_point));
-                            } else {
-                                world_state.player.stick_data = Some(StickData::Right(collision_point));
-                            }
-                        }
-                    }
+            for h in 0..grip_requests.len() {
+                let request = &grip_requests[h];
+                if !request.held || world_state.player.grips[h].is_some() {
+                    continue;
+                }
+                if !(world_state.collision.grabbable_flags[triangle_idx] || request.any_surface) {
+                    continue;
+                }
 
-                    let stick_sphere_radius = 0.05;
-                    match action {
-                        StickData::Left(focus) => {
-                            match world_state.player.stick_data {
-                                Some(StickData::Left(_)) => {}
-                                _ => {
-                                    grip_triangle(&mut world_state, *focus, stick_sphere_radius, &triangle, &triangle_sphere, &mut left_sticky_grabbing, true);
-                                }
-                            }
-                        }
-                        StickData::Right(focus) => {
-                            match world_state.player.stick_data {
-                                Some(StickData::Right(_)) => {}
-                                _ => {
-                                    grip_triangle(&mut world_state, *focus, stick_sphere_radius, &triangle, &triangle_sphere, &mut right_sticky_grabbing, false);
-                                }
-                            }
+                if let Some(hand_position) = hand_tracking_positions[h] {
+                    let sphere = Sphere {
+                        focus: world_state.player.tracking_position + hand_position,
+                        radius: climbing::GRIP_RADIUS
+                    };
+                    if let Some((_, collision_point)) = triangle_sphere_collision_point(&sphere, &triangle, &triangle_sphere) {
+                        //A hand that's already holding on decides where the body is
+                        if !world_state.player.is_climbing() {
+                            world_state.player.tracking_position += collision_point - sphere.focus;
                         }
+                        world_state.player.tracking_velocity = glm::zero();
+                        world_state.player.grips[h] = Some(collision_point);
                     }
                 }
             }
@@ -1985,6 +2633,13 @@ fn main() {
                             if dot_z_up >= MIN_NORMAL_LIKENESS {
                                 let t = (glm::dot(&triangle.normal, &(triangle.a - tot_sphere.focus)) + tot_sphere.radius) / dot_z_up;
                                 totoro.position += Z_UP * t;
+
+                                //Landing too hard hurts
+                                let impact_speed = -totoro.velocity.z;
+                                if impact_speed > damage::SAFE_IMPACT_SPEED {
+                                    let amount = totoro.damage(DamageSource::Fall, (impact_speed - damage::SAFE_IMPACT_SPEED) * damage::FALL_DAMAGE_PER_SPEED);
+                                    totoro_events.push(TotoroEvent::Damaged { idx: i, source: DamageSource::Fall, amount, position: totoro.position });
+                                }
                                 totoro.velocity.z = 0.0;
                             } else {
                                 totoro.position += vec;
@@ -1997,28 +2652,64 @@ fn main() {
             }
         }
 
+        //Landing too hard hurts the player too, and solid ground away from hazards is remembered as a place to respawn
+        {
+            let player = &mut world_state.player;
+            if player.impact_speed > damage::PLAYER_SAFE_IMPACT_SPEED {
+                player.damage((player.impact_speed - damage::PLAYER_SAFE_IMPACT_SPEED) * damage::PLAYER_FALL_DAMAGE_PER_SPEED);
+            }
+            if player.touching_ground && !in_hazard && player.is_alive() {
+                player.safe_position = Some(player.tracked_segment.p1);
+            }
+        }
+
+        update_player_life(&mut world_state, delta_time);
+        if player_was_alive && !world_state.player.is_alive() {
+            session_stats.player_deaths += 1;
+        }
+
+        //Let audio and the UI react to this frame's Totoro damage and deaths
+        for event in totoro_events.drain(audio_totoro_events) {
+            totoro_voices.react(&event, &audio_sender, &mut world_state.rng, &world_state.totoros, &mut sound_attachments, &mut sound_occlusion);
+        }
+        for event in totoro_events.drain(ui_totoro_events) {
+            if let TotoroEvent::Died { .. } = event {
+                session_stats.totoro_deaths += 1;
+            }
+            if let Some(message) = event.describe() {
+                log_totoro_event(&mut totoro_event_log, message);
+            }
+        }
+
+        //Let the music follow what's happening
+        //The audio thread keeps track of the state even with dynamic music off, so turning it back on picks the right track
+        if let Some(state) = music_director.update(&world_state, delta_time) {
+            send_or_error(&audio_sender, AudioCommand::SetMusicState(state));
+        }
+
         //After all collision processing has been completed, update the tracking space matrices once more
-        world_from_tracking = glm::translation(&world_state.player.tracking_position);
+        world_from_tracking = world_state.player.world_from_tracking();
         tracking_from_world = glm::affine_inverse(world_from_tracking);
 
         //Tell the audio thread about the listener's current state
+        //Velocities are per real second, so that slow motion doesn't exaggerate the Doppler effect
         {
-            //Just doing the match here to determine if the listener should be the HMD or the free camera
-            let (listener_pos, listener_vel, listener_forward, listener_up) = match &xr_instance {
-                Some(_) => {
-                    let head_pose_mat = match xrutil::locate_space(&view_space, &tracking_space, last_xr_render_time) {
-                        Some(space) => { xrutil::pose_to_mat4(&space, &world_from_tracking) }
+            //Just doing the match here to determine if the listener should be the player's head or the free camera
+            let (listener_pos, listener_vel, listener_forward, listener_up) = match (&xr_instance, desktop_player.enabled) {
+                (Some(_), _) | (None, true) => {
+                    let head_pose_mat = match head_pose {
+                        Some(pose) => { xrutil::pose_to_mat4(&pose, &world_from_tracking) }
                         None => { glm::identity() }
                     };
 
                     let pos = world_state.player.tracked_segment.p0;
-                    let vel = pos - world_state.player.last_tracked_segment.p0;
+                    let vel = if frame_time > 0.0 { (pos - world_state.player.last_tracked_segment.p0) / frame_time } else { glm::zero() };
                     let forward = glm::vec4_to_vec3(&(head_pose_mat * glm::vec4(0.0, 0.0, -1.0, 0.0)));
                     let up = glm::vec4_to_vec3(&(head_pose_mat * glm::vec4(0.0, 1.0, 0.0, 0.0)));
                     (vec_to_array(pos), vec_to_array(vel), vec_to_array(forward), vec_to_array(up))
                 }
-                None => {
-                    let camera_vel = world_state.freecam.position - world_state.freecam.last_position;
+                (None, false) => {
+                    let camera_vel = if frame_time > 0.0 { (world_state.freecam.position - world_state.freecam.last_position) / frame_time } else { glm::zero() };
                     let camera_forward = glm::vec4_to_vec3(&(world_state.freecam.world_from_view * glm::vec4(0.0, 0.0, -1.0, 0.0)));
                     let camera_up = glm::vec4_to_vec3(&(world_state.freecam.world_from_view * glm::vec4(0.0, 1.0, 0.0, 0.0)));
                     
@@ -2029,6 +2720,22 @@ fn main() {
             send_or_error(&audio_sender, AudioCommand::SetListenerPosition(listener_pos));
             send_or_error(&audio_sender, AudioCommand::SetListenerVelocity(listener_vel));
             send_or_error(&audio_sender, AudioCommand::SetListenerOrientation((listener_forward, listener_up)));
+
+            //Move the sounds that are attached to things
+            sound_attachments.update(&audio_sender, frame_time, |anchor| {
+                match anchor {
+                    SoundAnchor::Totoro(totoro) => { world_state.totoros.get_by_ref(totoro).map(|t| t.position) }
+                }
+            });
+
+            //Muffle sounds behind terrain and give the listener's surroundings their reverb
+            let listener = glm::make_vec3(&listener_pos);
+            sound_occlusion.update(&audio_sender, &world_state.collision.terrain, &listener, frame_time, |handle| sound_attachments.position(handle));
+            let reverb = environment::listener_reverb(&world_state.reverb_zones, &listener);
+            if reverb != listener_reverb {
+                send_or_error(&audio_sender, AudioCommand::SetReverb(reverb));
+                listener_reverb = reverb;
+            }
         }
 
         //Network updating section
@@ -2057,6 +2764,20 @@ fn main() {
                 imgui::Drag::new("Y").speed(drag_speed).build(&imgui_ui, &mut vector.y);
                 imgui::Drag::new("Z").speed(drag_speed).build(&imgui_ui, &mut vector.z);
             }
+            fn do_spectrum_binding(imgui_ui: &imgui::Ui, label: &str, binding: &mut SpectrumBinding) {
+                let mut options = vec!["Off"];
+                options.extend_from_slice(&spectrum::BAND_NAMES);
+                let mut band_idx = match binding.band {
+                    Some(b) => { b + 1 }
+                    None => { 0 }
+                };
+                if imgui_ui.combo_simple_string(label, &mut band_idx, &options) {
+                    binding.band = if band_idx == 0 { None } else { Some(band_idx - 1) };
+                }
+                if binding.band.is_some() {
+                    Slider::new(&format!("Strength###{}_strength", label), 0.0, 4.0).build(&imgui_ui, &mut binding.strength);
+                }
+            }
 
             if let Some(win_token) = imgui::Window::new("Main menu").menu_bar(true).begin(&imgui_ui) {
                 if let Some(menu_token) = imgui_ui.begin_menu_bar() {
@@ -2073,6 +2794,7 @@ fn main() {
             
                                 //Load entity data
                                 load_ent(&format!("maps/{}.ent", lvl_name), &mut scene_data, &mut world_state);
+                                music_director.reset();
                                 viewing_triangles = false;
                                 
                             }
@@ -2135,15 +2857,39 @@ fn main() {
                                     ];
                                     let floats_per_totoro = 4;
                                     let floats_per_light = 9;
+                                    let floats_per_spawner = 8;
+                                    let floats_per_poi = 5;
+                                    let floats_per_water_volume = 5;
+                                    let floats_per_energy_pickup = 5;
+                                    let floats_per_hazard_region = 5;
+                                    let floats_per_respawn_point = 3;
+                                    let floats_per_reverb_zone = 5;
+
+                                    //Totoros created by spawners are not saved, since the spawners will recreate them
+                                    let mut placed_totoros = 0;
+                                    for i in 0..totoros.len() {
+                                        if let Some(tot) = &totoros[i] {
+                                            if let None = tot.spawner {
+                                                placed_totoros += 1;
+                                            }
+                                        }
+                                    }
                                     
                                     //Precompute final filesize
                                     let grab_flags = &world_state.collision.grabbable_flags;
                                     let size = {
-                                        let totoro_floats = floats_per_totoro * totoros.count();
+                                        let totoro_floats = floats_per_totoro * placed_totoros;
                                         let point_light_floats = scene_data.point_lights.count() * floats_per_light;
+                                        let spawner_floats = world_state.totoro_spawners.count() * floats_per_spawner;
+                                        let poi_floats = world_state.points_of_interest.count() * floats_per_poi;
+                                        let water_volume_floats = world_state.water_volumes.count() * floats_per_water_volume;
+                                        let energy_pickup_floats = world_state.energy_pickups.count() * floats_per_energy_pickup;
+                                        let hazard_region_floats = world_state.hazard_regions.count() * floats_per_hazard_region;
+                                        let respawn_point_floats = world_state.respawn_points.count() * floats_per_respawn_point;
+                                        let reverb_zone_floats = world_state.reverb_zones.count() * floats_per_reverb_zone;
                                         let grab_bool_bytes = grab_flags.len();
-                                        let stored_sizes = 3;
-                                        size_of::<f32>() * (floats_to_write.len() + totoro_floats + point_light_floats) + grab_bool_bytes + size_of::<u32>() * stored_sizes
+                                        let stored_sizes = 10;
+                                        size_of::<f32>() * (floats_to_write.len() + totoro_floats + point_light_floats + spawner_floats + poi_floats + water_volume_floats + energy_pickup_floats + hazard_region_floats + respawn_point_floats + reverb_zone_floats) + grab_bool_bytes + size_of::<u32>() * stored_sizes
                                     };
     
                                     //Convert to raw bytes and write to file
@@ -2160,11 +2906,13 @@ fn main() {
                                     }
     
                                     //Write totoro data
-                                    write_u32_to_buffer(&mut bytes, totoros.count() as u32);
+                                    write_u32_to_buffer(&mut bytes, placed_totoros as u32);
                                     for i in 0..totoros.len() {
                                         if let Some(tot) = &totoros[i] {
-                                            write_vec3_to_buffer(&mut bytes, tot.home);
-                                            write_f32_to_buffer(&mut bytes, tot.scale);
+                                            if let None = tot.spawner {
+                                                write_vec3_to_buffer(&mut bytes, tot.home);
+                                                write_f32_to_buffer(&mut bytes, tot.scale);
+                                            }
                                         }
                                     }
     
@@ -2179,6 +2927,77 @@ fn main() {
                                             write_f32_to_buffer(&mut bytes, light.flicker_timescale);
                                         }
                                     }
+
+                                    //Write totoro spawner data
+                                    write_u32_to_buffer(&mut bytes, world_state.totoro_spawners.count() as u32);
+                                    for i in 0..world_state.totoro_spawners.len() {
+                                        if let Some(spawner) = &world_state.totoro_spawners.entities[i] {
+                                            write_vec3_to_buffer(&mut bytes, spawner.position);
+                                            write_f32_to_buffer(&mut bytes, spawner.radius);
+                                            write_f32_to_buffer(&mut bytes, spawner.max_population as f32);
+                                            write_f32_to_buffer(&mut bytes, spawner.respawn_delay);
+                                            write_f32_to_buffer(&mut bytes, spawner.min_scale);
+                                            write_f32_to_buffer(&mut bytes, spawner.max_scale);
+                                        }
+                                    }
+
+                                    //Write point of interest data
+                                    write_u32_to_buffer(&mut bytes, world_state.points_of_interest.count() as u32);
+                                    for i in 0..world_state.points_of_interest.len() {
+                                        if let Some(poi) = &world_state.points_of_interest.entities[i] {
+                                            write_vec3_to_buffer(&mut bytes, poi.position);
+                                            write_f32_to_buffer(&mut bytes, poi.kind as usize as f32);
+                                            write_f32_to_buffer(&mut bytes, poi.radius);
+                                        }
+                                    }
+
+                                    //Write water volume data
+                                    write_u32_to_buffer(&mut bytes, world_state.water_volumes.count() as u32);
+                                    for i in 0..world_state.water_volumes.len() {
+                                        if let Some(volume) = &world_state.water_volumes.entities[i] {
+                                            write_vec3_to_buffer(&mut bytes, volume.position);
+                                            write_f32_to_buffer(&mut bytes, volume.radius);
+                                            write_f32_to_buffer(&mut bytes, volume.depth);
+                                        }
+                                    }
+
+                                    //Write energy pickup data
+                                    write_u32_to_buffer(&mut bytes, world_state.energy_pickups.count() as u32);
+                                    for i in 0..world_state.energy_pickups.len() {
+                                        if let Some(pickup) = &world_state.energy_pickups.entities[i] {
+                                            write_vec3_to_buffer(&mut bytes, pickup.position);
+                                            write_f32_to_buffer(&mut bytes, pickup.amount);
+                                            write_f32_to_buffer(&mut bytes, pickup.respawn_delay);
+                                        }
+                                    }
+
+                                    //Write hazard region data
+                                    write_u32_to_buffer(&mut bytes, world_state.hazard_regions.count() as u32);
+                                    for i in 0..world_state.hazard_regions.len() {
+                                        if let Some(hazard) = &world_state.hazard_regions.entities[i] {
+                                            write_vec3_to_buffer(&mut bytes, hazard.position);
+                                            write_f32_to_buffer(&mut bytes, hazard.radius);
+                                            write_f32_to_buffer(&mut bytes, hazard.damage_per_second);
+                                        }
+                                    }
+
+                                    //Write respawn point data
+                                    write_u32_to_buffer(&mut bytes, world_state.respawn_points.count() as u32);
+                                    for i in 0..world_state.respawn_points.len() {
+                                        if let Some(point) = &world_state.respawn_points.entities[i] {
+                                            write_vec3_to_buffer(&mut bytes, point.position);
+                                        }
+                                    }
+
+                                    //Write reverb zone data
+                                    write_u32_to_buffer(&mut bytes, world_state.reverb_zones.count() as u32);
+                                    for i in 0..world_state.reverb_zones.len() {
+                                        if let Some(zone) = &world_state.reverb_zones.entities[i] {
+                                            write_vec3_to_buffer(&mut bytes, zone.position);
+                                            write_f32_to_buffer(&mut bytes, zone.radius);
+                                            write_f32_to_buffer(&mut bytes, zone.preset as usize as f32);
+                                        }
+                                    }
     
                                     match file.write(&bytes) {
                                         Ok(n) => {
@@ -2273,18 +3092,110 @@ fn main() {
                         if do_vsync { glfw.set_swap_interval(SwapInterval::Sync(1)); }
                         else { glfw.set_swap_interval(SwapInterval::None); }
                     }
+                    let mut desktop_play = desktop_player.enabled;
+                    if imgui_ui.checkbox("Desktop play (F1 to exit)", &mut desktop_play) {
+                        desktop_player.set_enabled(desktop_play, &mut window, &mut world_state.freecam);
+                    }
+                }
+                if gamepad.is_connected() {
+                    if gamepad.menu_mode {
+                        imgui_ui.text("Gamepad is navigating the menu");
+                    } else {
+                        imgui_ui.text("Gamepad connected");
+                    }
                 }
                 imgui_ui.checkbox("Camera collision", &mut world_state.freecam.is_collidable);
-                if let Some(_) = &xr_instance {
-                    imgui_ui.checkbox("HMD Perspective", &mut hmd_pov);
-                    imgui_ui.checkbox("Infinite ammo", &mut infinite_ammo);
+                if xr_instance.is_some() || desktop_player.enabled {
+                    imgui_ui.text(format!("Player health: {:.0}/{:.0}", world_state.player.health, damage::PLAYER_MAX_HEALTH));
+                    if let Some(_) = &xr_instance {
+                        imgui_ui.checkbox("HMD Perspective", &mut hmd_pov);
+                    }
+                    imgui_ui.checkbox("Infinite ammo", &mut gadget_settings.infinite_ammo);
+                    imgui_ui.checkbox("Grapple to any terrain", &mut gadget_settings.grapple_any_terrain);
+                    Slider::new("Grapple range", 5.0, 100.0).build(&imgui_ui, &mut gadget_settings.grapple_range);
+                    Slider::new("Reel speed", 1.0, 30.0).build(&imgui_ui, &mut gadget_settings.reel_speed);
+                    Slider::new("Min rope length", 0.5, 10.0).build(&imgui_ui, &mut gadget_settings.min_rope_length);
+                    for (name, rules) in gadget_settings.energy_rules.iter_mut() {
+                        imgui_ui.text(*name);
+                        imgui::Drag::new(format!("Capacity###{}_capacity", name)).speed(0.1).build(&imgui_ui, &mut rules.capacity);
+                        imgui::Drag::new(format!("Drain scale###{}_drain_scale", name)).speed(0.01).build(&imgui_ui, &mut rules.drain_scale);
+
+                        let mut recharge_idx = rules.recharge.index();
+                        if imgui_ui.combo_simple_string(format!("Recharge###{}_recharge", name), &mut recharge_idx, &Recharge::NAMES) && recharge_idx != rules.recharge.index() {
+                            if let Some(recharge) = Recharge::from_index(recharge_idx) {
+                                rules.recharge = recharge;
+                            }
+                        }
+                        if let Recharge::OverTime { rate, delay } = &mut rules.recharge {
+                            imgui::Drag::new(format!("Recharge rate###{}_rate", name)).speed(0.01).build(&imgui_ui, rate);
+                            imgui::Drag::new(format!("Recharge delay###{}_delay", name)).speed(0.01).build(&imgui_ui, delay);
+                            *rate = f32::max(*rate, 0.0);
+                            *delay = f32::max(*delay, 0.0);
+                        }
+                        rules.capacity = f32::max(rules.capacity, 0.0);
+                        rules.drain_scale = f32::max(rules.drain_scale, 0.0);
+                    }
+                    imgui_ui.separator();
+
+                    imgui_ui.text("Locomotion");
+                    do_radio_button(&imgui_ui, "Smooth movement", &mut locomotion_settings.move_mode, MoveMode::Smooth);
+                    do_radio_button(&imgui_ui, "Teleport", &mut locomotion_settings.move_mode, MoveMode::Teleport);
+                    do_radio_button(&imgui_ui, "Snap turn", &mut locomotion_settings.turn_mode, TurnMode::Snap);
+                    do_radio_button(&imgui_ui, "Smooth turn", &mut locomotion_settings.turn_mode, TurnMode::Smooth);
+                    do_radio_button(&imgui_ui, "No turning", &mut locomotion_settings.turn_mode, TurnMode::Off);
+                    match locomotion_settings.turn_mode {
+                        TurnMode::Snap => { Slider::new("Snap angle", 15.0, 90.0).build(&imgui_ui, &mut locomotion_settings.snap_turn_degrees); }
+                        TurnMode::Smooth => { Slider::new("Turn speed", 30.0, 360.0).build(&imgui_ui, &mut locomotion_settings.smooth_turn_speed); }
+                        TurnMode::Off => {}
+                    }
+                    imgui_ui.checkbox("Comfort vignette", &mut locomotion_settings.comfort_vignette);
                 }
                 imgui_ui.separator();
                 
                 //Music controls section
                 imgui_ui.text("Music controls");
-                if Slider::new("Master Volume", 0.0, 100.0).build(&imgui_ui, &mut bgm_volume) {
-                    send_or_error(&audio_sender, AudioCommand::SetListenerGain(bgm_volume));
+                if Slider::new("Master Volume", 0.0, 100.0).build(&imgui_ui, &mut master_volume) {
+                    send_or_error(&audio_sender, AudioCommand::SetListenerGain(master_volume));
+                    mixer_changed = true;
+                }
+                for bus in Bus::ALL.iter() {
+                    let settings = mixer.bus_mut(*bus);
+                    if Slider::new(&format!("{} Volume", bus.name()), 0.0, 100.0).build(&imgui_ui, &mut settings.volume) {
+                        send_or_error(&audio_sender, AudioCommand::SetBusVolume(*bus, settings.volume));
+                        mixer_changed = true;
+                    }
+                    imgui_ui.same_line();
+                    if imgui_ui.checkbox(&format!("M###mute_{}", bus.name()), &mut settings.muted) {
+                        send_or_error(&audio_sender, AudioCommand::SetBusMuted(*bus, settings.muted));
+                    }
+                    imgui_ui.same_line();
+                    if imgui_ui.checkbox(&format!("S###solo_{}", bus.name()), &mut settings.soloed) {
+                        send_or_error(&audio_sender, AudioCommand::SetBusSoloed(*bus, settings.soloed));
+                    }
+                }
+
+                //Choosing "Default" follows whatever the system's default device is
+                let mut device_strs = vec!["Default"];
+                for device in audio_devices.iter() {
+                    device_strs.push(device.as_str());
+                }
+                let mut device_idx = match &preferred_audio_device {
+                    Some(name) => { audio_devices.iter().position(|device| device == name).map_or(0, |i| i + 1) }
+                    None => { 0 }
+                };
+                if imgui_ui.combo_simple_string("Output device", &mut device_idx, &device_strs) {
+                    preferred_audio_device = if device_idx == 0 { None } else { Some(audio_devices[device_idx - 1].clone()) };
+                    send_or_error(&audio_sender, AudioCommand::SetOutputDevice(preferred_audio_device.clone()));
+                    audio_device_changed = true;
+                }
+                imgui_ui.same_line();
+                if do_button(&imgui_ui, "Refresh") {
+                    send_or_error(&audio_sender, AudioCommand::RefreshOutputDevices);
+                }
+                if let Some(name) = &audio_device {
+                    imgui_ui.text(format!("Playing on: {}", name));
+                } else if audio_device_lost {
+                    imgui_ui.text("Audio device lost, waiting for one to come back...");
                 }
 
                 if do_button(&imgui_ui, "Play/Pause") {
@@ -2295,15 +3206,50 @@ fn main() {
                     send_or_error(&audio_sender, AudioCommand::RestartBGM);
                 }
                 imgui_ui.same_line();
-                if do_button(&imgui_ui, "Choose mp3") {
+                if do_button(&imgui_ui, "Choose music") {
                     send_or_error(&audio_sender, AudioCommand::SelectNewBGM);
                 }
+                if imgui_ui.checkbox("Dynamic music", &mut music_director.enabled) {
+                    send_or_error(&audio_sender, AudioCommand::SetDynamicMusic(music_director.enabled));
+                }
+                if music_director.enabled {
+                    imgui_ui.same_line();
+                    imgui_ui.text(format!("({})", music_director.state.name()));
+                }
+
+                imgui_ui.text("Music visuals:");
+                for i in 0..spectrum::BAND_COUNT {
+                    imgui::ProgressBar::new(music_bands[i]).overlay_text(spectrum::BAND_NAMES[i]).build(&imgui_ui);
+                }
+                do_spectrum_binding(&imgui_ui, "Light flicker", &mut spectrum_bindings.flicker_amplitude);
+                do_spectrum_binding(&imgui_ui, "Sun size", &mut spectrum_bindings.sun_size);
+                do_spectrum_binding(&imgui_ui, "Glitch intensity", &mut spectrum_bindings.glitch_intensity);
+
+                //With the recording backend there's nothing to hear, so show what would have played instead
+                if let Some(recording) = &audio_recording {
+                    imgui_ui.text(format!("Recording audio: {} sounds played", recording.sfx_played_count()));
+                    for (time, request) in recording.recent_sfx(5).iter() {
+                        let p = request.position;
+                        imgui_ui.text(format!("{:.2}s {} at ({:.1}, {:.1}, {:.1})", time, request.path, p[0], p[1], p[2]));
+                    }
+                }
 
                 imgui_ui.separator();
 
                 if Slider::new("Timescale", 0.001, 2.0).build(&imgui_ui, &mut world_state.delta_timescale) {
                     send_or_error(&audio_sender, AudioCommand::SetPitchShift(world_state.delta_timescale));
                 }
+
+                //Gameplay RNG seed
+                imgui_ui.text(format!("RNG seed: {}", world_state.rng.seed()));
+                imgui::InputText::new(&imgui_ui, "###seed_input", &mut seed_string).build();
+                imgui_ui.same_line();
+                if do_button(&imgui_ui, "Reseed") {
+                    match seed_string.trim().parse::<u32>() {
+                        Ok(seed) => { world_state.rng.reseed(seed); }
+                        Err(e) => { tfd::message_box_ok("Invalid seed", &format!("\"{}\" is not a valid seed: {}", seed_string, e), tfd::MessageBoxIcon::Warning); }
+                    }
+                }
                 
                 //Reset player position button
                 if let Some(_) = &xr_instance {
@@ -2328,12 +3274,26 @@ fn main() {
             if entity_panel {
                 if let Some(win_token) = imgui::Window::new("Entity panel").begin(&imgui_ui) {
                     imgui_ui.text(format!("Totoros spawned: {}", world_state.totoros.count()));
+                    imgui_ui.text(format!("Totoro spawners: {}", world_state.totoro_spawners.count()));
+                    imgui_ui.text(format!("Points of interest: {}", world_state.points_of_interest.count()));
+                    imgui_ui.text(format!("Water volumes: {}", world_state.water_volumes.count()));
+                    imgui_ui.text(format!("Energy pickups: {}", world_state.energy_pickups.count()));
+                    imgui_ui.text(format!("Hazard regions: {}", world_state.hazard_regions.count()));
+                    imgui_ui.text(format!("Respawn points: {}", world_state.respawn_points.count()));
+                    imgui_ui.text(format!("Reverb zones: {}", world_state.reverb_zones.count()));
                     imgui_ui.text(format!("Point lights count: {}/{}", scene_data.point_lights.count(), render::MAX_POINT_LIGHTS));
                     imgui_ui.separator();
 
                     imgui_ui.text("Visualizers");
                     imgui_ui.checkbox("View point lights", &mut viewing_point_lights);
                     imgui_ui.checkbox("View player spawn", &mut viewing_player_spawn);
+                    imgui_ui.checkbox("View totoro spawners", &mut viewing_totoro_spawners);
+                    imgui_ui.checkbox("View points of interest", &mut viewing_points_of_interest);
+                    imgui_ui.checkbox("View water volumes", &mut viewing_water_volumes);
+                    imgui_ui.checkbox("View energy pickups", &mut viewing_energy_pickups);
+                    imgui_ui.checkbox("View hazard regions", &mut viewing_hazard_regions);
+                    imgui_ui.checkbox("View respawn points", &mut viewing_respawn_points);
+                    imgui_ui.checkbox("View reverb zones", &mut viewing_reverb_zones);
                     if imgui_ui.checkbox("View collision triangles", &mut viewing_triangles) {
                         if let Some(re) = scene_data.transparent_entities.get_mut_element(world_state.collision_re_index) {
                             let mat = if viewing_triangles { glm::identity::<f32, 4>() }
@@ -2346,12 +3306,30 @@ fn main() {
                     imgui_ui.separator();
                     imgui_ui.text("Click actions");
                     do_radio_button(&imgui_ui, "Create totoro", &mut click_action, ClickAction::CreateTotoro);
+                    do_radio_button(&imgui_ui, "Create totoro spawner", &mut click_action, ClickAction::CreateTotoroSpawner);
+                    do_radio_button(&imgui_ui, "Create point of interest", &mut click_action, ClickAction::CreatePointOfInterest);
+                    imgui_ui.same_line();
+                    imgui_ui.combo_simple_string("###new_poi_kind", &mut new_poi_kind, &PoiKind::NAMES);
+                    do_radio_button(&imgui_ui, "Create water volume", &mut click_action, ClickAction::CreateWaterVolume);
+                    do_radio_button(&imgui_ui, "Create energy pickup", &mut click_action, ClickAction::CreateEnergyPickup);
+                    do_radio_button(&imgui_ui, "Create hazard region", &mut click_action, ClickAction::CreateHazardRegion);
+                    do_radio_button(&imgui_ui, "Create respawn point", &mut click_action, ClickAction::CreateRespawnPoint);
+                    do_radio_button(&imgui_ui, "Create reverb zone", &mut click_action, ClickAction::CreateReverbZone);
+                    imgui_ui.same_line();
+                    imgui_ui.combo_simple_string("###new_reverb_preset", &mut new_reverb_preset, &ReverbPreset::NAMES);
                     do_radio_button(&imgui_ui, "Create light source", &mut click_action, ClickAction::CreatePointLight);
                     do_radio_button(&imgui_ui, "Delete object", &mut click_action, ClickAction::DeleteObject);
                     do_radio_button(&imgui_ui, "Move player spawn", &mut click_action, ClickAction::MovePlayerSpawn);
                     do_radio_button(&imgui_ui, "Toggle collision triangle's grabbability", &mut click_action, ClickAction::ToggleGrabbableTriangle);
                     imgui_ui.separator();
                     imgui_ui.checkbox("Turbo clicking", &mut turbo_clicking);
+                    imgui_ui.separator();
+
+                    imgui_ui.text(format!("Totoro deaths: {}", session_stats.totoro_deaths));
+                    imgui_ui.text(format!("Player deaths: {}", session_stats.player_deaths));
+                    for message in totoro_event_log.iter() {
+                        imgui_ui.text(message);
+                    }
 
                     if do_button(&imgui_ui, "Delete all totoros") {
                         world_state.totoros.clear();
@@ -2505,9 +3483,15 @@ fn main() {
                     imgui_ui.text(format!("Velocity ({:.3}, {:.3}, {:.3})", tot.velocity.x, tot.velocity.y, tot.velocity.z));
                     imgui_ui.text(format!("AI state: {:?}", tot.state));
                     imgui_ui.text(format!("AI timer state: {:.5}/{:.5}", scene_data.elapsed_time - tot.state_timer, tot.relax_duration));
+                    imgui_ui.text(format!("Activity: {:?}", tot.activity));
+                    imgui::Slider::new("Hunger", 0.0, 1.0).build(&imgui_ui, &mut tot.needs.hunger);
+                    imgui::Slider::new("Rest", 0.0, 1.0).build(&imgui_ui, &mut tot.needs.rest);
+                    imgui::Slider::new("Social", 0.0, 1.0).build(&imgui_ui, &mut tot.needs.social);
                             
                     imgui_ui.separator();
                     imgui::Slider::new("Health", 0.0, 100.0).build(&imgui_ui, &mut tot.health);
+                    imgui_ui.text(format!("Breath: {:.2}/{:.2}", tot.breath, damage::MAX_BREATH));
+                    imgui_ui.text(format!("Last damaged by: {:?}", tot.last_damage_source));
                     imgui::Slider::new("Scale", 0.1, 4.0).build(&imgui_ui, &mut tot.scale);
 
                     if do_button(&imgui_ui, "Toggle AI") {
@@ -2531,6 +3515,168 @@ fn main() {
                 }
             }
 
+            //Do selected Totoro spawner window
+            if let Some(idx) = world_state.totoro_spawners.selected_idx {
+                let spawner = world_state.totoro_spawners.get_mut_element(idx).unwrap();
+                if let Some(token) = imgui::Window::new(format!("Totoro spawner #{} control panel###totoro_spawner_panel", idx)).begin(&imgui_ui) {
+                    do_readwrite_vec3(&imgui_ui, "Position", &mut spawner.position);
+                    imgui_ui.text(format!("Population: {}/{}", spawner.population, spawner.max_population));
+                    imgui_ui.text(format!("Respawn timer: {:.2}/{:.2}", spawner.respawn_timer, spawner.respawn_delay));
+
+                    imgui_ui.separator();
+                    imgui::Slider::new("Spawn radius", 0.0, 50.0).build(&imgui_ui, &mut spawner.radius);
+                    imgui::Slider::new("Max population", 0, 32).build(&imgui_ui, &mut spawner.max_population);
+                    imgui::Slider::new("Respawn delay", 0.0, 120.0).build(&imgui_ui, &mut spawner.respawn_delay);
+                    imgui::Slider::new("Min scale", 0.1, 4.0).build(&imgui_ui, &mut spawner.min_scale);
+                    imgui::Slider::new("Max scale", 0.1, 4.0).build(&imgui_ui, &mut spawner.max_scale);
+                    if spawner.max_scale < spawner.min_scale {
+                        spawner.max_scale = spawner.min_scale;
+                    }
+
+                    imgui_ui.separator();
+                    do_radio_button(&imgui_ui, "Reposition spawner", &mut click_action, ClickAction::MoveSelectedTotoroSpawner);
+                    if do_button(&imgui_ui, "Delete this spawner") {
+                        delete_totoro_spawner(&mut world_state, idx);
+                    }
+
+                    if do_button(&imgui_ui, "Close") { world_state.totoro_spawners.selected_idx = None; }
+
+                    token.end();
+                }
+            }
+
+            //Do selected point of interest window
+            if let Some(idx) = world_state.points_of_interest.selected_idx {
+                let poi = world_state.points_of_interest.get_mut_element(idx).unwrap();
+                if let Some(token) = imgui::Window::new(format!("Point of interest #{} control panel###poi_panel", idx)).begin(&imgui_ui) {
+                    do_readwrite_vec3(&imgui_ui, "Position", &mut poi.position);
+
+                    let mut kind_idx = poi.kind as usize;
+                    if imgui_ui.combo_simple_string("Kind", &mut kind_idx, &PoiKind::NAMES) {
+                        if let Some(kind) = PoiKind::from_index(kind_idx) {
+                            poi.kind = kind;
+                        }
+                    }
+                    imgui::Slider::new("Radius", 0.1, 10.0).build(&imgui_ui, &mut poi.radius);
+
+                    imgui_ui.separator();
+                    do_radio_button(&imgui_ui, "Reposition point of interest", &mut click_action, ClickAction::MoveSelectedPointOfInterest);
+                    if do_button(&imgui_ui, "Delete this point of interest") {
+                        delete_point_of_interest(&mut world_state, idx);
+                    }
+
+                    if do_button(&imgui_ui, "Close") { world_state.points_of_interest.selected_idx = None; }
+
+                    token.end();
+                }
+            }
+
+            //Do selected water volume window
+            if let Some(idx) = world_state.water_volumes.selected_idx {
+                let volume = world_state.water_volumes.get_mut_element(idx).unwrap();
+                if let Some(token) = imgui::Window::new(format!("Water volume #{} control panel###water_volume_panel", idx)).begin(&imgui_ui) {
+                    do_readwrite_vec3(&imgui_ui, "Surface position", &mut volume.position);
+                    imgui::Slider::new("Radius", 0.1, 50.0).build(&imgui_ui, &mut volume.radius);
+                    imgui::Slider::new("Depth", 0.1, 20.0).build(&imgui_ui, &mut volume.depth);
+
+                    imgui_ui.separator();
+                    do_radio_button(&imgui_ui, "Reposition water volume", &mut click_action, ClickAction::MoveSelectedWaterVolume);
+                    if do_button(&imgui_ui, "Delete this water volume") {
+                        world_state.water_volumes.delete(idx);
+                    }
+
+                    if do_button(&imgui_ui, "Close") { world_state.water_volumes.selected_idx = None; }
+
+                    token.end();
+                }
+            }
+
+            //Do selected energy pickup window
+            if let Some(idx) = world_state.energy_pickups.selected_idx {
+                let pickup = world_state.energy_pickups.get_mut_element(idx).unwrap();
+                if let Some(token) = imgui::Window::new(format!("Energy pickup #{} control panel###energy_pickup_panel", idx)).begin(&imgui_ui) {
+                    do_readwrite_vec3(&imgui_ui, "Position", &mut pickup.position);
+                    imgui::Slider::new("Refill amount", 0.0, 1.0).build(&imgui_ui, &mut pickup.amount);
+                    imgui::Slider::new("Respawn delay", 0.0, 120.0).build(&imgui_ui, &mut pickup.respawn_delay);
+                    if !pickup.is_available() {
+                        imgui_ui.text(format!("Respawning in {:.1}s", pickup.cooldown));
+                    }
+
+                    imgui_ui.separator();
+                    do_radio_button(&imgui_ui, "Reposition energy pickup", &mut click_action, ClickAction::MoveSelectedEnergyPickup);
+                    if do_button(&imgui_ui, "Delete this energy pickup") {
+                        world_state.energy_pickups.delete(idx);
+                    }
+
+                    if do_button(&imgui_ui, "Close") { world_state.energy_pickups.selected_idx = None; }
+
+                    token.end();
+                }
+            }
+
+            //Do selected hazard region window
+            if let Some(idx) = world_state.hazard_regions.selected_idx {
+                let hazard = world_state.hazard_regions.get_mut_element(idx).unwrap();
+                if let Some(token) = imgui::Window::new(format!("Hazard region #{} control panel###hazard_region_panel", idx)).begin(&imgui_ui) {
+                    do_readwrite_vec3(&imgui_ui, "Position", &mut hazard.position);
+                    imgui::Slider::new("Radius", 0.1, 20.0).build(&imgui_ui, &mut hazard.radius);
+                    imgui::Slider::new("Damage per second", 0.0, 200.0).build(&imgui_ui, &mut hazard.damage_per_second);
+
+                    imgui_ui.separator();
+                    do_radio_button(&imgui_ui, "Reposition hazard region", &mut click_action, ClickAction::MoveSelectedHazardRegion);
+                    if do_button(&imgui_ui, "Delete this hazard region") {
+                        world_state.hazard_regions.delete(idx);
+                    }
+
+                    if do_button(&imgui_ui, "Close") { world_state.hazard_regions.selected_idx = None; }
+
+                    token.end();
+                }
+            }
+
+            //Do selected respawn point window
+            if let Some(idx) = world_state.respawn_points.selected_idx {
+                let point = world_state.respawn_points.get_mut_element(idx).unwrap();
+                if let Some(token) = imgui::Window::new(format!("Respawn point #{} control panel###respawn_point_panel", idx)).begin(&imgui_ui) {
+                    do_readwrite_vec3(&imgui_ui, "Position", &mut point.position);
+
+                    imgui_ui.separator();
+                    do_radio_button(&imgui_ui, "Reposition respawn point", &mut click_action, ClickAction::MoveSelectedRespawnPoint);
+                    if do_button(&imgui_ui, "Delete this respawn point") {
+                        world_state.respawn_points.delete(idx);
+                    }
+
+                    if do_button(&imgui_ui, "Close") { world_state.respawn_points.selected_idx = None; }
+
+                    token.end();
+                }
+            }
+
+            //Do selected reverb zone window
+            if let Some(idx) = world_state.reverb_zones.selected_idx {
+                let zone = world_state.reverb_zones.get_mut_element(idx).unwrap();
+                if let Some(token) = imgui::Window::new(format!("Reverb zone #{} control panel###reverb_zone_panel", idx)).begin(&imgui_ui) {
+                    do_readwrite_vec3(&imgui_ui, "Position", &mut zone.position);
+                    imgui::Slider::new("Radius", 0.5, 100.0).build(&imgui_ui, &mut zone.radius);
+                    let mut preset_idx = zone.preset as usize;
+                    if imgui_ui.combo_simple_string("Preset", &mut preset_idx, &ReverbPreset::NAMES) {
+                        if let Some(preset) = ReverbPreset::from_index(preset_idx) {
+                            zone.preset = preset;
+                        }
+                    }
+
+                    imgui_ui.separator();
+                    do_radio_button(&imgui_ui, "Reposition reverb zone", &mut click_action, ClickAction::MoveSelectedReverbZone);
+                    if do_button(&imgui_ui, "Delete this reverb zone") {
+                        world_state.reverb_zones.delete(idx);
+                    }
+
+                    if do_button(&imgui_ui, "Close") { world_state.reverb_zones.selected_idx = None; }
+
+                    token.end();
+                }
+            }
+
             //Do selected point light window
             if let Some(idx) = scene_data.point_lights.selected_idx {
                 let light = scene_data.point_lights.get_mut_element(idx).unwrap();
@@ -2587,7 +3733,7 @@ fn main() {
                         0.0, 0.0, 0.0, 1.0
                     );
 
-                    let mm = glm::translation(&totoro.position) * rotation_mat * uniform_scale(totoro.scale);
+                    let mm = glm::translation(&totoro.position) * rotation_mat * uniform_scale(totoro.scale) * totoro.animator.local_transform();
                     write_matrix_to_buffer(&mut transform_buffer, current_totoro, mm);
 
                     if let Some(idx) = world_state.totoros.selected_idx {
@@ -2647,7 +3793,7 @@ fn main() {
                     buffer[(current_light + MAX_POINT_LIGHTS) * 4 + 2] = light.color[2];
                     
                     //Modulate power
-                    let offset = light.flicker_amplitude * simplex.get([0.0, light.flicker_timescale as f64 * scene_data.elapsed_time as f64]) as f32;
+                    let offset = light.flicker_amplitude * scene_data.flicker_scale * simplex.get([0.0, light.flicker_timescale as f64 * scene_data.elapsed_time as f64]) as f32;
                     buffer[(2 * MAX_POINT_LIGHTS) * 4 + current_light] = light.power + offset;
         
                     current_light += 1;
@@ -2693,6 +3839,10 @@ fn main() {
         ));
 
         //Create a view matrix from the camera state
+        //In desktop mode the camera is the player's head
+        if desktop_player.enabled {
+            world_state.freecam.position = world_state.player.tracked_segment.p0;
+        }
         {
             let new_view_matrix = glm::rotation(world_state.freecam.orientation.y, &glm::vec3(1.0, 0.0, 0.0)) *
                                   glm::rotation(world_state.freecam.orientation.x, &Z_UP) *
@@ -2729,40 +3879,16 @@ fn main() {
 
                             //Right here is where we want to update the controller objects' transforms
                             {
-                                if let Some(pose) = &left_grip_pose {
-                                    match gadget_model_map.get_mut(&left_hand_gadget) {
-                                        Some(entity_idx) => {
-                                            if let Some(entity) = scene_data.opaque_entities.get_mut_element(*entity_idx) {
-                                                entity.update_single_transform(0, &xrutil::pose_to_mat4(pose, &world_from_tracking));
-                                            }
-                                        }
-                                        None => { println!("No model for gadget {:?}", left_hand_gadget); }
-                                    }
-                                }
-
-                                if let Some(pose) = &right_grip_pose {
-                                    match gadget_model_map.get_mut(&right_hand_gadget) {
-                                        Some(entity_idx) => {
-                                            if let Some(entity) = scene_data.opaque_entities.get_mut_element(*entity_idx) {
-                                                entity.update_single_transform(1, &xrutil::pose_to_mat4(pose, &world_from_tracking));
-                                            }
-                                        }
-                                        None => { println!("No model for gadget {:?}", right_hand_gadget); }
-                                    }
-                                }
-                            }
-
-                            //Apply the water pillar scales
-                            {
-                                let poses = [left_hand_aim_pose, right_hand_aim_pose];
-                                let scales = [&left_water_pillar_scale, &right_water_pillar_scale];
-                                for i in 0..poses.len() {
-                                    if let Some(p) = poses[i] {
-                                        if let Some(entity) = scene_data.opaque_entities.get_mut_element(water_cylinder_entity_index) {
-                                            let mm = xrutil::pose_to_mat4(&p, &world_from_tracking) * glm::scaling(scales[i]);
-                                            entity.update_single_transform(i, &mm);
-                                        }
-                                    }
+                                let grip_poses = [left_grip_pose, right_grip_pose];
+                                let aim_poses = [left_hand_aim_pose, right_hand_aim_pose];
+                                for i in 0..gadget_hands.len() {
+                                    let mut ctx = GadgetRenderContext {
+                                        hand: gadget_hands[i].hand,
+                                        scene_data: &mut scene_data,
+                                        grip_transform: grip_poses[i].map(|pose| xrutil::pose_to_mat4(&pose, &world_from_tracking)),
+                                        aim_transform: aim_poses[i].map(|pose| xrutil::pose_to_mat4(&pose, &world_from_tracking))
+                                    };
+                                    gadget_hands[i].render(&mut ctx);
                                 }
                             }
 
@@ -2805,6 +3931,7 @@ fn main() {
                                         perspective
                                     );
                                     render::main_scene(&sc_rendertarget.framebuffer, &scene_data, &view_data);
+                                    render::view_overlay(&sc_rendertarget.framebuffer, vignette_program, vignette_vao, comfort_vignette.strength, world_state.player.life_state.fade());
     
                                     //Blit the MSAA image into the swapchain image
                                     let color_texture = sc_images[i][image_index as usize];
@@ -2888,6 +4015,19 @@ fn main() {
 
             //Main window rendering
             if !hmd_pov {
+                //The virtual hands hold the gadgets in desktop mode
+                if desktop_player.enabled {
+                    for i in 0..gadget_hands.len() {
+                        let mut ctx = GadgetRenderContext {
+                            hand: gadget_hands[i].hand,
+                            scene_data: &mut scene_data,
+                            grip_transform: aim_poses[i].map(|pose| xrutil::pose_to_mat4(&pose, &world_from_tracking)),
+                            aim_transform: aim_poses[i].map(|pose| xrutil::pose_to_mat4(&pose, &world_from_tracking))
+                        };
+                        gadget_hands[i].render(&mut ctx);
+                    }
+                }
+
                 //Render shadows
                 let projection = &world_state.freecam.clipping_from_view;
                 let v_mat = &world_state.freecam.view_from_world;
@@ -2901,6 +4041,9 @@ fn main() {
                     world_state.freecam.clipping_from_view
                 );
                 render::main_scene(&core_rt.framebuffer, &scene_data, &freecam_viewdata);
+                if desktop_player.enabled {
+                    render::view_overlay(&core_rt.framebuffer, vignette_program, vignette_vao, comfort_vignette.strength, world_state.player.life_state.fade());
+                }
 
                 //Resolving the MSAA rendertarget
                 //Both framebuffers have an internal format of gl::SRGB8_ALPHA8
@@ -2993,4 +4136,20 @@ fn main() {
         window.swap_buffers();  //Display the rendered frame to the window
         glfw.poll_events();     //Poll events for next frame
     }
+
+    //Remember the settings for next time, only rewriting the file if something changed
+    let loaded_config = config.clone();
+    if mixer_changed || audio_device_changed {
+        config.float_options.insert(String::from(Configuration::MASTER_VOLUME), master_volume);
+        mixer.save_to_config(&mut config);
+        match &preferred_audio_device {
+            Some(name) => { config.string_options.insert(String::from(Configuration::AUDIO_DEVICE), Configuration::escape(name)); }
+            None => { config.string_options.remove(Configuration::AUDIO_DEVICE); }
+        }
+    }
+    gadget_settings.save_to_config(&mut config);
+    locomotion_settings.save_to_config(&mut config);
+    if config != loaded_config {
+        config.to_file(Configuration::CONFIG_FILEPATH);
+    }
 }
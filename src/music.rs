@@ -0,0 +1,90 @@
+use crate::audio::MusicState;
+use crate::gamestate::{MoveState, TotoroState, WorldState};
+
+//Decides what the music should be doing based on what's happening in the game
+//The audio thread handles lining the transitions up with the beat
+
+const DANGER_RADIUS: f32 = 20.0;            //Panicking Totoros farther away than this don't count as danger
+const DANGER_FALL_SPEED: f32 = 8.0;         //Falling faster than this is dangerous
+const DANGER_LINGER_TIME: f32 = 6.0;        //Seconds the danger music keeps playing after the danger is gone
+
+pub struct MusicDirector {
+    pub enabled: bool,
+    pub state: MusicState,
+    had_totoros: bool,
+    danger_timer: f32
+}
+
+impl MusicDirector {
+    pub fn new() -> Self {
+        MusicDirector {
+            enabled: true,
+            state: MusicState::Explore,
+            had_totoros: false,
+            danger_timer: 0.0
+        }
+    }
+
+    //Forgets about the last level, so a newly loaded one doesn't start out cleared
+    //The state itself is left alone so the next update reports the change
+    pub fn reset(&mut self) {
+        self.had_totoros = false;
+        self.danger_timer = 0.0;
+    }
+
+    //Returns the new state when it changes
+    pub fn update(&mut self, world_state: &WorldState, delta_time: f32) -> Option<MusicState> {
+        let player = &world_state.player;
+        let totoros = &world_state.totoros;
+
+        let mut living_totoros = 0;
+        let mut panic_nearby = false;
+        for i in 0..totoros.len() {
+            if let Some(totoro) = &totoros.entities[i] {
+                match totoro.state {
+                    TotoroState::StartDying | TotoroState::Dying | TotoroState::BrainDead => {}
+                    _ => { living_totoros += 1; }
+                }
+                match totoro.state {
+                    TotoroState::Startled | TotoroState::PrePanicking | TotoroState::Panicking => {
+                        if glm::distance(&player.tracked_segment.p1, &totoro.position) < DANGER_RADIUS {
+                            panic_nearby = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let falling_fast = player.movement_state == MoveState::Falling &&
+                           !player.is_climbing() &&
+                           player.tracking_velocity.z < -DANGER_FALL_SPEED;
+        if panic_nearby || falling_fast {
+            self.danger_timer = DANGER_LINGER_TIME;
+        } else {
+            self.danger_timer = f32::max(self.danger_timer - delta_time, 0.0);
+        }
+
+        //The level counts as cleared once every Totoro that was in it is gone
+        //Spawners keep bringing them back, so levels with spawners are never cleared
+        if living_totoros > 0 {
+            self.had_totoros = true;
+        }
+        let cleared = self.had_totoros && living_totoros == 0 && world_state.totoro_spawners.count() == 0;
+
+        let state = if self.danger_timer > 0.0 {
+            MusicState::Danger
+        } else if cleared {
+            MusicState::Cleared
+        } else {
+            MusicState::Explore
+        };
+
+        if state != self.state {
+            self.state = state;
+            Some(state)
+        } else {
+            None
+        }
+    }
+}
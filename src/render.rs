@@ -27,6 +27,8 @@ pub const DEBUG_HIGHLIGHTED_ATTRIBUTE: GLuint = 2;
 pub const DEBUG_COLOR_ATTRIBUTE: GLuint = 3;
 pub const DEBUG_TRANSFORM_ATTRIBUTE: GLuint = 4;
 
+pub const PARTICLE_TRANSFORM_ATTRIBUTE: GLuint = 2;
+
 //Represents all of the data necessary to render an object (potentially instanced) that exists in the 3D scene
 #[derive(Debug)]
 pub struct RenderEntity {
@@ -243,6 +245,23 @@ impl RenderEntity {
     }
 }
 
+//Creates a quad that the particle shader turns to face the viewer, meant to be instanced once per particle
+pub unsafe fn billboard_entity(program: GLuint, max_instances: usize) -> RenderEntity {
+    let vertices = [
+        -1.0f32, -1.0,
+        1.0, -1.0,
+        1.0, 1.0,
+        -1.0, 1.0
+    ];
+    let indices = [0u16, 1, 2, 0, 2, 3];
+    let vao = glutil::create_vertex_array_object(&vertices, &indices, &[2]);
+
+    let mut re = RenderEntity::from_vao(vao, program, indices.len(), max_instances, PARTICLE_TRANSFORM_ATTRIBUTE, false);
+    re.transparent = true;
+    re.active_instances = 0;
+    re
+}
+
 impl Drop for RenderEntity {
     fn drop(&mut self) {
         let texs = if self.using_cached_textures {
@@ -343,6 +362,9 @@ pub struct SceneData {
     pub sun_direction: glm::TVec3<f32>,
     pub sun_color: [f32; 3],
     pub sun_size: f32,
+    pub sun_size_scale: f32,            //These three follow the music every frame, so they're kept apart from the saved values
+    pub flicker_scale: f32,
+    pub glitch_intensity: f32,
     pub sun_shadow_map: CascadedShadowMap,
     pub shadow_intensity: f32,
     pub ambient_strength: f32,
@@ -389,6 +411,9 @@ impl Default for SceneData {
             sun_direction: glm::vec3(0.0, 0.0, 1.0),
             sun_color: [1.0, 1.0, 1.0],
             sun_size: 0.999,
+            sun_size_scale: 1.0,
+            flicker_scale: 1.0,
+            glitch_intensity: 1.0,
             shadow_intensity: 1.0,
             ambient_strength: 0.2,
             sun_shadow_map,
@@ -494,7 +519,7 @@ pub unsafe fn main_scene(framebuffer: &Framebuffer, scene_data: &SceneData, view
     glutil::bind_matrix4(scene_data.skybox_program, "view_projection", &skybox_view_projection);
     glutil::bind_vector3(scene_data.skybox_program, "sun_color", &sun_c);
     glutil::bind_vector3(scene_data.skybox_program, "sun_direction", &scene_data.sun_direction);
-    glutil::bind_float(scene_data.skybox_program, "sun_size", scene_data.sun_size);
+    glutil::bind_float(scene_data.skybox_program, "sun_size", scene_data.sun_size * scene_data.sun_size_scale);
     gl::BindTexture(gl::TEXTURE_CUBE_MAP, scene_data.skybox_cubemap);
     gl::BindVertexArray(scene_data.skybox_vao);
     gl::DrawElements(gl::TRIANGLES, CUBE_INDICES_COUNT, gl::UNSIGNED_SHORT, ptr::null());
@@ -651,6 +676,23 @@ pub fn compute_shadow_cascade_matrices(
     out_mats
 }
 
+//Darkens the edges of the image for the comfort vignette, and the whole image when fading to black, with a fullscreen triangle
+//The vao is empty since the vertex shader generates the triangle from gl_VertexID
+pub unsafe fn view_overlay(framebuffer: &Framebuffer, program: GLuint, vao: GLuint, vignette_strength: f32, fade: f32) {
+    if vignette_strength <= 0.0 && fade <= 0.0 {
+        return;
+    }
+
+    framebuffer.bind();
+    gl::UseProgram(program);
+    glutil::bind_float(program, "strength", vignette_strength);
+    glutil::bind_float(program, "fade", fade);
+    gl::Disable(gl::DEPTH_TEST);
+    gl::BindVertexArray(vao);
+    gl::DrawArrays(gl::TRIANGLES, 0, 3);
+    gl::Enable(gl::DEPTH_TEST);
+}
+
 pub unsafe fn post_processing(fbo_texture_view: GLuint, window_size: glm::TVec2<u32>, postfx_program: GLuint, scene_data: &SceneData) {
     gl::BindTexture(gl::TEXTURE_2D, fbo_texture_view);
     gl::GenerateMipmap(gl::TEXTURE_2D);
@@ -658,6 +700,7 @@ pub unsafe fn post_processing(fbo_texture_view: GLuint, window_size: glm::TVec2<
     //Binding the compute shader program
     gl::UseProgram(postfx_program);
     glutil::bind_float(postfx_program, "elapsed_time", scene_data.elapsed_time);
+    glutil::bind_float(postfx_program, "glitch_intensity", scene_data.glitch_intensity);
 
     let post_flag = match scene_data.postfx_flag {
         PostEffectFlag::PassThrough => { 0 }
@@ -0,0 +1,66 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use strum::EnumCount;
+
+//Each gameplay system draws from its own stream so that e.g. playing an extra yell doesn't change what the AI does
+#[derive(Clone, Copy, Debug, EnumCount, PartialEq, Eq)]
+pub enum RngStream {
+    Spawning,
+    Ai,
+    Audio,
+    Lighting
+}
+
+//Seedable source of randomness for everything that affects gameplay
+//Reusing a seed reproduces the same sequence of random decisions
+pub struct GameRng {
+    seed: u32,
+    streams: Vec<StdRng>
+}
+
+impl GameRng {
+    pub fn new(seed: u32) -> Self {
+        let mut rng = GameRng {
+            seed,
+            streams: Vec::with_capacity(RngStream::COUNT)
+        };
+        rng.reseed(seed);
+        rng
+    }
+
+    pub fn seed(&self) -> u32 { self.seed }
+
+    //Restarts every stream from the given seed
+    pub fn reseed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.streams.clear();
+        for i in 0..RngStream::COUNT {
+            //Give each stream a distinct seed derived from the main one
+            let stream_seed = (seed as u64) ^ ((i as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            self.streams.push(StdRng::seed_from_u64(stream_seed));
+        }
+    }
+
+    pub fn stream(&mut self, stream: RngStream) -> &mut StdRng {
+        &mut self.streams[stream as usize]
+    }
+
+    //Uniformly distributed in [0, 1)
+    pub fn random_f32(&mut self, stream: RngStream) -> f32 {
+        self.stream(stream).gen::<f32>()
+    }
+
+    pub fn ranged_f32(&mut self, stream: RngStream, min: f32, max: f32) -> f32 {
+        self.random_f32(stream) * (max - min) + min
+    }
+
+    //Difference of two uniform samples, so it's in (-1, 1) and clustered around zero
+    pub fn binomial(&mut self, stream: RngStream) -> f32 {
+        self.random_f32(stream) - self.random_f32(stream)
+    }
+
+    //Uniformly distributed in [0, len)
+    pub fn random_index(&mut self, stream: RngStream, len: usize) -> usize {
+        self.stream(stream).gen_range(0..len)
+    }
+}
@@ -17,7 +17,10 @@ use ozy::structs::OptionVec;
 use ozy::collision::*;
 
 use crate::traits::{SphereCollider};
+use crate::ai::{PoiKind, PointOfInterest};
+use crate::audio::ReverbPreset;
 use crate::gamestate::*;
+use crate::rng::RngStream;
 use crate::structs::*;
 use crate::render::NEAR_DISTANCE;
 use crate::*;
@@ -105,6 +108,19 @@ pub unsafe fn create_skybox_cubemap(sky_name: &str) -> GLuint {
 	cubemap
 }
 
+//Deselects every editor object, so that only one control panel is open at a time
+pub fn clear_selections(world_state: &mut WorldState, scene_data: &mut SceneData) {
+    world_state.totoros.selected_idx = None;
+    world_state.totoro_spawners.selected_idx = None;
+    world_state.points_of_interest.selected_idx = None;
+    world_state.water_volumes.selected_idx = None;
+    world_state.energy_pickups.selected_idx = None;
+    world_state.hazard_regions.selected_idx = None;
+    world_state.respawn_points.selected_idx = None;
+    world_state.reverb_zones.selected_idx = None;
+    scene_data.point_lights.selected_idx = None;
+}
+
 pub fn get_clicked_object<T: SphereCollider>(entity_list: &EntityList<T>, click_ray: &Ray) -> Option<(f32, usize)> {
     let mut smallest_t = f32::INFINITY;
     let mut hit_index = None;
@@ -216,14 +232,6 @@ pub fn compute_click_ray(camera: &Camera, window_size: glm::TVec2<f32>, screen_s
     }
 }
 
-pub fn rand_binomial() -> f32 {
-    rand::random::<f32>() - rand::random::<f32>()
-}
-
-pub fn ranged_randomf32(min: f32, max: f32) -> f32 {
-    rand::random::<f32>() * (max - min) + min
-}
-
 pub fn load_lvl(level_name: &str, world_state: &mut WorldState, scene_data: &mut SceneData, texture_keeper: &mut TextureKeeper, standard_program: GLuint) {    
     let level_load_error = |s: std::io::Error| {
         tfd::message_box_ok("Error loading level", &format!("Error reading from level {}: {}", level_name, s), tfd::MessageBoxIcon::Error);
@@ -302,6 +310,13 @@ pub fn load_ent(path: &str, scene_data: &mut SceneData, world_state: &mut WorldS
 
     //First, clear world data
     world_state.totoros.clear();
+    world_state.totoro_spawners.clear();
+    world_state.points_of_interest.clear();
+    world_state.water_volumes.clear();
+    world_state.energy_pickups.clear();
+    world_state.hazard_regions.clear();
+    world_state.respawn_points.clear();
+    world_state.reverb_zones.clear();
     scene_data.point_lights.clear();
 
     match File::open(path) {
@@ -351,8 +366,8 @@ pub fn load_ent(path: &str, scene_data: &mut SceneData, world_state: &mut WorldS
             let raw_floats = io_or_error(io::read_f32_data(&mut file, totoros_count as usize * floats_per_totoro), path);
             for i in (0..raw_floats.len()).step_by(floats_per_totoro) {
                 let pos = glm::vec3(raw_floats[i], raw_floats[i + 1], raw_floats[i + 2]);                
-                let mut tot = Totoro::new(pos, rand::random::<f32>() * 4.5 - 2.0);
-                tot.scale = raw_floats[i + 3];
+                let creation_time = world_state.rng.ranged_f32(RngStream::Spawning, -2.0, 2.5);
+                let tot = Totoro::new(pos, raw_floats[i + 3], creation_time, &mut world_state.rng);
                 world_state.totoros.insert(tot);
             }
 
@@ -371,6 +386,137 @@ pub fn load_ent(path: &str, scene_data: &mut SceneData, world_state: &mut WorldS
                 scene_data.point_lights.insert(light);
             }
 
+            //Load totoro spawners
+            //Older .ent files end after the lights, so EOF here just means there are no spawners
+            let floats_per_spawner = 8;
+            let spawner_count = match io::read_u32(&mut file) {
+                Ok(count) => { count }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { 0 }
+                Err(e) => { io_or_error(Err(e), path) }
+            };
+            let raw_floats = io_or_error(io::read_f32_data(&mut file, spawner_count as usize * floats_per_spawner), path);
+            for i in (0..raw_floats.len()).step_by(floats_per_spawner) {
+                let position = glm::vec3(raw_floats[i], raw_floats[i + 1], raw_floats[i + 2]);
+                let mut spawner = TotoroSpawner::new(position);
+                spawner.radius = raw_floats[i + 3];
+                spawner.max_population = raw_floats[i + 4] as u32;
+                spawner.respawn_delay = raw_floats[i + 5];
+                spawner.respawn_timer = spawner.respawn_delay;
+                spawner.min_scale = raw_floats[i + 6];
+                spawner.max_scale = raw_floats[i + 7];
+
+                world_state.totoro_spawners.insert(spawner);
+            }
+
+            //Load points of interest
+            let floats_per_poi = 5;
+            let poi_count = match io::read_u32(&mut file) {
+                Ok(count) => { count }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { 0 }
+                Err(e) => { io_or_error(Err(e), path) }
+            };
+            let raw_floats = io_or_error(io::read_f32_data(&mut file, poi_count as usize * floats_per_poi), path);
+            for i in (0..raw_floats.len()).step_by(floats_per_poi) {
+                let position = glm::vec3(raw_floats[i], raw_floats[i + 1], raw_floats[i + 2]);
+                let kind = match PoiKind::from_index(raw_floats[i + 3] as usize) {
+                    Some(k) => { k }
+                    None => {
+                        println!("Skipping point of interest with unknown kind {}", raw_floats[i + 3]);
+                        continue;
+                    }
+                };
+                let mut poi = PointOfInterest::new(position, kind);
+                poi.radius = raw_floats[i + 4];
+
+                world_state.points_of_interest.insert(poi);
+            }
+
+            //Load water volumes
+            let floats_per_water_volume = 5;
+            let water_volume_count = match io::read_u32(&mut file) {
+                Ok(count) => { count }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { 0 }
+                Err(e) => { io_or_error(Err(e), path) }
+            };
+            let raw_floats = io_or_error(io::read_f32_data(&mut file, water_volume_count as usize * floats_per_water_volume), path);
+            for i in (0..raw_floats.len()).step_by(floats_per_water_volume) {
+                let position = glm::vec3(raw_floats[i], raw_floats[i + 1], raw_floats[i + 2]);
+                let mut volume = WaterVolume::new(position);
+                volume.radius = raw_floats[i + 3];
+                volume.depth = raw_floats[i + 4];
+
+                world_state.water_volumes.insert(volume);
+            }
+
+            //Load energy pickups
+            let floats_per_energy_pickup = 5;
+            let energy_pickup_count = match io::read_u32(&mut file) {
+                Ok(count) => { count }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { 0 }
+                Err(e) => { io_or_error(Err(e), path) }
+            };
+            let raw_floats = io_or_error(io::read_f32_data(&mut file, energy_pickup_count as usize * floats_per_energy_pickup), path);
+            for i in (0..raw_floats.len()).step_by(floats_per_energy_pickup) {
+                let position = glm::vec3(raw_floats[i], raw_floats[i + 1], raw_floats[i + 2]);
+                let mut pickup = EnergyPickup::new(position);
+                pickup.amount = raw_floats[i + 3];
+                pickup.respawn_delay = raw_floats[i + 4];
+
+                world_state.energy_pickups.insert(pickup);
+            }
+
+            //Load hazard regions
+            let floats_per_hazard_region = 5;
+            let hazard_region_count = match io::read_u32(&mut file) {
+                Ok(count) => { count }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { 0 }
+                Err(e) => { io_or_error(Err(e), path) }
+            };
+            let raw_floats = io_or_error(io::read_f32_data(&mut file, hazard_region_count as usize * floats_per_hazard_region), path);
+            for i in (0..raw_floats.len()).step_by(floats_per_hazard_region) {
+                let position = glm::vec3(raw_floats[i], raw_floats[i + 1], raw_floats[i + 2]);
+                let mut hazard = HazardRegion::new(position);
+                hazard.radius = raw_floats[i + 3];
+                hazard.damage_per_second = raw_floats[i + 4];
+
+                world_state.hazard_regions.insert(hazard);
+            }
+
+            //Load respawn points
+            let floats_per_respawn_point = 3;
+            let respawn_point_count = match io::read_u32(&mut file) {
+                Ok(count) => { count }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { 0 }
+                Err(e) => { io_or_error(Err(e), path) }
+            };
+            let raw_floats = io_or_error(io::read_f32_data(&mut file, respawn_point_count as usize * floats_per_respawn_point), path);
+            for i in (0..raw_floats.len()).step_by(floats_per_respawn_point) {
+                let position = glm::vec3(raw_floats[i], raw_floats[i + 1], raw_floats[i + 2]);
+                world_state.respawn_points.insert(RespawnPoint { position });
+            }
+
+            //Load reverb zones
+            let floats_per_reverb_zone = 5;
+            let reverb_zone_count = match io::read_u32(&mut file) {
+                Ok(count) => { count }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { 0 }
+                Err(e) => { io_or_error(Err(e), path) }
+            };
+            let raw_floats = io_or_error(io::read_f32_data(&mut file, reverb_zone_count as usize * floats_per_reverb_zone), path);
+            for i in (0..raw_floats.len()).step_by(floats_per_reverb_zone) {
+                let position = glm::vec3(raw_floats[i], raw_floats[i + 1], raw_floats[i + 2]);
+                let preset = match ReverbPreset::from_index(raw_floats[i + 4] as usize) {
+                    Some(p) => { p }
+                    None => {
+                        println!("Skipping reverb zone with unknown preset {}", raw_floats[i + 4]);
+                        continue;
+                    }
+                };
+                let mut zone = ReverbZone::new(position, preset);
+                zone.radius = raw_floats[i + 3];
+
+                world_state.reverb_zones.insert(zone);
+            }
 
             //Create the skybox cubemap
             scan_skybox_directory(world_state, &new_skybox);
@@ -8,10 +8,24 @@ use crate::*;
 pub enum ClickAction {
     Select,
     CreateTotoro,
+    CreateTotoroSpawner,
+    CreatePointOfInterest,
+    CreateWaterVolume,
+    CreateEnergyPickup,
+    CreateHazardRegion,
+    CreateRespawnPoint,
+    CreateReverbZone,
     CreatePointLight,
     DeleteObject,
     MovePlayerSpawn,
     MoveSelectedTotoro,
+    MoveSelectedTotoroSpawner,
+    MoveSelectedPointOfInterest,
+    MoveSelectedWaterVolume,
+    MoveSelectedEnergyPickup,
+    MoveSelectedHazardRegion,
+    MoveSelectedRespawnPoint,
+    MoveSelectedReverbZone,
     MovePointLight,
     ToggleGrabbableTriangle
 }
@@ -20,6 +34,13 @@ impl Default for ClickAction {
     fn default() -> Self { ClickAction::Select }
 }
 
+//Counters for the current run of the game, shown in the entity panel
+#[derive(Default)]
+pub struct SessionStats {
+    pub totoro_deaths: usize,
+    pub player_deaths: usize
+}
+
 pub struct Mouse {
     pub clicked: bool,
     pub was_clicked: bool,
@@ -67,6 +88,7 @@ enum TokenType {
     String
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Configuration {
     pub int_options: HashMap<String, u32>,
     pub float_options: HashMap<String, f32>,
@@ -76,14 +98,32 @@ pub struct Configuration {
 impl Configuration {
     pub const WINDOWED_WIDTH: &'static str = "windowed_width";
     pub const WINDOWED_HEIGHT: &'static str = "windowed_height";
-    const INTS: [&'static str; 2] = [Self::WINDOWED_WIDTH, Self::WINDOWED_HEIGHT];
+    pub const RNG_SEED: &'static str = "rng_seed";             //Optional. A random seed is chosen each run when absent
+    pub const GRAPPLE_ANY_TERRAIN: &'static str = "grapple_any_terrain";   //Optional. Nonzero lets the grappling hook attach to any triangle
+    pub const TELEPORT_LOCOMOTION: &'static str = "teleport_locomotion";   //Optional. Nonzero replaces smooth movement with teleporting
+    pub const TURN_MODE: &'static str = "turn_mode";                       //Optional. 0 is snap turn, 1 is smooth turn, 2 disables turning
+    pub const COMFORT_VIGNETTE: &'static str = "comfort_vignette";         //Optional. Nonzero darkens the view's edges while moving
+    const INTS: [&'static str; 7] = [Self::WINDOWED_WIDTH, Self::WINDOWED_HEIGHT, Self::RNG_SEED, Self::GRAPPLE_ANY_TERRAIN, Self::TELEPORT_LOCOMOTION, Self::TURN_MODE, Self::COMFORT_VIGNETTE];
 
-    pub const BGM_VOLUME: &'static str = "bgm_volume";
-    const FLOATS: [&'static str; 1] = [Self::BGM_VOLUME];
+    pub const MASTER_VOLUME: &'static str = "master_volume";
+    pub const BGM_VOLUME: &'static str = "bgm_volume";                     //Volume of the music bus
+    pub const SFX_VOLUME: &'static str = "sfx_volume";
+    pub const VOICE_VOLUME: &'static str = "voice_volume";
+    pub const UI_VOLUME: &'static str = "ui_volume";
+    pub const GRAPPLE_RANGE: &'static str = "grapple_range";               //Optional. Farthest the grappling hook can attach, in meters
+    pub const GRAPPLE_REEL_SPEED: &'static str = "grapple_reel_speed";     //Optional. Meters per second of rope reeled in at full trigger
+    pub const GRAPPLE_MIN_ROPE_LENGTH: &'static str = "grapple_min_rope_length";
+    pub const SNAP_TURN_DEGREES: &'static str = "snap_turn_degrees";       //Optional. Angle of each snap turn
+    pub const SMOOTH_TURN_SPEED: &'static str = "smooth_turn_speed";       //Optional. Degrees per second of smooth turning at full deflection
+    const FLOATS: [&'static str; 10] = [Self::MASTER_VOLUME, Self::BGM_VOLUME, Self::SFX_VOLUME, Self::VOICE_VOLUME, Self::UI_VOLUME, Self::GRAPPLE_RANGE, Self::GRAPPLE_REEL_SPEED, Self::GRAPPLE_MIN_ROPE_LENGTH, Self::SNAP_TURN_DEGREES, Self::SMOOTH_TURN_SPEED];
 
     pub const LEVEL_NAME: &'static str = "level_name";
     pub const MUSIC_NAME: &'static str = "default_music";
-    const STRS: [&'static str; 2] = [Self::LEVEL_NAME, Self::MUSIC_NAME];
+    pub const GAMEPAD_MAPPINGS: &'static str = "gamepad_mappings";         //Optional. Comma-separated action:input pairs, e.g. grab:x,respawn:b
+    pub const AUDIO_BACKEND: &'static str = "audio_backend";               //Optional. openal, null, or record:<log path> to run without an audio device
+    pub const AUDIO_DEVICE: &'static str = "audio_device";                 //Optional. Name of the preferred output device, escaped since device names have spaces
+    pub const GADGET_ENERGY: &'static str = "gadget_energy";               //Optional. Comma-separated gadget:capacity:drain_scale:recharge rules, e.g. Net:3:1:over_time:0.5:1.5
+    const STRS: [&'static str; 6] = [Self::LEVEL_NAME, Self::MUSIC_NAME, Self::GAMEPAD_MAPPINGS, Self::AUDIO_BACKEND, Self::AUDIO_DEVICE, Self::GADGET_ENERGY];
 
     pub const CONFIG_FILEPATH: &'static str = "settings.cfg";
 
@@ -176,19 +216,39 @@ impl Configuration {
             Ok(mut file) => {
                 //Write int options
                 for label in &Self::INTS {
-                    let string = format!("{} = {}\n", label, self.int_options.get(*label).unwrap());
-                    if let Err(e) = file.write(string.as_bytes()) {
-                        println!("Error writing configuration file: {}", e);
-                        return;
+                    if let Some(value) = self.int_options.get(*label) {
+                        let string = format!("{} = {}\n", label, value);
+                        if let Err(e) = file.write(string.as_bytes()) {
+                            println!("Error writing configuration file: {}", e);
+                            return;
+                        }
+                    }
+                }
+
+                //Write float options
+                //Display never uses exponent notation, but whole numbers need a decimal point to be read back as floats
+                for label in &Self::FLOATS {
+                    if let Some(value) = self.float_options.get(*label) {
+                        let mut number = format!("{}", value);
+                        if !number.contains('.') {
+                            number.push_str(".0");
+                        }
+                        let string = format!("{} = {}\n", label, number);
+                        if let Err(e) = file.write(string.as_bytes()) {
+                            println!("Error writing configuration file: {}", e);
+                            return;
+                        }
                     }
                 }
     
                 //Write string options
                 for label in &Self::STRS {
-                    let string = format!("{} = {}\n", label, self.string_options.get(*label).unwrap());
-                    if let Err(e) = file.write(string.as_bytes()) {
-                        println!("Error writing configuration file: {}", e);
-                        return;
+                    if let Some(value) = self.string_options.get(*label) {
+                        let string = format!("{} = {}\n", label, value);
+                        if let Err(e) = file.write(string.as_bytes()) {
+                            println!("Error writing configuration file: {}", e);
+                            return;
+                        }
                     }
                 }
             }
@@ -198,6 +258,15 @@ impl Configuration {
         }
     }
 
+    //Values can't contain whitespace, so strings that might have spaces go through these on their way in and out
+    pub fn escape(value: &str) -> String {
+        value.replace('%', "%25").replace(' ', "%20")
+    }
+
+    pub fn unescape(value: &str) -> String {
+        value.replace("%20", " ").replace("%25", "%")
+    }
+
     pub fn get_window_size(&self) -> glm::TVec2<u32> {
         match (self.int_options.get(Configuration::WINDOWED_WIDTH), self.int_options.get(Configuration::WINDOWED_HEIGHT)) {
             (Some(width), Some(height)) => { glm::vec2(*width, *height) }
@@ -216,26 +285,47 @@ pub struct DebugSphere {
     pub highlighted: bool
 }
 
+//Refers to one entity in an EntityList, and stops referring to anything once that entity is deleted
+//Indices get reused, so the generation is what tells a new entity apart from the deleted one that had its slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityRef {
+    pub idx: usize,
+    generation: u32
+}
+
 pub struct EntityList<T> {
     pub entities: OptionVec<T>,
-    pub selected_idx: Option<usize>
+    pub selected_idx: Option<usize>,
+    generations: Vec<u32>                       //Bumped every time the entity at that index is deleted
 }
 
 impl<T> EntityList<T> {
     pub fn new() -> Self {
         EntityList {
             entities: OptionVec::new(),
-            selected_idx: None
+            selected_idx: None,
+            generations: Vec::new()
         }
     }
 
     pub fn clear(&mut self) {
+        //Slots that were never deleted have no generation yet, so they need one before everything is bumped
+        if self.generations.len() < self.entities.len() {
+            self.generations.resize(self.entities.len(), 0);
+        }
         self.entities.clear();
         self.selected_idx = None;
+        for generation in self.generations.iter_mut() {
+            *generation += 1;
+        }
     }
 
     pub fn delete(&mut self, idx: usize) {
         self.entities.delete(idx);
+        if idx >= self.generations.len() {
+            self.generations.resize(idx + 1, 0);
+        }
+        self.generations[idx] += 1;
         if let Some(i) = self.selected_idx {
             if i == idx {
                 self.selected_idx = None;
@@ -243,6 +333,28 @@ impl<T> EntityList<T> {
         }
     }
 
+    pub fn reference(&self, idx: usize) -> EntityRef {
+        EntityRef {
+            idx,
+            generation: self.generations.get(idx).copied().unwrap_or(0)
+        }
+    }
+
+    //Returns None if the entity has been deleted
+    pub fn get_by_ref(&self, entity: EntityRef) -> Option<&T> {
+        if entity.idx >= self.len() || self.reference(entity.idx) != entity {
+            return None;
+        }
+        self.entities[entity.idx].as_ref()
+    }
+
+    pub fn get_mut_by_ref(&mut self, entity: EntityRef) -> Option<&mut T> {
+        if entity.idx >= self.len() || self.reference(entity.idx) != entity {
+            return None;
+        }
+        self.entities.get_mut_element(entity.idx)
+    }
+
     pub fn count(&self) -> usize { self.entities.count() }
 
     pub fn get_mut_element(&mut self, idx: usize) -> Option<&mut T> { self.entities.get_mut_element(idx) }
@@ -254,7 +366,8 @@ impl<T> EntityList<T> {
     pub fn with_capacity(capacity: usize) -> Self {
         EntityList {
             entities: OptionVec::with_capacity(capacity),
-            selected_idx: None
+            selected_idx: None,
+            generations: Vec::with_capacity(capacity)
         }
     }
 }
\ No newline at end of file
@@ -8,14 +8,20 @@ pub const OCULUS_TOUCH_INTERACTION_PROFILE: &str =          "/interaction_profil
 pub const LEFT_GRIP_POSE: &str =                            "/user/hand/left/input/grip/pose";
 pub const LEFT_AIM_POSE: &str =                             "/user/hand/left/input/aim/pose";
 pub const LEFT_TRIGGER: &str =                              "/user/hand/left/input/trigger/value";
+pub const LEFT_SQUEEZE_FLOAT: &str =                        "/user/hand/left/input/squeeze/value";
+pub const LEFT_SQUEEZE_CLICK: &str =                        "/user/hand/left/input/squeeze/click";
 pub const LEFT_STICK_VECTOR2: &str =                        "/user/hand/left/input/thumbstick";
 pub const LEFT_TRACKPAD_VECTOR2: &str =                     "/user/hand/left/input/trackpad";
 pub const LEFT_TRACKPAD_CLICK: &str =                       "/user/hand/left/input/trackpad/click";
 pub const LEFT_B_BUTTON: &str =                             "/user/hand/left/input/b/click";
 pub const LEFT_Y_BUTTON: &str =                             "/user/hand/left/input/y/click";
+pub const RIGHT_STICK_VECTOR2: &str =                       "/user/hand/right/input/thumbstick";
+pub const RIGHT_TRACKPAD_VECTOR2: &str =                    "/user/hand/right/input/trackpad";
 pub const RIGHT_TRACKPAD_CLICK: &str =                      "/user/hand/right/input/trackpad/click";
 pub const RIGHT_TRACKPAD_FORCE: &str =                      "/user/hand/right/input/trackpad/force";
 pub const RIGHT_TRIGGER_FLOAT: &str =                       "/user/hand/right/input/trigger/value";
+pub const RIGHT_SQUEEZE_FLOAT: &str =                       "/user/hand/right/input/squeeze/value";
+pub const RIGHT_SQUEEZE_CLICK: &str =                       "/user/hand/right/input/squeeze/click";
 pub const RIGHT_GRIP_POSE: &str =                           "/user/hand/right/input/grip/pose";
 pub const RIGHT_AIM_POSE: &str =                            "/user/hand/right/input/aim/pose";
 pub const RIGHT_A_BUTTON_BOOL: &str =                       "/user/hand/right/input/a/click";
@@ -134,8 +140,8 @@ pub fn make_reference_space<G: xr::Graphics>(session: &Option<xr::Session<G>>, r
     }
 }
 
-pub fn tracked_player_segment(view_space: &Option<xr::Space>, tracking_space: &Option<xr::Space>, time: xr::Time, world_from_tracking: &glm::TMat4<f32>) -> LineSegment {
-    match locate_space(&view_space, &tracking_space, time) {
+pub fn tracked_player_segment(head_pose: &Option<xr::Posef>, world_from_tracking: &glm::TMat4<f32>) -> LineSegment {
+    match head_pose {
         Some(pose) => {
             let head = world_from_tracking * glm::vec4(pose.position.x, pose.position.y, pose.position.z, 1.0);
             let feet = world_from_tracking * glm::vec4(pose.position.x, pose.position.y, 0.0, 1.0);